@@ -1,10 +1,18 @@
 use std::{
   io::{self, Write, Read}, path::{PathBuf}, slice,
-  fs::{read_dir, ReadDir, File}};
+  fs::{read_dir, File}};
 
 
 
-use proto_sigil::parser::new_parser::ParsingState;
+use proto_sigil::{
+  parser::{
+    new_parser::ParsingState,
+    formatter::{format_directory, FormatMode, FormatStatus}},
+  elaborator::{
+    main::{elaborate_directory, ElaborationOutcome},
+    queries::{Query, evaluate, infer_type, describe}},
+  expression_trees::raw_syntax_nodes::FileId,
+};
 
 use crate::parser::CLIParseState;
 
@@ -15,7 +23,7 @@ pub struct KnotState {
   inp: io::Stdin,
   recent_line: String,
   command_parser: Option<CLIParseState>,
-  watched_directory: Option<ReadDir>
+  watched_directory: Option<PathBuf>
 }
 
 const CTRL : &str = "\u{1b}";
@@ -150,6 +158,27 @@ impl KnotState {
         _ if self.prefix_match(":check", false) => {
           self.check_files();
         }
+        _ if self.prefix_match(":fmt", true) => {
+          self.skip_whitespaces();
+          let mode =
+            if self.prefix_match("--check", true) { FormatMode::Check }
+            else { FormatMode::Rewrite };
+          self.format_files(mode);
+        }
+        _ if self.prefix_match(":eval", true) => {
+          self.skip_whitespaces();
+          let show_steps = self.prefix_match("--steps", true);
+          self.skip_whitespaces();
+          self.evaluate_expression(show_steps);
+        }
+        _ if self.prefix_match(":type", true) => {
+          self.skip_whitespaces();
+          self.show_type();
+        }
+        _ if self.prefix_match(":info", true) => {
+          self.skip_whitespaces();
+          self.show_info();
+        }
         _ => {
           let unrecognised_command_err = [
             RED, "Unrecognised command", DN, "\n"
@@ -170,8 +199,16 @@ impl KnotState {
       "   " , DIM, "Sets folder that is watched in current session\n", DN,
       ":check\n",
       "   ", DIM, "Examines validity of watched definitions\n", DN,
-      ":eval\n",
-      "   ", DIM, "Performs reduction of a specified definition", DN, "\n"
+      ":fmt [--check]\n",
+      "   ", DIM, "Rewrites watched files in canonical layout.\n",
+      "   With --check only lists files that would change\n", DN,
+      ":eval [--steps] <name | expr>\n",
+      "   ", DIM, "Performs reduction of a specified definition or expression.\n",
+      "   With --steps also shows every rewrite that was done\n", DN,
+      ":type <expr>\n",
+      "   ", DIM, "Infers type of an expression\n", DN,
+      ":info <name>\n",
+      "   ", DIM, "Shows a declaration, where it is and what it refers to", DN, "\n"
     ];
     self.write_lines(&msg, None);
   }
@@ -199,7 +236,7 @@ impl KnotState {
       self.write_lines(&err, None);
       return;
     }
-    let dir = read_dir(path);
+    let dir = read_dir(&path);
     if let Err(_) = dir {
       let err = [
         RED, "Cant open directory", DN, "\n"
@@ -207,12 +244,37 @@ impl KnotState {
       self.write_lines(&err, None);
       return;
     }
-    let dir = dir.unwrap();
-    self.watched_directory = Some(dir);
+    self.watched_directory = Some(path);
   }
   fn check_files(&mut self) {
     if let Some(dir) = &self.watched_directory {
-      unimplemented!()
+      let outcome = elaborate_directory(dir.clone());
+      let failed = outcome.diagnostics.did_record_any_issues();
+      if outcome.source_files.is_empty() && !failed {
+        self.write_lines(&[DIM, "No source files were found", DN, "\n"], None);
+        return;
+      }
+      for (index, file) in outcome.source_files.iter().enumerate() {
+        let count =
+          outcome.diagnostics.count_reports_from(FileId(index as u32));
+        let path = file.path.display().to_string();
+        let decls = format!(
+          "{} declaration{}", file.declarations.len(),
+          if file.declarations.len() == 1 {""} else {"s"});
+        if count == 0 {
+          self.write_lines(&[&path, " ", DIM, &decls, ", ok", DN, "\n"], None);
+        } else {
+          let problems = format!(
+            "{} problem{}", count, if count == 1 {""} else {"s"});
+          self.write_lines(
+            &[&path, " ", DIM, &decls, ", ", DN, RED, &problems, DN, "\n"], None);
+        }
+      }
+      if failed {
+        self.write_line("\n");
+        let rendered = outcome.render_reports();
+        self.write_line(&rendered);
+      }
     } else {
       let err = [
         RED, "No directory was set for check", DN, "\n"
@@ -220,4 +282,161 @@ impl KnotState {
       self.write_lines(&err, None);
     }
   }
-}
\ No newline at end of file
+  fn format_files(&mut self, mode: FormatMode) {
+    let Some(dir) = self.watched_directory.clone() else {
+      let err = [
+        RED, "No directory was set for formatting", DN, "\n"
+      ];
+      self.write_lines(&err, None);
+      return;
+    };
+    let reports = match format_directory(&dir, mode) {
+      Ok(reports) => reports,
+      Err(err) => {
+        self.write_lines(&[RED, &err.to_string(), DN, "\n"], None);
+        return;
+      }
+    };
+    if reports.is_empty() {
+      self.write_lines(&[DIM, "No source files were found", DN, "\n"], None);
+      return;
+    }
+    for report in reports {
+      let path = report.path.display().to_string();
+      match report.status {
+        FormatStatus::Unchanged => {
+          self.write_lines(&[&path, " ", DIM, "ok", DN, "\n"], None);
+        },
+        FormatStatus::Reformatted => {
+          let verdict = match mode {
+            FormatMode::Check => "would be reformatted",
+            FormatMode::Rewrite => "reformatted",
+          };
+          self.write_lines(&[&path, " ", RED, verdict, DN, "\n"], None);
+        },
+        FormatStatus::Unparsable(errors) => {
+          let problems = format!(
+            "{} parse error{}, left as is", errors.len(),
+            if errors.len() == 1 {""} else {"s"});
+          self.write_lines(&[&path, " ", RED, &problems, DN, "\n"], None);
+        },
+      }
+    }
+  }
+  fn read_rest_of_line(&mut self) -> String {
+    if let Some(ref parser) = self.command_parser {
+      return parser.rest_of_line()
+    }
+    panic!("No data was read prior to invocation of the command")
+  }
+  fn evaluate_expression(&mut self, show_steps: bool) {
+    let Some(dir) = self.watched_directory.clone() else {
+      let err = [
+        RED, "No directory was set for evaluation", DN, "\n"
+      ];
+      self.write_lines(&err, None);
+      return;
+    };
+    let input = self.read_rest_of_line();
+    if input.is_empty() {
+      self.write_lines(&[RED, "Nothing to evaluate", DN, "\n"], None);
+      return;
+    }
+    let outcome = elaborate_directory(dir);
+    self.warn_about_problems(&outcome);
+    let query = match Query::parse(&outcome, &input) {
+      Ok(query) => query,
+      Err(rendered) => {
+        self.write_line(&rendered);
+        return;
+      }
+    };
+    let evaluation = evaluate(&query, show_steps);
+    for step in &evaluation.steps {
+      let step = format!("{}  ~>  {}", step.redex, step.contractum);
+      self.write_lines(&[DIM, &step, DN, "\n"], None);
+    }
+    let result = evaluation.result.to_string();
+    self.write_lines(&[&result, "\n"], None);
+    if evaluation.gave_up {
+      let note = [
+        RED, "Reduction was stopped, rules may be looping", DN, "\n"
+      ];
+      self.write_lines(&note, None);
+    }
+  }
+  fn warn_about_problems(&mut self, outcome: &ElaborationOutcome) {
+    if outcome.diagnostics.did_record_any_issues() {
+      let warning = [
+        DIM, "Watched files have problems, results may be off", DN, "\n"
+      ];
+      self.write_lines(&warning, None);
+    }
+  }
+  fn show_type(&mut self) {
+    let Some(dir) = self.watched_directory.clone() else {
+      let err = [
+        RED, "No directory was set for type inference", DN, "\n"
+      ];
+      self.write_lines(&err, None);
+      return;
+    };
+    let input = self.read_rest_of_line();
+    if input.is_empty() {
+      self.write_lines(&[RED, "Expected an expression", DN, "\n"], None);
+      return;
+    }
+    let outcome = elaborate_directory(dir);
+    self.warn_about_problems(&outcome);
+    let inferred =
+      Query::parse(&outcome, &input).and_then(|query| infer_type(&query));
+    match inferred {
+      Ok(type_) => {
+        let line = format!("{} : {}", input, type_);
+        self.write_lines(&[&line, "\n"], None);
+      },
+      Err(rendered) => self.write_line(&rendered),
+    }
+  }
+  fn show_info(&mut self) {
+    let Some(dir) = self.watched_directory.clone() else {
+      let err = [
+        RED, "No directory was set for lookup", DN, "\n"
+      ];
+      self.write_lines(&err, None);
+      return;
+    };
+    let input = self.read_rest_of_line();
+    if input.is_empty() {
+      self.write_lines(&[RED, "Expected a name", DN, "\n"], None);
+      return;
+    }
+    let outcome = elaborate_directory(dir);
+    let info = Query::parse(&outcome, &input).and_then(|query| {
+      return describe(&query).map_err(|err| format!("{}{}{}\n", RED, err, DN))
+    });
+    let info = match info {
+      Ok(info) => info,
+      Err(rendered) => {
+        self.write_line(&rendered);
+        return;
+      }
+    };
+    match info.rendered {
+      Some(ref rendered) => self.write_lines(&[rendered.trim_end(), "\n"], None),
+      None => {
+        let name = info.name.materialise_name();
+        self.write_lines(&[&name, " ", RED, "has problems, see :check", DN, "\n"], None);
+      }
+    }
+    let place = format!("defined at {}:{}", info.path.display(), info.position);
+    self.write_lines(&[DIM, &place, DN, "\n"], None);
+    if !info.references.is_empty() {
+      let names = info.references.iter()
+        .map(|name| name.materialise_name())
+        .collect::<Vec<_>>()
+        .join(", ");
+      self.write_lines(&[DIM, "refers to ", DN, &names, "\n"], None);
+    }
+  }
+}
@@ -81,6 +81,17 @@ impl CLIParseState {
   }
 }
 
+impl CLIParseState {
+  // everything that was not consumed yet, without the line break
+  pub fn rest_of_line(&self) -> String {
+    let slice = unsafe {
+      std::slice::from_raw_parts(
+        self.bytes.add(self.byte_index as usize),
+        (self.end_index - self.byte_index) as usize) };
+    return String::from_utf8_lossy(slice).trim_end().to_string();
+  }
+}
+
 impl CLIParseState {
   pub fn init(command_ptr: *const u8, length: u32) -> Self { unsafe {
     let first_char = *command_ptr as u32;
@@ -4,11 +4,51 @@
 use std::collections::HashSet;
 
 use crate::expression_trees::better_nodes::{
-  ConcretisedNode, Symbol, ConcretisedNodeRepr, ConcretisedRewriteRule};
+  ConcretisedNode, Symbol, ConcretisedNodeRepr, ConcretisedRewriteRule,
+  Declaration, DeclKind};
 use super::diagnostics::{SomeDiagnosticsDelegate, ProblemReport, Kind};
 
 
 
+pub fn check_context_use_in_declaration(
+  declaration: &Declaration,
+  diagnostic_service: &mut dyn SomeDiagnosticsDelegate,
+) {
+  let mut roots = Vec::<ConcretisedNode>::new();
+  match declaration.repr {
+    DeclKind::WellScopedMapping { given_type, rewrite_rules, .. } => {
+      roots.push(unsafe { *given_type });
+      let ptr = rewrite_rules.project_ptr();
+      let lim = rewrite_rules.project_count();
+      for i in 0 .. lim as usize {
+        let ConcretisedRewriteRule { rhs, .. } = unsafe { *ptr.add(i) };
+        roots.push(unsafe { *rhs });
+      }
+    },
+    DeclKind::WellScopedDefinition { given_type, value, .. } => {
+      roots.push(unsafe { *given_type });
+      roots.push(unsafe { *value });
+    },
+    DeclKind::WellScopedData { given_type, constructors, .. } => {
+      roots.push(unsafe { *given_type });
+      for ix in 0 .. constructors.project_count() {
+        roots.push(unsafe { *(*constructors.get_ptr(ix)).given_type });
+      }
+    },
+    _ => panic!("Context use can only be checked on concretised declarations")
+  }
+  for root in roots {
+    let mut unused = HashSet::new();
+    check_context_use(root, diagnostic_service, &mut unused);
+    if !unused.is_empty() {
+      let problem = ProblemReport {
+        kind: Kind::UnusedItemsInImpCtx(unused)
+      };
+      diagnostic_service.report_problem(problem)
+    }
+  }
+}
+
 
 pub fn check_context_use(
   node: ConcretisedNode,
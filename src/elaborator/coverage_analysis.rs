@@ -0,0 +1,254 @@
+use std::fmt::{Display, Formatter, self};
+
+use crate::expression_trees::better_nodes::{
+  Symbol, ConcretisedRewriteRule, ConcretisedPattern, ConcretisedPatternKind, ArrayPtr};
+
+use super::{
+  diagnostics::{SomeDiagnosticsDelegate, ProblemReport, Kind},
+  rewrite_system_check::BindSynthTypeShape,
+  data_types::DataTypes,
+};
+
+
+// Patterns with binders forgotten, which is all that matters for coverage
+#[derive(Debug, Clone)]
+enum Pat {
+  Any,
+  Pt,
+  Left(Box<Pat>),
+  Right(Box<Pat>),
+  Tuple(Box<Pat>, Box<Pat>),
+  Constructor(Symbol, Vec<Pat>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ctor { Pt, Left, Right, Tuple, Named(Symbol, usize) }
+
+impl Pat {
+  fn of(pattern: ConcretisedPattern) -> Self {
+    let sub = |pattern: *mut ConcretisedPattern| Box::new(Pat::of(unsafe { *pattern }));
+    match pattern.repr {
+      ConcretisedPatternKind::Wildcard |
+      ConcretisedPatternKind::VarBinding(_) => Pat::Any,
+      ConcretisedPatternKind::Pt => Pat::Pt,
+      ConcretisedPatternKind::Left(v) => Pat::Left(sub(v)),
+      ConcretisedPatternKind::Right(v) => Pat::Right(sub(v)),
+      ConcretisedPatternKind::Tuple(l, r) => Pat::Tuple(sub(l), sub(r)),
+      ConcretisedPatternKind::Constructor { name, arguments } => {
+        let arguments = (0 .. arguments.project_count())
+          .map(|ix| *sub(arguments.get_ptr(ix)))
+          .collect();
+        Pat::Constructor(name, arguments)
+      },
+    }
+  }
+  fn ctor(&self) -> Option<Ctor> {
+    match self {
+      Pat::Any => None,
+      Pat::Pt => Some(Ctor::Pt),
+      Pat::Left(_) => Some(Ctor::Left),
+      Pat::Right(_) => Some(Ctor::Right),
+      Pat::Tuple(..) => Some(Ctor::Tuple),
+      Pat::Constructor(name, arguments) => Some(Ctor::Named(*name, arguments.len())),
+    }
+  }
+  fn fields(&self) -> Vec<Pat> {
+    match self {
+      Pat::Any | Pat::Pt => Vec::new(),
+      Pat::Left(v) | Pat::Right(v) => vec![(**v).clone()],
+      Pat::Tuple(l, r) => vec![(**l).clone(), (**r).clone()],
+      Pat::Constructor(_, arguments) => arguments.clone(),
+    }
+  }
+}
+
+impl Ctor {
+  fn arity(self) -> usize {
+    match self {
+      Ctor::Pt => 0,
+      Ctor::Left | Ctor::Right => 1,
+      Ctor::Tuple => 2,
+      Ctor::Named(_, arity) => arity,
+    }
+  }
+  // takes fields from the front of a witness
+  fn build(self, fields: &mut Vec<Pat>) -> Pat {
+    let mut taken = fields.drain(.. self.arity());
+    let mut next = || Box::new(taken.next().unwrap());
+    match self {
+      Ctor::Pt => Pat::Pt,
+      Ctor::Left => Pat::Left(next()),
+      Ctor::Right => Pat::Right(next()),
+      Ctor::Tuple => { let l = next(); Pat::Tuple(l, next()) },
+      Ctor::Named(name, _) => Pat::Constructor(name, taken.collect()),
+    }
+  }
+}
+
+// Constructors of values of a shape. Shapes that patterns said
+// nothing about have none, so that only a wildcard covers them.
+// Constructors of a data type whose indices cant fit are left out
+fn signature(shape: &BindSynthTypeShape, data_types: &DataTypes) -> Vec<Ctor> {
+  match shape {
+    BindSynthTypeShape::Singleton => vec![Ctor::Pt],
+    BindSynthTypeShape::Either(..) => vec![Ctor::Left, Ctor::Right],
+    BindSynthTypeShape::Pair(..) => vec![Ctor::Tuple],
+    BindSynthTypeShape::Data(name, written) => {
+      let ctors = data_types.constructors_of(*name).unwrap_or_default();
+      return ctors.iter()
+        .filter(|ctor| written.is_none_or(|type_| data_types.may_build(ctor, type_)))
+        .map(|ctor| Ctor::Named(ctor.name, ctor.fields.len()))
+        .collect()
+    },
+    _ => Vec::new(),
+  }
+}
+
+fn field_shapes(
+  ctor: Ctor, shape: &BindSynthTypeShape, data_types: &DataTypes,
+) -> Vec<BindSynthTypeShape> {
+  let unknown = BindSynthTypeShape::Variable;
+  match (ctor, shape) {
+    (Ctor::Named(name, arity), _) => {
+      let Some(ctor) = data_types.find_constructor(name) else {
+        return vec![unknown; arity]
+      };
+      return ctor.fields.iter()
+        .map(|field| BindSynthTypeShape::of_type(*field, data_types))
+        .collect()
+    },
+    (Ctor::Pt, _) => Vec::new(),
+    (Ctor::Left, BindSynthTypeShape::Either(l, _)) => vec![(**l).clone()],
+    (Ctor::Right, BindSynthTypeShape::Either(_, r)) => vec![(**r).clone()],
+    (Ctor::Tuple, BindSynthTypeShape::Pair(l, r)) => vec![(**l).clone(), (**r).clone()],
+    (Ctor::Tuple, _) => vec![unknown.clone(), unknown],
+    _ => vec![unknown],
+  }
+}
+
+// rows whose first pattern admits the constructor, with its fields in front
+fn specialise(rows: &[Vec<Pat>], ctor: Ctor) -> Vec<Vec<Pat>> {
+  return rows.iter().filter_map(|row| {
+    let fields = match row[0].ctor() {
+      None => vec![Pat::Any; ctor.arity()],
+      Some(head) if head == ctor => row[0].fields(),
+      Some(_) => return None,
+    };
+    return Some(fields.into_iter().chain(row[1 ..].iter().cloned()).collect())
+  }).collect()
+}
+
+// rows that match anything in the first column, without it
+fn default_rows(rows: &[Vec<Pat>]) -> Vec<Vec<Pat>> {
+  return rows.iter()
+    .filter(|row| row[0].ctor().is_none())
+    .map(|row| row[1 ..].to_vec())
+    .collect()
+}
+
+// Finds values that are matched by the given patterns,
+// but by none of the rows. Answer is one of such values,
+// written as patterns, or nothing if rows cover them all.
+// This is usefulness check from `Warnings for pattern matching` by Maranget
+fn uncovered(
+  rows: &[Vec<Pat>], shapes: &[BindSynthTypeShape], query: &[Pat],
+  data_types: &DataTypes,
+) -> Option<Vec<Pat>> {
+  if query.is_empty() {
+    return if rows.is_empty() { Some(Vec::new()) } else { None }
+  }
+  let shape = &shapes[0];
+  let with_ctor = |ctor: Ctor, fields: Vec<Pat>| {
+    let mut shapes_ = field_shapes(ctor, shape, data_types);
+    shapes_.extend(shapes[1 ..].iter().cloned());
+    let query_ = fields.into_iter().chain(query[1 ..].iter().cloned()).collect::<Vec<_>>();
+    let mut witness = uncovered(&specialise(rows, ctor), &shapes_, &query_, data_types)?;
+    let head = ctor.build(&mut witness);
+    witness.insert(0, head);
+    return Some(witness)
+  };
+  if let Some(ctor) = query[0].ctor() {
+    return with_ctor(ctor, query[0].fields())
+  }
+  let ctors = signature(shape, data_types);
+  let used = rows.iter().filter_map(|row| row[0].ctor()).collect::<Vec<_>>();
+  let is_complete = !ctors.is_empty() && ctors.iter().all(|ctor| used.contains(ctor));
+  if is_complete {
+    return ctors.iter()
+      .find_map(|ctor| with_ctor(*ctor, vec![Pat::Any; ctor.arity()]))
+  }
+  let mut witness =
+    uncovered(&default_rows(rows), &shapes[1 ..], &query[1 ..], data_types)?;
+  // name a constructor that is missing, when there is one
+  let head = match ctors.iter().find(|ctor| !used.contains(ctor)) {
+    Some(ctor) if !used.is_empty() => {
+      ctor.build(&mut vec![Pat::Any; ctor.arity()])
+    },
+    _ => Pat::Any,
+  };
+  witness.insert(0, head);
+  return Some(witness)
+}
+
+
+// Reports inputs that no rule of a mapping matches,
+// and rules that only match what rules before them already do.
+// Shapes of columns come from patterns, and must be free of conflicts
+pub fn check_coverage(
+  name: Symbol,
+  rewrite_rules: ArrayPtr<ConcretisedRewriteRule>,
+  column_shapes: &[BindSynthTypeShape],
+  data_types: &DataTypes,
+  diagnostic_delegate: &mut dyn SomeDiagnosticsDelegate,
+) {
+  let mut rows = Vec::<Vec<Pat>>::new();
+  for ix in 0 .. rewrite_rules.project_count() {
+    let rule = unsafe { *rewrite_rules.get_ptr(ix) };
+    let row = (0 .. rule.matchers.project_count())
+      .map(|column| Pat::of(unsafe { *rule.matchers.get_ptr(column) }))
+      .collect::<Vec<_>>();
+    if uncovered(&rows, column_shapes, &row, data_types).is_none() {
+      let problem = ProblemReport {
+        kind: Kind::UnreachableRule(rule.location) };
+      diagnostic_delegate.report_problem(problem);
+    }
+    rows.push(row);
+  }
+  let anything = vec![Pat::Any; column_shapes.len()];
+  if let Some(witness) = uncovered(&rows, column_shapes, &anything, data_types) {
+    let example = witness.iter()
+      .map(|pat| pat.to_string())
+      .collect::<Vec<_>>()
+      .join(", ");
+    let problem = ProblemReport {
+      kind: Kind::MissingCases { name, example } };
+    diagnostic_delegate.report_problem(problem);
+  }
+}
+
+// written as it would be in a clause
+impl Display for Pat {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    let arg = |f: &mut Formatter<'_>, pat: &Pat| match pat {
+      Pat::Any | Pat::Pt => write!(f, "{}", pat),
+      Pat::Constructor(_, arguments) if arguments.is_empty() => write!(f, "{}", pat),
+      _ => write!(f, "({})", pat),
+    };
+    match self {
+      Pat::Any => write!(f, "_"),
+      Pat::Pt => write!(f, "pt"),
+      Pat::Left(v) => { write!(f, "inl ")?; arg(f, v) },
+      Pat::Right(v) => { write!(f, "inr ")?; arg(f, v) },
+      Pat::Tuple(l, r) => {
+        write!(f, "two ")?; arg(f, l)?; write!(f, " ")?; arg(f, r)
+      },
+      Pat::Constructor(name, arguments) => {
+        write!(f, "{}", name.materialise_name())?;
+        for argument in arguments {
+          write!(f, " ")?; arg(f, argument)?;
+        }
+        return Ok(())
+      },
+    }
+  }
+}
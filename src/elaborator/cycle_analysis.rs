@@ -1,79 +1,266 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, VecDeque};
 
 use crate::expression_trees::{
   better_nodes::{
-    Declaration, DeclKind, Symbol, ConcretisedNode, ConcretisedNodeRepr}};
+    Declaration, DeclKind, Symbol, ConcretisedNode, ConcretisedNodeRepr,
+    ConcretisedRewriteRule, ArrayPtr, Origin}};
 
-use super::environment::PasteboardTable;
+use super::{
+  environment::{PasteboardTable, DefaultTableStreamingIterator},
+  diagnostics::{DiagnosticService, ProblemReport, Kind},
+  termination_check::check_termination,
+};
 
 
+#[derive(Debug, Clone, Copy)]
+pub struct Dependency {
+  // located at the first use, or at the first immediate one if there is such
+  pub name: Symbol,
+  // taken every time the declaration is unfolded, as opposed to
+  // ones that are taken only when rules of it or of a lambda fire
+  pub is_immediate: bool,
+}
 
-fn detect_cycles(
-  definition: &Declaration,
-  steps: &mut HashSet<Symbol>,
-  global_scope: &PasteboardTable<Symbol, Declaration>,
-  will_generate_ground_forms: &mut bool,
-) {
-  match definition.repr {
-    DeclKind::WellScopedMapping { name, given_type, rewrite_rules } => {
-
+// Declarations that a concretised declaration refers to,
+// from its type and its value or rules, in order of first use
+pub fn trace_dependencies(declaration: &Declaration) -> Vec<Dependency> {
+  let mut found = Vec::new();
+  match declaration.repr {
+    DeclKind::WellScopedDefinition { given_type, value, .. } => {
+      trace_node(unsafe { *given_type }, false, &mut found);
+      trace_node(unsafe { *value }, true, &mut found);
     },
-    DeclKind::WellScopedDefinition { name, given_type, value } => {
-      steps.insert(name);
-
-      trace_dependencies(
-        steps,
-        unsafe { &*value },
-        will_generate_ground_forms,
-        global_scope)
+    DeclKind::WellScopedMapping { given_type, rewrite_rules, .. } => {
+      trace_node(unsafe { *given_type }, false, &mut found);
+      trace_rules(rewrite_rules, &mut found);
     },
-    _ => {
-      panic!("detect_cycles: not a well-scoped definition");
-    }
+    DeclKind::WellScopedData { given_type, constructors, .. } => {
+      trace_node(unsafe { *given_type }, false, &mut found);
+      for ix in 0 .. constructors.project_count() {
+        let ctor = unsafe { *constructors.get_ptr(ix) };
+        trace_node(unsafe { *ctor.given_type }, false, &mut found);
+      }
+    },
+    _ => panic!("Dependencies are known only after scope analysis")
   }
+  return found
 }
 
-fn trace_dependencies(
-  steps: &mut HashSet<Symbol>,
-  expr: &ConcretisedNode,
-  will_generate_ground_forms: &mut bool,
-  global_scope: &PasteboardTable<Symbol, Declaration>,
-) {
-  let ConcretisedNode {
-    kind,
-    location,
-    implicit_context
-  } = expr;
-  match kind {
-    ConcretisedNodeRepr::Star => (),
-    ConcretisedNodeRepr::Void |
-    ConcretisedNodeRepr::Singleton |
-    ConcretisedNodeRepr::Pt => {
-      *will_generate_ground_forms = true;
+fn note(name: Symbol, is_immediate: bool, found: &mut Vec<Dependency>) {
+  match found.iter_mut().find(|dep| dep.name == name) {
+    Some(dep) => if is_immediate && !dep.is_immediate {
+      *dep = Dependency { name, is_immediate }
+    },
+    None => found.push(Dependency { name, is_immediate }),
+  }
+}
+
+fn trace_node(node: ConcretisedNode, is_immediate: bool, found: &mut Vec<Dependency>) {
+  if let Some(ctx) = node.implicit_context {
+    for ix in 0 .. ctx.project_count() {
+      let (_, type_) = unsafe { *ctx.get_ptr(ix) };
+      if let Some(type_) = type_ { trace_node(type_, is_immediate, found) }
+    }
+  }
+  let each = |array: ArrayPtr<ConcretisedNode>, found: &mut Vec<Dependency>| {
+    for ix in 0 .. array.project_count() {
+      trace_node(unsafe { *array.get_ptr(ix) }, is_immediate, found)
+    }
+  };
+  match node.kind {
+    ConcretisedNodeRepr::Reference { name, origination: Origin::GlobalScope } => {
+      note(name, is_immediate, found)
+    },
+    ConcretisedNodeRepr::App { root, arguments, origination } => {
+      if let Origin::GlobalScope = origination { note(root, is_immediate, found) }
+      each(arguments, found)
     },
-    ConcretisedNodeRepr::Reference { name, .. } => {
-      if steps.contains(name) {
-        //
-      } else {
-        let declaration =
-          global_scope.retrieve_ref(name).unwrap();
-
-        detect_cycles(
-          &declaration,
-          steps,
-          global_scope,
-          will_generate_ground_forms)
+    ConcretisedNodeRepr::Wit { premises, conclusion } => {
+      each(premises, found);
+      trace_node(unsafe { *conclusion }, is_immediate, found)
+    },
+    ConcretisedNodeRepr::Sigma { head, spine } |
+    ConcretisedNodeRepr::Arrow { head, spine, .. } => {
+      for ix in 0 .. head.project_count() {
+        let (_, type_) = unsafe { *head.get_ptr(ix) };
+        trace_node(type_, is_immediate, found)
       }
+      trace_node(unsafe { *spine }, is_immediate, found)
+    },
+    // rules of a lambda are used only when it is applied
+    ConcretisedNodeRepr::Lam { rewrite_rules } => trace_rules(rewrite_rules, found),
+    ConcretisedNodeRepr::Pair(l, r) |
+    ConcretisedNodeRepr::Tuple(l, r) |
+    ConcretisedNodeRepr::Either(l, r) => {
+      trace_node(unsafe { *l }, is_immediate, found);
+      trace_node(unsafe { *r }, is_immediate, found)
     },
-    ConcretisedNodeRepr::App { root, arguments, origination } => todo!(),
-    ConcretisedNodeRepr::Wit { premises, conclusion } => todo!(),
-    ConcretisedNodeRepr::Sigma { head, spine } => todo!(),
-    ConcretisedNodeRepr::Arrow { head, spine, performs_introspection } => todo!(),
-    ConcretisedNodeRepr::Lam { rewrite_rules } => todo!(),
-    ConcretisedNodeRepr::Pair(_, _) => todo!(),
-    ConcretisedNodeRepr::Tuple(_, _) => todo!(),
-    ConcretisedNodeRepr::Either(_, _) => todo!(),
-    ConcretisedNodeRepr::Left(_) => todo!(),
-    ConcretisedNodeRepr::Right(_) => todo!(),
+    ConcretisedNodeRepr::Left(v) |
+    ConcretisedNodeRepr::Right(v) => trace_node(unsafe { *v }, is_immediate, found),
+    ConcretisedNodeRepr::Reference { .. } |
+    ConcretisedNodeRepr::Star | ConcretisedNodeRepr::Void |
+    ConcretisedNodeRepr::Singleton | ConcretisedNodeRepr::Pt => (),
+  }
+}
+
+// patterns dont refer to declarations
+fn trace_rules(rewrite_rules: ArrayPtr<ConcretisedRewriteRule>, found: &mut Vec<Dependency>) {
+  for ix in 0 .. rewrite_rules.project_count() {
+    let rule = unsafe { *rewrite_rules.get_ptr(ix) };
+    trace_node(unsafe { *rule.rhs }, false, found)
   }
-}
\ No newline at end of file
+}
+
+
+// Builds graph of all declarations, and marks those that
+// can reach themselves as participating in cycle formation.
+// Recursion through rules is fine, but a cycle where every step
+// is immediate unfolds forever, so that one is reported.
+// Members of every cycle are then checked for termination.
+// Malformed declarations have no edges, since their trees cant be trusted
+pub fn analyse_cycles(
+  symbol_table: &PasteboardTable<Symbol, *mut Declaration>,
+  diagnostics: &DiagnosticService,
+) {
+  let mut declarations =
+    DefaultTableStreamingIterator::init(symbol_table).collect::<Vec<_>>();
+  // table has no order of its own, and reports should
+  declarations.sort_by_key(|decl| {
+    let name = unsafe { &**decl }.project_name();
+    return (name.origin.0, name.location.primary_offset)
+  });
+  // constructors are in the table too, under the data type they belong to
+  declarations.dedup();
+  let indices = declarations.iter().enumerate()
+    .flat_map(|(ix, decl)| {
+      let decl = unsafe { &**decl };
+      let mut names = decl.project_constructor_names();
+      names.push(decl.project_name());
+      return names.into_iter().map(move |name| (name, ix))
+    })
+    .collect::<HashMap<_, _>>();
+
+  let edges = declarations.iter().map(|decl| {
+    let decl = unsafe { &**decl };
+    if decl.is_malformed { return Vec::new() }
+    return trace_dependencies(decl).into_iter()
+      .filter_map(|dep| indices.get(&dep.name).map(|ix| (*ix, dep)))
+      .collect::<Vec<_>>()
+  }).collect::<Vec<_>>();
+
+  let all = edges.iter()
+    .map(|out| out.iter().map(|(ix, _)| *ix).collect())
+    .collect::<Vec<_>>();
+  for component in strongly_connected_components(&all) {
+    if !is_cyclic(&component, &all) { continue }
+    for ix in &component {
+      unsafe { &mut *declarations[*ix] }.participate_in_cycle_formation = true
+    }
+    let members = component.iter().map(|ix| declarations[*ix]).collect::<Vec<_>>();
+    check_termination(&members, diagnostics);
+  }
+
+  let immediate = edges.iter()
+    .map(|out| out.iter().filter(|(_, dep)| dep.is_immediate).map(|(ix, _)| *ix).collect())
+    .collect::<Vec<_>>();
+  let mut cycles = Vec::new();
+  for component in strongly_connected_components(&immediate) {
+    if !is_cyclic(&component, &immediate) { continue }
+    let path = shortest_cycle(&component, &edges);
+    let start = unsafe { &*declarations[component[0]] }.project_name();
+    let mut symbols = vec![start];
+    symbols.extend(path.iter().map(|dep| dep.name));
+    cycles.push((start, symbols));
+  }
+  cycles.sort_by_key(|(start, _)| (start.origin.0, start.location.primary_offset));
+  for (start, path) in cycles {
+    let problem = ProblemReport { kind: Kind::DefinitionCycle(path) };
+    diagnostics.report_problem(start.origin, problem);
+  }
+}
+
+fn is_cyclic(component: &[usize], graph: &[Vec<usize>]) -> bool {
+  return component.len() > 1 || graph[component[0]].contains(&component[0])
+}
+
+// Tarjan's algorithm. Each component is sorted
+fn strongly_connected_components(graph: &[Vec<usize>]) -> Vec<Vec<usize>> {
+  struct State<'a> {
+    graph: &'a [Vec<usize>],
+    order: Vec<Option<usize>>,
+    lowest: Vec<usize>,
+    on_stack: Vec<bool>,
+    stack: Vec<usize>,
+    counter: usize,
+    components: Vec<Vec<usize>>,
+  }
+  fn visit(state: &mut State, node: usize) {
+    state.order[node] = Some(state.counter);
+    state.lowest[node] = state.counter;
+    state.counter += 1;
+    state.stack.push(node);
+    state.on_stack[node] = true;
+    for &next in &state.graph[node] {
+      match state.order[next] {
+        None => {
+          visit(state, next);
+          state.lowest[node] = state.lowest[node].min(state.lowest[next]);
+        },
+        Some(order) if state.on_stack[next] => {
+          state.lowest[node] = state.lowest[node].min(order);
+        },
+        Some(_) => (),
+      }
+    }
+    if Some(state.lowest[node]) != state.order[node] { return }
+    let mut component = Vec::new();
+    loop {
+      let member = state.stack.pop().unwrap();
+      state.on_stack[member] = false;
+      component.push(member);
+      if member == node { break }
+    }
+    component.sort();
+    state.components.push(component);
+  }
+  let count = graph.len();
+  let mut state = State {
+    graph, order: vec![None; count], lowest: vec![0; count],
+    on_stack: vec![false; count], stack: Vec::new(), counter: 0,
+    components: Vec::new(),
+  };
+  for node in 0 .. count {
+    if state.order[node].is_none() { visit(&mut state, node) }
+  }
+  return state.components
+}
+
+// Immediate steps that lead from the first member of a component
+// back to it, by breadth first search inside of the component
+fn shortest_cycle(
+  component: &[usize], edges: &[Vec<(usize, Dependency)>]
+) -> Vec<Dependency> {
+  let start = component[0];
+  let mut came_from = HashMap::<usize, (usize, Dependency)>::new();
+  let mut queue = VecDeque::from([start]);
+  while let Some(node) = queue.pop_front() {
+    for (next, dep) in &edges[node] {
+      if !dep.is_immediate || !component.contains(next) { continue }
+      if *next == start {
+        let mut path = vec![*dep];
+        let mut at = node;
+        while at != start {
+          let (previous, dep) = came_from[&at];
+          path.push(dep);
+          at = previous;
+        }
+        path.reverse();
+        return path
+      }
+      if came_from.contains_key(next) { continue }
+      came_from.insert(*next, (node, *dep));
+      queue.push_back(*next);
+    }
+  }
+  unreachable!("Members of a cyclic component reach each other")
+}
@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+
+use crate::expression_trees::{
+  better_nodes::{
+    Declaration, DeclKind, Symbol, ConcretisedNode, ConcretisedNodeRepr, Origin},
+  raw_syntax_nodes::SourceLocation};
+
+use super::diagnostics::{SomeDiagnosticsDelegate, ProblemReport, Kind};
+
+
+// What patterns need to know about a constructor
+#[derive(Debug, Clone)]
+pub struct ConstructorSignature {
+  pub name: Symbol,
+  // types of arguments it takes
+  pub fields: Vec<ConcretisedNode>,
+  // type of values it builds
+  pub result: ConcretisedNode,
+}
+
+impl ConstructorSignature {
+  fn of(name: Symbol, type_: ConcretisedNode) -> Self {
+    let type_ = ConcretisedNode { implicit_context: None, ..type_ };
+    let ConcretisedNodeRepr::Arrow { head, spine, .. } = type_.kind else {
+      return Self { name, fields: Vec::new(), result: type_ }
+    };
+    let fields = (0 .. head.project_count())
+      .map(|ix| unsafe { (*head.get_ptr(ix)).1 })
+      .collect();
+    return Self { name, fields, result: unsafe { *spine } }
+  }
+}
+
+// head of a type, if it is something global applied to arguments
+fn global_head(node: ConcretisedNode) -> Option<(Symbol, Vec<ConcretisedNode>)> {
+  match node.kind {
+    ConcretisedNodeRepr::Reference { name, origination: Origin::GlobalScope } => {
+      return Some((name, Vec::new()))
+    },
+    ConcretisedNodeRepr::App { root, arguments, origination: Origin::GlobalScope } => {
+      let arguments = (0 .. arguments.project_count())
+        .map(|ix| unsafe { *arguments.get_ptr(ix) })
+        .collect();
+      return Some((root, arguments))
+    },
+    _ => return None
+  }
+}
+
+
+// Checks that every constructor of a data type builds values of it,
+// and that the type occurs in types of their arguments
+// only strictly positively. Gives false if something is off
+pub fn check_data_declaration(
+  declaration: &Declaration,
+  diagnostic_delegate: &mut dyn SomeDiagnosticsDelegate,
+) -> bool {
+  let DeclKind::WellScopedData { name, constructors, .. } = declaration.repr else {
+    panic!("Only concretised data types can be checked")
+  };
+  let mut is_well_formed = true;
+  for ix in 0 .. constructors.project_count() {
+    let ctor = unsafe { *constructors.get_ptr(ix) };
+    let signature = ConstructorSignature::of(ctor.name, unsafe { *ctor.given_type });
+    let builds_own = global_head(signature.result)
+      .is_some_and(|(head, _)| head == name);
+    if !builds_own {
+      let problem = ProblemReport {
+        kind: Kind::ForeignConstructorType { data: name, type_loc: signature.result.location }
+      };
+      diagnostic_delegate.report_problem(problem);
+      is_well_formed = false;
+    }
+    for field in signature.fields {
+      let Some(occurrence) = find_nonpositive(field, name) else { continue };
+      let problem = ProblemReport {
+        kind: Kind::NonPositiveOccurrence { data: name, occurrence }
+      };
+      diagnostic_delegate.report_problem(problem);
+      is_well_formed = false;
+    }
+  }
+  return is_well_formed
+}
+
+// First place in a type of an argument where data type occurs other than
+// strictly positively. That is to the left of an arrow, or inside of
+// arguments of some type, its own indices included.
+// Other data types could let it through their parameters,
+// but that is not worked out, so it is rejected as well
+fn find_nonpositive(node: ConcretisedNode, data: Symbol) -> Option<SourceLocation> {
+  match node.kind {
+    ConcretisedNodeRepr::App { arguments, .. } => {
+      return (0 .. arguments.project_count())
+        .find_map(|ix| find_occurrence(unsafe { *arguments.get_ptr(ix) }, data))
+    },
+    ConcretisedNodeRepr::Pair(l, r) |
+    ConcretisedNodeRepr::Either(l, r) => {
+      return find_nonpositive(unsafe { *l }, data)
+        .or_else(|| find_nonpositive(unsafe { *r }, data))
+    },
+    ConcretisedNodeRepr::Arrow { head, spine, .. } => {
+      let in_head = (0 .. head.project_count())
+        .find_map(|ix| find_occurrence(unsafe { (*head.get_ptr(ix)).1 }, data));
+      return in_head.or_else(|| find_nonpositive(unsafe { *spine }, data))
+    },
+    ConcretisedNodeRepr::Sigma { head, spine } => {
+      let in_head = (0 .. head.project_count())
+        .find_map(|ix| find_nonpositive(unsafe { (*head.get_ptr(ix)).1 }, data));
+      return in_head.or_else(|| find_nonpositive(unsafe { *spine }, data))
+    },
+    ConcretisedNodeRepr::Reference { .. } |
+    ConcretisedNodeRepr::Star | ConcretisedNodeRepr::Void |
+    ConcretisedNodeRepr::Singleton => return None,
+    // values in types may mention it in any way
+    _ => return find_occurrence(node, data)
+  }
+}
+
+fn find_occurrence(node: ConcretisedNode, data: Symbol) -> Option<SourceLocation> {
+  let any = |nodes: &mut dyn Iterator<Item = ConcretisedNode>| {
+    nodes.map(|node| find_occurrence(node, data)).find(Option::is_some).flatten()
+  };
+  match node.kind {
+    ConcretisedNodeRepr::Reference { name, origination: Origin::GlobalScope } => {
+      if name == data { return Some(node.location) }
+      return None
+    },
+    ConcretisedNodeRepr::App { root, arguments, origination } => {
+      if root == data && matches!(origination, Origin::GlobalScope) {
+        return Some(node.location)
+      }
+      return any(&mut (0 .. arguments.project_count())
+        .map(|ix| unsafe { *arguments.get_ptr(ix) }))
+    },
+    ConcretisedNodeRepr::Wit { premises, conclusion } => {
+      return any(&mut (0 .. premises.project_count())
+        .map(|ix| unsafe { *premises.get_ptr(ix) })
+        .chain([unsafe { *conclusion }]))
+    },
+    ConcretisedNodeRepr::Arrow { head, spine, .. } |
+    ConcretisedNodeRepr::Sigma { head, spine } => {
+      return any(&mut (0 .. head.project_count())
+        .map(|ix| unsafe { (*head.get_ptr(ix)).1 })
+        .chain([unsafe { *spine }]))
+    },
+    ConcretisedNodeRepr::Pair(l, r) |
+    ConcretisedNodeRepr::Either(l, r) |
+    ConcretisedNodeRepr::Tuple(l, r) => {
+      return any(&mut [unsafe { *l }, unsafe { *r }].into_iter())
+    },
+    ConcretisedNodeRepr::Left(v) |
+    ConcretisedNodeRepr::Right(v) => return find_occurrence(unsafe { *v }, data),
+    // rules of a lambda are not looked into
+    ConcretisedNodeRepr::Lam { .. } |
+    ConcretisedNodeRepr::Reference { .. } |
+    ConcretisedNodeRepr::Star | ConcretisedNodeRepr::Void |
+    ConcretisedNodeRepr::Singleton | ConcretisedNodeRepr::Pt => return None,
+  }
+}
+
+
+// Constructors of every well formed data type.
+// Gathered once all files are concretised, and not changed after
+#[derive(Default)]
+pub struct DataTypes {
+  types: HashMap<Symbol, Vec<ConstructorSignature>>,
+  // data type that each constructor belongs to
+  owners: HashMap<Symbol, Symbol>,
+}
+
+impl DataTypes {
+  pub fn collect<'a>(declarations: impl Iterator<Item = &'a Declaration>) -> Self {
+    let mut data_types = Self::default();
+    for decl in declarations {
+      if decl.is_malformed { continue }
+      let DeclKind::WellScopedData { name, constructors, .. } = decl.repr else { continue };
+      let signatures = (0 .. constructors.project_count()).map(|ix| {
+        let ctor = unsafe { *constructors.get_ptr(ix) };
+        data_types.owners.insert(ctor.name, name);
+        return ConstructorSignature::of(ctor.name, unsafe { *ctor.given_type })
+      }).collect();
+      data_types.types.insert(name, signatures);
+    }
+    return data_types
+  }
+  pub fn constructors_of(&self, data: Symbol) -> Option<&[ConstructorSignature]> {
+    return self.types.get(&data).map(|ctors| &ctors[..])
+  }
+  pub fn owner_of(&self, constructor: Symbol) -> Option<Symbol> {
+    return self.owners.get(&constructor).copied()
+  }
+  pub fn find_constructor(&self, name: Symbol) -> Option<&ConstructorSignature> {
+    let owner = self.owner_of(name)?;
+    return self.types[&owner].iter().find(|ctor| ctor.name == name)
+  }
+  // Whether a constructor can build values of a given type.
+  // Only constructors in indices can tell that it cant,
+  // anything else may turn out to be equal to anything
+  pub fn may_build(&self, ctor: &ConstructorSignature, type_: ConcretisedNode) -> bool {
+    return self.may_unify(ctor.result, type_)
+  }
+  fn may_unify(&self, left: ConcretisedNode, right: ConcretisedNode) -> bool {
+    let (Some((lh, la)), Some((rh, ra))) = (global_head(left), global_head(right))
+    else { return true };
+    let is_rigid = |name: Symbol| {
+      self.owners.contains_key(&name) || self.types.contains_key(&name)
+    };
+    if !is_rigid(lh) || !is_rigid(rh) { return true }
+    if lh != rh || la.len() != ra.len() { return false }
+    return la.into_iter().zip(ra).all(|(l, r)| self.may_unify(l, r))
+  }
+}
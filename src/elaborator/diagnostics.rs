@@ -1,25 +1,52 @@
 
 
-use std::{sync::Mutex, collections::HashSet,};
-use crate::expression_trees::{raw_syntax_nodes::SourceLocation, better_nodes::Symbol};
-
-use super::frame_allocator::{RcTaskBoxRef};
+use std::{sync::Mutex, collections::HashSet, fmt::{Display, Formatter, self}};
+use crate::{
+  expression_trees::{
+    raw_syntax_nodes::{SourceLocation, FileId}, better_nodes::Symbol},
+  parser::new_parser::ParseError};
+use super::report_rendering::{SourceView, render_report};
 
 
 
 pub struct DiagnosticService {
-  reports: Mutex<Vec<ProblemReport>>
+  reports: Mutex<Vec<(FileId, ProblemReport)>>
     // nobody is going to write code that consist mostly of error, right?
 }
 impl DiagnosticService {
   pub fn init() -> Self {
     Self { reports: Mutex::new(Vec::new()) }
   }
-  pub fn report_problem(&self, problem: ProblemReport) {
+  pub fn report_problem(&self, origin: FileId, problem: ProblemReport) {
     let mut item =
       self.reports.lock().unwrap();
-    item.push(problem);
+    item.push((origin, problem));
+    drop(item);
+  }
+  // moves everything that was gathered by a delegate in one take,
+  // so that workers dont fight over the lock for each report
+  pub fn absorb_delegate(&self, delegate: DiagnosticsDelegate) {
+    let DiagnosticsDelegate { reports, associated_file } = delegate;
+    if reports.is_empty() { return; }
+    let mut item =
+      self.reports.lock().unwrap();
+    for report in reports {
+      item.push((associated_file, report));
+    }
+    drop(item);
+  }
+  pub fn count_reports_from(&self, origin: FileId) -> usize {
+    let item = self.reports.lock().unwrap();
+    let count =
+      item.iter().filter(|(file, _)| *file == origin).count();
+    drop(item);
+    return count
+  }
+  pub fn collect_reports(&self) -> Vec<(FileId, ProblemReport)> {
+    let item = self.reports.lock().unwrap();
+    let copy = item.clone();
     drop(item);
+    return copy
   }
   pub fn did_record_any_issues(&self) -> bool {
     let item = self.reports.lock().unwrap();
@@ -27,19 +54,33 @@ impl DiagnosticService {
     drop(item);
     return count != 0
   }
-  pub fn dump_reports_to_stdout(&self) {
-    let reps = self.reports.lock().unwrap();
-    let len = reps.len();
-    println!("Encountered {} error{}.", len, if len > 1 {"s"} else {""} );
-    // todo
+  pub fn render_reports(&self, sources: &[SourceView]) -> String {
+    let mut reports = self.collect_reports();
+    // files are checked in parallel, so reports come out of order
+    reports.sort_by_key(|(origin, _)| origin.0);
+    let mut out = String::new();
+    for (origin, report) in reports {
+      render_report(origin, &report, sources, &mut out);
+      out.push('\n');
+    }
+    return out
+  }
+  pub fn dump_reports_to_stdout(&self, sources: &[SourceView]) {
+    let rendered = self.render_reports(sources);
+    print!("{}", rendered);
+    let len = self.reports.lock().unwrap().len();
+    println!("Encountered {} error{}.", len, if len != 1 {"s"} else {""} );
   }
 }
 
 pub struct DiagnosticsDelegate {
   pub reports: Vec<ProblemReport>,
-  pub associated_text: RcTaskBoxRef<String>,
+  pub associated_file: FileId,
 }
 impl DiagnosticsDelegate {
+  pub fn init(associated_file: FileId) -> Self {
+    Self { reports: Vec::new(), associated_file }
+  }
   pub fn report_problem(&mut self, report: ProblemReport) {
     self.reports.push(report)
   }
@@ -74,8 +115,10 @@ pub enum Kind {
   DuplicatesInImpCtx(HashSet<Symbol>),
   UnsedImpCtxAtTerminalNode(SourceLocation),
   UnusedItemsInImpCtx(HashSet<Symbol>),
+  // expected type can be written in another file
   MismatchedType {
     type_expr: SourceLocation,
+    type_origin: FileId,
     term_expr: SourceLocation
   },
   ArityMismatch {
@@ -86,6 +129,135 @@ pub enum Kind {
   BinderShapeConflict {
     pattern_loc: SourceLocation,
   },
-  NonfuncTypeInFuncPos(SourceLocation)
+  PatternMismatchesSignature {
+    pattern_loc: SourceLocation,
+    type_loc: SourceLocation,
+  },
+  NonfuncTypeInFuncPos(SourceLocation),
+  // example is a clause that no rule of the mapping matches
+  MissingCases {
+    name: Symbol,
+    example: String
+  },
+  UnreachableRule(SourceLocation),
+  MalformedSyntax(ParseError),
+  UnreadableFile(String),
+  // folder of sources as a whole, so reported with detached origin
+  UnreadableDirectory(String),
+  UnknownModule(Symbol),
+  AmbiguousName {
+    name: Symbol,
+    candidates: Vec<Symbol>
+  },
+  // modules in import order, first one is repeated at the end
+  ImportCycle(Vec<Symbol>),
+  // definition followed by the uses that lead back to it
+  DefinitionCycle(Vec<Symbol>),
+  // call that starts a way back to the declaration with nothing getting smaller
+  NonTerminatingRecursion {
+    name: Symbol,
+    call_site: SourceLocation
+  },
+  NonPositiveOccurrence {
+    data: Symbol,
+    occurrence: SourceLocation
+  },
+  ForeignConstructorType {
+    data: Symbol,
+    type_loc: SourceLocation
+  },
+  // type that reduced for too long to be compared with anything
+  ReductionOutOfFuel {
+    type_expr: SourceLocation,
+    type_origin: FileId
+  },
+  // expression whose type can only be checked, but nothing says what it is
+  UninferableType(SourceLocation),
+}
+
+
+fn list_symbols(symbols: &HashSet<Symbol>) -> String {
+  let mut names =
+    symbols.iter().map(|sym| sym.materialise_name()).collect::<Vec<_>>();
+  // sets dont have stable order
+  names.sort();
+  let names =
+    names.iter().map(|name| format!("`{}`", name)).collect::<Vec<_>>();
+  return names.join(", ")
 }
 
+impl Display for Kind {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self {
+      Kind::DuplicateDecls { another, .. } =>
+        write!(f, "`{}` is declared more than once", another.materialise_name()),
+      Kind::IrrelevantSymbol(sym) =>
+        write!(f, "`{}` is not defined in this scope", sym.materialise_name()),
+      Kind::DuplicatedBinders(syms) =>
+        write!(f, "{} bound more than once", list_symbols(syms)),
+      Kind::InvalidDeconstructionPattern(sym) =>
+        write!(f, "`{}` cannot be used to deconstruct a value", sym.materialise_name()),
+      Kind::IncorrectArity(_) =>
+        write!(f, "incorrect number of arguments"),
+      Kind::DuplicatesInImpCtx(syms) =>
+        write!(f, "{} listed more than once in implicit context", list_symbols(syms)),
+      Kind::UnsedImpCtxAtTerminalNode(_) =>
+        write!(f, "implicit context is given to an expression that cant use it"),
+      Kind::UnusedItemsInImpCtx(syms) =>
+        write!(f, "{} from implicit context never used", list_symbols(syms)),
+      Kind::MismatchedType { .. } =>
+        write!(f, "expression does not have expected type"),
+      Kind::ArityMismatch { expected, found, .. } =>
+        write!(f, "clause has {} patterns, but {} were expected", found, expected),
+      Kind::BinderShapeConflict { .. } =>
+        write!(f, "pattern conflicts with other patterns in this position"),
+      Kind::PatternMismatchesSignature { .. } =>
+        write!(f, "pattern cant match values of the type in signature"),
+      Kind::NonfuncTypeInFuncPos(_) =>
+        write!(f, "declaration with rewrite rules must have a function type"),
+      Kind::MissingCases { name, example } =>
+        write!(f, "rules of `{}` dont cover every case, for example `{}`",
+          name.materialise_name(), example),
+      Kind::UnreachableRule(_) =>
+        write!(f, "rule can never fire, rules before it match everything it does"),
+      Kind::MalformedSyntax(err) =>
+        write!(f, "{}", err),
+      Kind::UnreadableFile(msg) =>
+        write!(f, "cant read file: {}", msg),
+      Kind::UnreadableDirectory(msg) =>
+        write!(f, "cant read directory of sources: {}", msg),
+      Kind::UnknownModule(module) =>
+        write!(f, "module `{}` is not found", module.materialise_name()),
+      Kind::AmbiguousName { name, candidates } => {
+        let candidates =
+          candidates.iter().map(|sym| format!("`{}`", sym.materialise_name()))
+          .collect::<Vec<_>>();
+        write!(f, "`{}` may refer to any of {}",
+          name.materialise_name(), candidates.join(", "))
+      },
+      Kind::ImportCycle(path) => {
+        let path =
+          path.iter().map(|sym| sym.materialise_name()).collect::<Vec<_>>();
+        write!(f, "modules import each other in a cycle: {}", path.join(" -> "))
+      },
+      Kind::DefinitionCycle(path) => {
+        let path =
+          path.iter().map(|sym| sym.materialise_name()).collect::<Vec<_>>();
+        write!(f, "definitions unfold into each other without end: {}", path.join(" -> "))
+      },
+      Kind::NonTerminatingRecursion { name, .. } =>
+        write!(f, "`{}` may recurse without end, arguments of recursive calls dont get smaller",
+          name.materialise_name()),
+      Kind::NonPositiveOccurrence { data, .. } =>
+        write!(f, "`{}` occurs in its own constructor in a way that is not strictly positive",
+          data.materialise_name()),
+      Kind::ForeignConstructorType { data, .. } =>
+        write!(f, "constructor of `{}` must build values of `{}`",
+          data.materialise_name(), data.materialise_name()),
+      Kind::ReductionOutOfFuel { .. } =>
+        write!(f, "could not decide if types match, reducing took too many steps"),
+      Kind::UninferableType(_) =>
+        write!(f, "could not decide the type of this expression"),
+    }
+  }
+}
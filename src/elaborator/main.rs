@@ -1,40 +1,126 @@
 
 use std::{
-  fs, path::PathBuf,
+  fs, io, path::{PathBuf, Path},
   ptr::addr_of_mut,
+  sync::mpsc::{Sender, channel}, mem::take,
+  collections::HashSet,
 };
 
 use crate::{
   detached,
   support_structures::no_bullshit_closure::DetachedClosure,
   expression_trees::{
-    raw_syntax_nodes::{
-      DeclPtr, DeclKind, Definition, ExprPtr, RawKind
-    },
-    better_nodes::Symbol
+    raw_syntax_nodes::FileId,
+    better_nodes::{Symbol, Declaration, DeclKind, ModuleHeader},
+    source_map::{SourceMap, LineTable},
+    symbol_interner::InternedName,
   },
+  parser::new_parser::{ParsingState, ParsedSource},
 };
 
 use super::{
   action_chain::{ActionLink, TaskContext,},
-  environment::{PasteboardTable, DefaultTableStreamingIterator},
-  diagnostics::DiagnosticService, presense_tester::PresenseSet,
+  environment::PasteboardTable,
+  diagnostics::{
+    DiagnosticService, DiagnosticsDelegate, ProblemReport, Kind},
+  report_rendering::SourceView,
+  presense_tester::PresenseSet,
+  scope_analysis::concretise_declaration,
+  module_system::{qualify, check_module_graph, ModuleScope},
+  type_check::check_declaration_types,
+  context_use_check::check_context_use_in_declaration,
+  rewrite_system_check::check_rewrite_system,
+  data_types::{DataTypes, check_data_declaration},
+  cycle_analysis::analyse_cycles,
+  worker::WorkGroup,
 };
 
 
+const SOURCE_FILE_EXTENSION : &str = "sigil";
+
+pub struct SourceFile {
+  pub path: PathBuf,
+  pub text: String,
+  pub header: ModuleHeader,
+  pub declarations: Vec<Declaration>,
+  // nodes of declarations are allocated by the parser,
+  // so it has to be kept around for as long as they are in use
+  parser: Option<ParsingState>,
+}
+
+pub struct ElaborationOutcome {
+  pub source_files: Vec<SourceFile>,
+  pub symbol_table: PasteboardTable<Symbol, *mut Declaration>,
+  pub global_symbols: PresenseSet<Symbol>,
+  pub constructors: PresenseSet<Symbol>,
+  pub diagnostics: DiagnosticService,
+  pub source_map: SourceMap,
+}
+unsafe impl Send for ElaborationOutcome {}
+impl ElaborationOutcome {
+  pub fn source_views(&self) -> Vec<SourceView<'_>> {
+    return self.source_files.iter().enumerate().map(|(ix, file)| {
+      let lines = self.source_map.lines_of(FileId(ix as u32));
+      SourceView { path: &file.path, text: &file.text, lines }
+    }).collect()
+  }
+  pub fn render_reports(&self) -> String {
+    return self.diagnostics.render_reports(&self.source_views())
+  }
+}
+
 struct EnvBuildState {
-  symbol_table: PasteboardTable<Symbol, DeclPtr>,
+  symbol_table: PasteboardTable<Symbol, *mut Declaration>,
+  global_symbols: PresenseSet<Symbol>,
+  constructors: PresenseSet<Symbol>,
+  data_types: DataTypes,
   diagnostics_engine: DiagnosticService,
   observant_dir_loc: PathBuf,
-  symbol_interner: (),
+  source_files: Vec<SourceFile>,
+  known_modules: HashSet<InternedName>,
+  outcome_sink: Option<Sender<ElaborationOutcome>>,
 }
 
 
+// Work graph that checks a folder, for callers that run it themselves.
+// Problems are printed once it is done, and the outcome is dropped.
+// Whoever has to act on problems, like picking an exit code,
+// should use elaborate_directory and look at its diagnostics
 pub fn elab_invocation_setup(root_folder_path: PathBuf) -> ActionLink {
+  return setup_elaboration(root_folder_path, None);
+}
+
+// Checks every source file in a given folder and hands back
+// everything that was built in the process.
+// Blocks the caller until elaboration is done.
+pub fn elaborate_directory(root_folder_path: PathBuf) -> ElaborationOutcome {
+  let (sender, receiver) = channel();
+  let work_graph = setup_elaboration(root_folder_path, Some(sender));
+  let executor = WorkGroup::init(work_graph);
+  executor.await_completion();
+  return receiver.recv().unwrap();
+}
 
-  let start = ActionLink::make_gateway(detached!([root_folder_path] |ctx: TaskContext| {
+fn setup_elaboration(
+  root_folder_path: PathBuf,
+  outcome_sink: Option<Sender<ElaborationOutcome>>
+) -> ActionLink {
+
+  let setup = (root_folder_path, outcome_sink);
+  let start = ActionLink::make_gateway(detached!([setup] |ctx: TaskContext| {
+    let (root_folder_path, outcome_sink) = setup;
     let env = ctx.interpret_frame::<EnvBuildState>();
-    unsafe { addr_of_mut!(env.observant_dir_loc).write(root_folder_path) };
+    unsafe {
+      addr_of_mut!(env.symbol_table).write(PasteboardTable::init());
+      addr_of_mut!(env.global_symbols).write(PresenseSet::init());
+      addr_of_mut!(env.constructors).write(PresenseSet::init());
+      addr_of_mut!(env.data_types).write(DataTypes::default());
+      addr_of_mut!(env.diagnostics_engine).write(DiagnosticService::init());
+      addr_of_mut!(env.observant_dir_loc).write(root_folder_path);
+      addr_of_mut!(env.source_files).write(Vec::new());
+      addr_of_mut!(env.known_modules).write(HashSet::new());
+      addr_of_mut!(env.outcome_sink).write(outcome_sink);
+    };
     return ActionLink::from_fun(begin_processing_files);
   }).erase_to_sendable());
   return ActionLink::make_autosized_frame_request::<EnvBuildState>(start);
@@ -42,24 +128,351 @@ pub fn elab_invocation_setup(root_folder_path: PathBuf) -> ActionLink {
 
 fn begin_processing_files(ctx: TaskContext) -> ActionLink {
 
+  let EnvBuildState {
+    observant_dir_loc,
+    source_files,
+    diagnostics_engine,
+    ..
+  } = ctx.interpret_frame::<EnvBuildState>();
+
+  let mut paths = Vec::new();
+  let outcome =
+    collect_source_paths(observant_dir_loc, &mut paths);
+  if let Err(err) = outcome {
+    let problem = ProblemReport {
+      kind: Kind::UnreadableDirectory(err.to_string())
+    };
+    diagnostics_engine.report_problem(FileId::DETACHED, problem);
+    // some files may have been found before the failure,
+    // but checking part of a project would only mislead
+    return ActionLink::from_fun(conclude_elaboration);
+  }
+  paths.sort();
+  for path in paths {
+    source_files.push(SourceFile {
+      path, text: String::new(), header: ModuleHeader::default(),
+      declarations: Vec::new(), parser: None
+    });
+  }
+
+  for index in 0 .. source_files.len() {
+    let file_id = FileId(index as u32);
+    let task = ActionLink::make_gateway(detached!([file_id] |ctx: TaskContext| {
+      parse_source_file(ctx, file_id);
+      return ActionLink::make_completion();
+    }).erase_to_sendable());
+    ctx.assign_work_for_schedule(
+      ActionLink::make_autosized_frame_request::<()>(task));
+  }
+
+  return ActionLink::from_fun(check_declarations);
+}
+
+pub fn collect_source_paths(
+  folder: &Path,
+  paths: &mut Vec<PathBuf>
+) -> io::Result<()> {
+  for entry in fs::read_dir(folder)? {
+    let path = entry?.path();
+    if path.is_dir() {
+      collect_source_paths(&path, paths)?;
+      continue;
+    }
+    let is_source_file =
+      path.extension().is_some_and(|ext| ext == SOURCE_FILE_EXTENSION);
+    if is_source_file { paths.push(path) }
+  }
+  return Ok(())
+}
+
+fn parse_source_file(ctx: TaskContext, file_id: FileId) {
+  let parrent_frame = ctx.get_parrent_frame().unwrap();
+  let env = parrent_frame.interpret_frame::<EnvBuildState>();
+  // each task owns exactly one file, so there is no contention here
+  let source_file = unsafe {
+    &mut *env.source_files.as_mut_ptr().add(file_id.0 as usize)
+  };
+  let mut delegate = DiagnosticsDelegate::init(file_id);
+
+  match fs::read_to_string(&source_file.path) {
+    Ok(text) => source_file.text = text,
+    Err(err) => {
+      let problem = ProblemReport {
+        kind: Kind::UnreadableFile(err.to_string())
+      };
+      delegate.report_problem(problem);
+      env.diagnostics_engine.absorb_delegate(delegate);
+      return;
+    }
+  }
+
+  let mut parser = ParsingState::init_for_file(&source_file.text, file_id);
+  let ParsedSource { header, declarations, errors } =
+    parser.parse_decls_with_recovery();
+  for err in errors {
+    let problem = ProblemReport {
+      kind: Kind::MalformedSyntax(err)
+    };
+    delegate.report_problem(problem);
+  }
+  // declarations are known by their canonical names from now on
+  for mut decl in declarations {
+    let name = qualify(header.name, decl.project_name());
+    decl.rename(name);
+    decl.rename_constructors(|ctor| qualify(header.name, ctor));
+    source_file.declarations.push(decl);
+  }
+  source_file.header = header;
+  source_file.parser = Some(parser);
+
+  env.diagnostics_engine.absorb_delegate(delegate);
+}
+
+fn check_declarations(ctx: TaskContext) -> ActionLink {
+
   let EnvBuildState {
     symbol_table,
+    global_symbols,
+    constructors,
     diagnostics_engine,
-    observant_dir_loc,
-    symbol_interner
+    source_files,
+    known_modules,
+    ..
+  } = ctx.interpret_frame::<EnvBuildState>();
+
+  let redeclarations = register_declarations(
+    source_files, symbol_table, global_symbols, constructors);
+
+  let headers = source_files.iter().enumerate()
+    .map(|(ix, file)| (FileId(ix as u32), &file.header))
+    .collect::<Vec<_>>();
+  *known_modules =
+    check_module_graph(&headers, global_symbols, diagnostics_engine);
+
+  for (origin, another) in redeclarations {
+    let one = unsafe { &**symbol_table.retrieve_ref(&another).unwrap() };
+    let one = one.project_constructor_names().into_iter()
+      .find(|ctor| *ctor == another)
+      .unwrap_or(one.project_name());
+    let problem = ProblemReport {
+      kind: Kind::DuplicateDecls { one, another }
+    };
+    diagnostics_engine.report_problem(origin, problem);
+  }
+
+  for index in 0 .. source_files.len() {
+    let file_id = FileId(index as u32);
+    let task = ActionLink::make_gateway(detached!([file_id] |ctx: TaskContext| {
+      check_source_file(ctx, file_id);
+      return ActionLink::make_completion();
+    }).erase_to_sendable());
+    ctx.assign_work_for_schedule(
+      ActionLink::make_autosized_frame_request::<()>(task));
+  }
+
+  return ActionLink::from_fun(check_rewrite_systems);
+}
+
+// Files are parsed in parallel, so which of them gets to a name first
+// is up to chance. Names are given out here instead, in order of files
+// and then of declarations in them, and the first one to claim a name keeps it.
+// Gives back names that were claimed again, with files that did so
+fn register_declarations(
+  source_files: &mut [SourceFile],
+  symbol_table: &PasteboardTable<Symbol, *mut Declaration>,
+  global_symbols: &PresenseSet<Symbol>,
+  constructors: &PresenseSet<Symbol>,
+) -> Vec<(FileId, Symbol)> {
+  let mut redeclarations = Vec::new();
+  for (index, source_file) in source_files.iter_mut().enumerate() {
+    let file_id = FileId(index as u32);
+    let mut accepted = Vec::new();
+    let mut claimed_constructors = Vec::new();
+    for mut decl in take(&mut source_file.declarations) {
+      let name = decl.project_name();
+      let was_declared = global_symbols.check_in(&name);
+      if was_declared {
+        redeclarations.push((file_id, name));
+        continue;
+      }
+      // constructors live among other globals
+      for ctor in decl.project_constructor_names() {
+        let was_declared = global_symbols.check_in(&ctor);
+        if was_declared {
+          redeclarations.push((file_id, ctor));
+          decl.is_malformed = true;
+          continue;
+        }
+        constructors.check_in(&ctor);
+        claimed_constructors.push((accepted.len(), ctor));
+      }
+      accepted.push(decl);
+    }
+    source_file.declarations = accepted;
+    // storage of declarations wont move from now on
+    for decl in source_file.declarations.iter_mut() {
+      let name = decl.project_name();
+      symbol_table.insert(&name, decl);
+    }
+    for (index, ctor) in claimed_constructors {
+      let decl = &mut source_file.declarations[index];
+      symbol_table.insert(&ctor, decl);
+    }
+  }
+  return redeclarations
+}
+
+fn check_source_file(ctx: TaskContext, file_id: FileId) {
+  let parrent_frame = ctx.get_parrent_frame().unwrap();
+  let env = parrent_frame.interpret_frame::<EnvBuildState>();
+  let source_file = unsafe {
+    &mut *env.source_files.as_mut_ptr().add(file_id.0 as usize)
+  };
+  let mut delegate = DiagnosticsDelegate::init(file_id);
+  let scope = ModuleScope::init(
+    &source_file.header, &env.known_modules, &env.global_symbols,
+    &env.constructors);
+
+  for decl in source_file.declarations.iter_mut() {
+    let reports_before = delegate.reports.len();
+    concretise_declaration(decl, &mut delegate, &scope);
+    if delegate.reports.len() != reports_before {
+      decl.is_malformed = true;
+      continue;
+    }
+    check_context_use_in_declaration(decl, &mut delegate);
+    if let DeclKind::WellScopedData { .. } = decl.repr {
+      decl.is_malformed = !check_data_declaration(decl, &mut delegate);
+    }
+  }
+
+  env.diagnostics_engine.absorb_delegate(delegate);
+}
+
+// patterns may use constructors from any file,
+// so rewrite rules are checked once all data types are known
+fn check_rewrite_systems(ctx: TaskContext) -> ActionLink {
+
+  let EnvBuildState {
+    source_files,
+    data_types,
+    ..
   } = ctx.interpret_frame::<EnvBuildState>();
 
-  let root_folder =
-    fs::read_dir(observant_dir_loc);
+  *data_types = DataTypes::collect(
+    source_files.iter().flat_map(|file| file.declarations.iter()));
 
-  match root_folder {
-    Ok(val) => {
-      println!("Openned successfully {:#?}", val)
+  for index in 0 .. source_files.len() {
+    let file_id = FileId(index as u32);
+    let task = ActionLink::make_gateway(detached!([file_id] |ctx: TaskContext| {
+      check_rewrite_systems_in_source_file(ctx, file_id);
+      return ActionLink::make_completion();
+    }).erase_to_sendable());
+    ctx.assign_work_for_schedule(
+      ActionLink::make_autosized_frame_request::<()>(task));
+  }
+
+  return ActionLink::from_fun(check_types);
+}
+
+fn check_rewrite_systems_in_source_file(ctx: TaskContext, file_id: FileId) {
+  let parrent_frame = ctx.get_parrent_frame().unwrap();
+  let env = parrent_frame.interpret_frame::<EnvBuildState>();
+  let source_file = unsafe {
+    &mut *env.source_files.as_mut_ptr().add(file_id.0 as usize)
+  };
+  let mut delegate = DiagnosticsDelegate::init(file_id);
+
+  for decl in source_file.declarations.iter_mut() {
+    if decl.is_malformed { continue }
+    if let DeclKind::WellScopedMapping { .. } = decl.repr {
+      let is_well_formed =
+        check_rewrite_system(*decl, &env.data_types, &mut delegate);
+      decl.is_malformed = !is_well_formed;
+    }
+  }
+
+  env.diagnostics_engine.absorb_delegate(delegate);
+}
+
+// types and cycles go through declarations from every file,
+// so checking them waits till all files are concretised
+fn check_types(ctx: TaskContext) -> ActionLink {
+
+  let EnvBuildState {
+    source_files,
+    symbol_table,
+    diagnostics_engine,
+    ..
+  } = ctx.interpret_frame::<EnvBuildState>();
+
+  // whole program is needed for this one, so it is done in place
+  analyse_cycles(symbol_table, diagnostics_engine);
+
+  for index in 0 .. source_files.len() {
+    let file_id = FileId(index as u32);
+    let task = ActionLink::make_gateway(detached!([file_id] |ctx: TaskContext| {
+      check_types_in_source_file(ctx, file_id);
+      return ActionLink::make_completion();
+    }).erase_to_sendable());
+    ctx.assign_work_for_schedule(
+      ActionLink::make_autosized_frame_request::<()>(task));
+  }
+
+  return ActionLink::from_fun(conclude_elaboration);
+}
+
+fn check_types_in_source_file(ctx: TaskContext, file_id: FileId) {
+  let parrent_frame = ctx.get_parrent_frame().unwrap();
+  let env = parrent_frame.interpret_frame::<EnvBuildState>();
+  let source_file = &env.source_files[file_id.0 as usize];
+  let mut delegate = DiagnosticsDelegate::init(file_id);
+
+  for decl in &source_file.declarations {
+    check_declaration_types(decl, &env.symbol_table, &mut delegate);
+  }
+
+  env.diagnostics_engine.absorb_delegate(delegate);
+}
+
+fn conclude_elaboration(ctx: TaskContext) -> ActionLink {
+
+  // frame is released upon completion, so its content has to be moved out
+  let EnvBuildState {
+    symbol_table,
+    global_symbols,
+    constructors,
+    diagnostics_engine,
+    source_files,
+    outcome_sink,
+    ..
+  } = unsafe { (ctx.interpret_frame::<EnvBuildState>() as *mut EnvBuildState).read() };
+
+  let mut source_map = SourceMap::init();
+  for file in &source_files {
+    let lines = match &file.parser {
+      Some(parser) => parser.line_table().clone(),
+      None => LineTable::compute(&file.text),
+    };
+    source_map.register_file(file.path.clone(), lines);
+  }
+
+  let outcome = ElaborationOutcome {
+    source_files,
+    symbol_table,
+    global_symbols,
+    constructors,
+    diagnostics: diagnostics_engine,
+    source_map,
+  };
+  match outcome_sink {
+    Some(sink) => {
+      let _ = sink.send(outcome);
     },
-    Err(err) => {
-      println!("{err}")
+    None => {
+      outcome.diagnostics.dump_reports_to_stdout(&outcome.source_views());
     },
   }
 
   return ActionLink::make_completion();
-}
\ No newline at end of file
+}
@@ -7,9 +7,17 @@ pub mod frame_allocator;
 pub mod main;
 //pub mod different_chain;
 pub mod diagnostics;
+pub mod report_rendering;
 pub mod presense_tester;
 pub mod scope_analysis;
+pub mod data_types;
+pub mod module_system;
+pub mod semantic_terms;
+pub mod normaliser;
+pub mod queries;
+pub mod type_check;
 pub mod context_use_check;
 pub mod cycle_analysis;
+pub mod termination_check;
 pub mod rewrite_system_check;
 pub mod coverage_analysis;
\ No newline at end of file
@@ -0,0 +1,220 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::parser::new_parser::is_identifier;
+use crate::expression_trees::{
+  raw_syntax_nodes::FileId,
+  better_nodes::{Symbol, ModuleHeader, Import, ImportSelection},
+  symbol_interner::{InternedName, global_interner},
+};
+
+use super::{
+  diagnostics::{DiagnosticService, ProblemReport, Kind},
+  presense_tester::PresenseSet,
+  scope_analysis::{NameScope, NameResolution},
+};
+
+
+// Declarations of a module `M` are known under `M.name`,
+// so that equally named things from different modules dont clash.
+// Files without a module line make up one unnamed module,
+// whose declarations keep their names as written.
+// It cant be imported, so its declarations are seen
+// only by other files without a module line
+pub fn qualify(module: Option<Symbol>, name: Symbol) -> Symbol {
+  let Some(module) = module else { return name };
+  let text = format!("{}.{}", module.materialise_name(), name.materialise_name());
+  return Symbol { name: global_interner().intern(&text), ..name }
+}
+
+// `A.B.c` is `c` from `A.B`.
+// Dots are operator chars too, so `<.>` is left alone.
+// Only identifiers can be qualified
+fn split_qualified(name: Symbol) -> Option<(Symbol, Symbol)> {
+  let text = name.materialise_name();
+  if !text.split('.').all(is_identifier) { return None }
+  let dot = text.rfind('.')?;
+  let interner = global_interner();
+  let mut module = name;
+  module.name = interner.intern(&text[.. dot]);
+  module.location.secondary_offset = module.location.primary_offset + dot as u32;
+  let mut base = name;
+  base.name = interner.intern(&text[dot + 1 ..]);
+  return Some((module, base))
+}
+
+
+// Checks imports of every file against the set of loaded modules.
+// Imports that name a missing module are reported and
+// should be left out of scopes, so that their names dont
+// show up as a flood of secondary errors
+pub fn check_module_graph(
+  headers: &[(FileId, &ModuleHeader)],
+  global_symbols: &PresenseSet<Symbol>,
+  diagnostics: &DiagnosticService,
+) -> HashSet<InternedName> {
+  let modules = headers.iter()
+    .filter_map(|(_, header)| header.name.map(|name| name.name))
+    .collect::<HashSet<_>>();
+
+  let mut graph = HashMap::<InternedName, Vec<(FileId, Symbol)>>::new();
+  for (file, header) in headers {
+    for import in &header.imports {
+      if !modules.contains(&import.module.name) {
+        let problem = ProblemReport { kind: Kind::UnknownModule(import.module) };
+        diagnostics.report_problem(*file, problem);
+        continue;
+      }
+      let selected = match import.selection {
+        ImportSelection::Everything => &[][..],
+        ImportSelection::Only(ref names) |
+        ImportSelection::Hiding(ref names) => &names[..],
+      };
+      for name in selected {
+        let exists = global_symbols.check_out(&qualify(Some(import.module), *name));
+        if !exists {
+          let problem = ProblemReport { kind: Kind::IrrelevantSymbol(*name) };
+          diagnostics.report_problem(*file, problem);
+        }
+      }
+      let Some(importer) = header.name else { continue };
+      graph.entry(importer.name).or_default().push((*file, import.module));
+    }
+  }
+
+  for (file, cycle) in find_import_cycles(&graph) {
+    let problem = ProblemReport { kind: Kind::ImportCycle(cycle) };
+    diagnostics.report_problem(file, problem);
+  }
+
+  return modules
+}
+
+// Each cycle is reported once, from the import that closes it.
+// Path starts and ends with the same module
+fn find_import_cycles(
+  graph: &HashMap<InternedName, Vec<(FileId, Symbol)>>
+) -> Vec<(FileId, Vec<Symbol>)> {
+  #[derive(Clone, Copy, PartialEq, Eq)]
+  enum Mark { InProgress, Done }
+
+  fn visit(
+    module: InternedName,
+    graph: &HashMap<InternedName, Vec<(FileId, Symbol)>>,
+    marks: &mut HashMap<InternedName, Mark>,
+    path: &mut Vec<Symbol>,
+    cycles: &mut Vec<(FileId, Vec<Symbol>)>,
+  ) {
+    marks.insert(module, Mark::InProgress);
+    for (file, imported) in graph.get(&module).map_or(&[][..], |edges| &edges[..]) {
+      match marks.get(&imported.name) {
+        Some(Mark::Done) => (),
+        Some(Mark::InProgress) => {
+          let start = path.iter()
+            .position(|step| step.name == imported.name)
+            .unwrap_or(0);
+          let mut cycle = path[start ..].to_vec();
+          cycle.push(*imported);
+          cycles.push((*file, cycle));
+        },
+        None => {
+          path.push(*imported);
+          visit(imported.name, graph, marks, path, cycles);
+          path.pop();
+        },
+      }
+    }
+    marks.insert(module, Mark::Done);
+  }
+
+  // order of a hash map is not stable, reports should be
+  let mut roots = graph.iter()
+    .map(|(module, edges)| (edges[0].0, edges[0].1.location.primary_offset, *module))
+    .collect::<Vec<_>>();
+  roots.sort_by_key(|(file, offset, _)| (file.0, *offset));
+
+  let mut marks = HashMap::new();
+  let mut cycles = Vec::new();
+  for (_, _, module) in roots {
+    if marks.contains_key(&module) { continue; }
+    let root = Symbol {
+      name: module,
+      ..graph[&module][0].1
+    };
+    let mut path = vec![root];
+    visit(module, graph, &mut marks, &mut path, &mut cycles);
+  }
+  return cycles
+}
+
+
+// Names visible from a single file: its own module first,
+// then everything its imports let in
+pub struct ModuleScope<'a> {
+  pub global_symbols: &'a PresenseSet<Symbol>,
+  // canonical names of all constructors of data types
+  pub constructors: &'a PresenseSet<Symbol>,
+  pub own_module: Option<Symbol>,
+  pub imports: Vec<&'a Import>,
+}
+
+impl <'a> ModuleScope<'a> {
+  pub fn init(
+    header: &'a ModuleHeader,
+    known_modules: &HashSet<InternedName>,
+    global_symbols: &'a PresenseSet<Symbol>,
+    constructors: &'a PresenseSet<Symbol>,
+  ) -> Self {
+    let imports = header.imports.iter()
+      .filter(|import| known_modules.contains(&import.module.name))
+      .collect();
+    return Self { global_symbols, constructors, own_module: header.name, imports }
+  }
+  // every import that admits a name gives a candidate.
+  // same declaration can come through several imports
+  fn candidates(
+    &self, name: Symbol, imports: impl Iterator<Item = &'a Import>
+  ) -> NameResolution {
+    let mut found = Vec::<Symbol>::new();
+    for import in imports {
+      if !import.selection.admits(name) { continue; }
+      let canonical = qualify(Some(import.module), name);
+      if !self.global_symbols.check_out(&canonical) { continue; }
+      if !found.contains(&canonical) { found.push(canonical) }
+    }
+    match found.len() {
+      0 => NameResolution::Missing,
+      1 => NameResolution::Found(found[0]),
+      _ => NameResolution::Ambiguous(found),
+    }
+  }
+}
+
+impl NameScope for ModuleScope<'_> {
+  fn resolve(&self, name: Symbol) -> NameResolution {
+    let local = qualify(self.own_module, name);
+    if self.global_symbols.check_out(&local) {
+      return NameResolution::Found(local)
+    }
+    let Some((qualifier, base)) = split_qualified(name) else {
+      return self.candidates(name, self.imports.iter().copied())
+    };
+    if self.own_module == Some(qualifier) {
+      let local = qualify(self.own_module, base);
+      if self.global_symbols.check_out(&local) {
+        return NameResolution::Found(local)
+      }
+      return NameResolution::Missing
+    }
+    // an alias hides the original name of a module
+    let mut qualifying = self.imports.iter().copied().filter(|import| {
+      return import.alias.unwrap_or(import.module) == qualifier
+    }).peekable();
+    if qualifying.peek().is_none() {
+      return NameResolution::UnknownModule(qualifier)
+    }
+    return self.candidates(base, qualifying)
+  }
+  fn is_constructor(&self, name: Symbol) -> bool {
+    return self.constructors.check_out(&name)
+  }
+}
@@ -0,0 +1,307 @@
+use crate::expression_trees::{
+  better_nodes::{
+    Symbol, Declaration, DeclKind, ConcretisedNodeRepr, ConcretisedRewriteRule,
+    ConcretisedPattern, ConcretisedPatternKind, ArrayPtr},
+  raw_syntax_nodes::FileId,
+};
+
+use super::{
+  environment::PasteboardTable,
+  semantic_terms::{Term, TermKind, Head, apply, substitute, mark_origin},
+};
+
+
+// Where declarations that terms refer to are found
+pub trait GlobalDeclarations {
+  fn find_declaration(&self, name: Symbol) -> Option<Declaration>;
+}
+
+impl GlobalDeclarations for PasteboardTable<Symbol, *mut Declaration> {
+  fn find_declaration(&self, name: Symbol) -> Option<Declaration> {
+    return self.retrieve_ref(&name).map(|decl| unsafe { **decl })
+  }
+}
+
+// One rewrite that was performed, for those who want to watch
+#[derive(Debug, Clone)]
+pub struct ReductionStep {
+  pub redex: Term,
+  pub contractum: Term,
+}
+
+// Reduces terms by unfolding definitions and rewriting
+// applications of mappings and lambdas with the first rule
+// whose patterns match the arguments.
+//
+// Rules can loop, and there is nothing yet that rules this out,
+// so every normaliser has a limited number of steps it may take.
+// After that terms are given back as far as they got
+pub struct Normaliser<'a> {
+  globals: &'a dyn GlobalDeclarations,
+  // placeholders that were filled by unification
+  pub holes: Vec<Option<Term>>,
+  fuel: usize,
+  trace: Option<Vec<ReductionStep>>,
+}
+
+pub const DEFAULT_FUEL : usize = 100_000;
+
+enum Match {
+  Matched(Vec<(Head, Term)>),
+  // argument has another shape
+  Failed,
+  // argument is not a value yet, so no rule can be picked
+  Stuck,
+}
+
+impl <'a> Normaliser<'a> {
+  pub fn init(globals: &'a dyn GlobalDeclarations) -> Self {
+    return Self { globals, holes: Vec::new(), fuel: DEFAULT_FUEL, trace: None }
+  }
+  pub fn set_fuel(&mut self, fuel: usize) {
+    self.fuel = fuel
+  }
+  pub fn ran_out_of_fuel(&self) -> bool {
+    self.fuel == 0
+  }
+  pub fn record_steps(&mut self) {
+    self.trace = Some(Vec::new())
+  }
+  pub fn take_steps(&mut self) -> Vec<ReductionStep> {
+    return self.trace.take().unwrap_or_default()
+  }
+  fn note_step(&mut self, redex: &Term, contractum: &Term) {
+    if let Some(ref mut trace) = self.trace {
+      trace.push(ReductionStep { redex: redex.clone(), contractum: contractum.clone() })
+    }
+  }
+}
+
+/// Weak head
+impl Normaliser<'_> {
+  // Reduces a term until its outermost shape is known.
+  // Result keeps the location of the given term
+  pub fn whnf(&mut self, term: &Term) -> Term {
+    let (location, origin) = (term.location, term.origin);
+    let mut term = term.clone();
+    loop {
+      if self.fuel == 0 { break }
+      let Some(reduced) = self.head_step(&term) else { break };
+      self.fuel -= 1;
+      term = reduced;
+    }
+    term.location = location;
+    term.origin = origin;
+    return term
+  }
+  // single rewrite at the head, if there is one to do
+  fn head_step(&mut self, term: &Term) -> Option<Term> {
+    match &term.kind {
+      TermKind::Neutral { head: Head::Hole(ix), arguments } => {
+        let solution = self.holes.get(*ix as usize)?.clone()?;
+        return Some(apply(solution, arguments.clone()))
+      },
+      TermKind::Neutral { head: Head::Global(name), arguments } => {
+        let decl = self.globals.find_declaration(*name)?;
+        if decl.is_malformed { return None }
+        let origin = decl.project_name().origin;
+        let reduced = match decl.repr {
+          DeclKind::WellScopedDefinition { value, .. } => {
+            let mut value = Term::of_node(unsafe { *value });
+            mark_origin(&mut value, origin);
+            apply(value, arguments.clone())
+          },
+          DeclKind::WellScopedMapping { given_type, rewrite_rules, .. } => {
+            let ConcretisedNodeRepr::Arrow { head, .. } = unsafe { *given_type }.kind
+            else { return None };
+            let arity = head.project_count() as usize;
+            self.rewrite(rewrite_rules, &[], Some(origin), arity, arguments)?
+          },
+          _ => return None
+        };
+        self.note_step(term, &reduced);
+        return Some(reduced)
+      },
+      TermKind::Apply { function, arguments } => {
+        let function = self.whnf(function);
+        let reduced = match function.kind {
+          TermKind::Lam { rewrite_rules, ref environment } => {
+            let arity = rewrite_rules_arity(rewrite_rules)?;
+            self.rewrite(rewrite_rules, environment, function.origin, arity, arguments)?
+          },
+          // function became a name or an application,
+          // so arguments are gathered and reduced with it
+          TermKind::Neutral { .. } | TermKind::Apply { .. } | TermKind::Unknown => {
+            return Some(apply(function, arguments.clone()))
+          },
+          _ => return None
+        };
+        self.note_step(term, &reduced);
+        return Some(reduced)
+      },
+      _ => return None
+    }
+  }
+  // Picks the first rule that matches. Extra arguments are
+  // applied to the result, and too few of them leave the term as it is.
+  // Origin is the file the rules were written in
+  fn rewrite(
+    &mut self,
+    rewrite_rules: ArrayPtr<ConcretisedRewriteRule>,
+    environment: &[(Symbol, Term)],
+    origin: Option<FileId>,
+    arity: usize,
+    arguments: &[Term],
+  ) -> Option<Term> {
+    if arguments.len() < arity { return None }
+    let (taken, extra) = arguments.split_at(arity);
+    // arguments are kept in the shape they were reduced to,
+    // so that the next rule doesnt have to do it all again
+    let mut taken = taken.to_vec();
+    for ix in 0 .. rewrite_rules.project_count() {
+      let rule = unsafe { *rewrite_rules.get_ptr(ix) };
+      if rule.matchers.project_count() as usize != arity { continue }
+      let mut bindings = environment.iter()
+        .map(|(name, value)| (Head::Local(*name), value.clone()))
+        .collect::<Vec<_>>();
+      let mut outcome = Match::Matched(Vec::new());
+      for (column, argument) in taken.iter_mut().enumerate() {
+        let pattern = unsafe { *rule.matchers.get_ptr(column as u32) };
+        outcome = self.match_pattern(pattern, argument);
+        let Match::Matched(ref mut found) = outcome else { break };
+        bindings.append(found);
+      }
+      match outcome {
+        Match::Matched(_) => {
+          let mut rhs = Term::of_node(unsafe { *rule.rhs });
+          if let Some(origin) = origin { mark_origin(&mut rhs, origin) }
+          return Some(apply(substitute(&rhs, &bindings), extra.to_vec()))
+        },
+        Match::Failed => continue,
+        Match::Stuck => return None,
+      }
+    }
+    return None
+  }
+  fn match_pattern(&mut self, pattern: ConcretisedPattern, term: &mut Term) -> Match {
+    match pattern.repr {
+      ConcretisedPatternKind::Wildcard => return Match::Matched(Vec::new()),
+      ConcretisedPatternKind::VarBinding(name) => {
+        return Match::Matched(vec![(Head::Local(name), term.clone())])
+      },
+      _ => *term = self.whnf(term),
+    };
+    let is_value = match term.kind {
+      TermKind::Pt | TermKind::Left(_) | TermKind::Right(_) | TermKind::Tuple(..) => true,
+      TermKind::Neutral { head: Head::Global(name), .. } => self.is_constructor(name),
+      _ => false,
+    };
+    match (pattern.repr, &mut term.kind) {
+      (ConcretisedPatternKind::Pt, TermKind::Pt) => Match::Matched(Vec::new()),
+      (ConcretisedPatternKind::Left(inner), TermKind::Left(value)) |
+      (ConcretisedPatternKind::Right(inner), TermKind::Right(value)) => {
+        self.match_pattern(unsafe { *inner }, value)
+      },
+      (ConcretisedPatternKind::Tuple(l, r), TermKind::Tuple(lv, rv)) => {
+        let left = self.match_pattern(unsafe { *l }, lv);
+        let Match::Matched(mut left) = left else { return left };
+        match self.match_pattern(unsafe { *r }, rv) {
+          Match::Matched(mut right) => {
+            left.append(&mut right);
+            Match::Matched(left)
+          },
+          other => other,
+        }
+      },
+      (ConcretisedPatternKind::Constructor { name, arguments: patterns },
+       TermKind::Neutral { head: Head::Global(head), arguments })
+      if name == *head && patterns.project_count() as usize == arguments.len() => {
+        let mut bindings = Vec::new();
+        for (ix, argument) in arguments.iter_mut().enumerate() {
+          let pattern = unsafe { *patterns.get_ptr(ix as u32) };
+          match self.match_pattern(pattern, argument) {
+            Match::Matched(mut found) => bindings.append(&mut found),
+            other => return other,
+          }
+        }
+        Match::Matched(bindings)
+      },
+      _ if is_value => Match::Failed,
+      _ => Match::Stuck,
+    }
+  }
+  // constructors build values that dont reduce any further
+  fn is_constructor(&self, name: Symbol) -> bool {
+    let Some(decl) = self.globals.find_declaration(name) else { return false };
+    return !decl.is_malformed && decl.find_constructor(name).is_some()
+  }
+}
+
+// lambdas dont declare their arity, but all of their rules agree on it
+fn rewrite_rules_arity(rewrite_rules: ArrayPtr<ConcretisedRewriteRule>) -> Option<usize> {
+  if rewrite_rules.project_count() == 0 { return None }
+  let first = unsafe { *rewrite_rules.get_ptr(0) };
+  return Some(first.matchers.project_count() as usize)
+}
+
+/// Full normalisation
+impl Normaliser<'_> {
+  // Reduces everywhere, including under binders.
+  // Bodies of lambdas are left alone, since their rules
+  // can only be taken apart by applying them
+  pub fn normalise(&mut self, term: &Term) -> Term {
+    let term = self.whnf(term);
+    let mut all = |terms: &[Term]| {
+      return terms.iter().map(|term| self.normalise(term)).collect::<Vec<_>>()
+    };
+    let kind = match term.kind {
+      TermKind::Neutral { head, ref arguments } => {
+        TermKind::Neutral { head, arguments: all(arguments) }
+      },
+      TermKind::Apply { ref function, ref arguments } => {
+        let arguments = all(arguments);
+        TermKind::Apply { function: Box::new(self.normalise(function)), arguments }
+      },
+      TermKind::Wit { ref premises, ref conclusion } => {
+        let premises = all(premises);
+        TermKind::Wit { premises, conclusion: Box::new(self.normalise(conclusion)) }
+      },
+      TermKind::Forall { ref context, ref body } => {
+        let context = context.iter()
+          .map(|(name, type_)| (*name, self.normalise(type_)))
+          .collect();
+        TermKind::Forall { context, body: Box::new(self.normalise(body)) }
+      },
+      TermKind::Pi { ref head, ref spine } | TermKind::Sigma { ref head, ref spine } => {
+        let head = head.iter()
+          .map(|(name, type_)| (*name, self.normalise(type_)))
+          .collect();
+        let spine = Box::new(self.normalise(spine));
+        match term.kind {
+          TermKind::Pi { .. } => TermKind::Pi { head, spine },
+          _ => TermKind::Sigma { head, spine },
+        }
+      },
+      TermKind::Lam { rewrite_rules, ref environment } => {
+        let environment = environment.iter()
+          .map(|(name, value)| (*name, self.normalise(value)))
+          .collect();
+        TermKind::Lam { rewrite_rules, environment }
+      },
+      TermKind::Pair(ref l, ref r) => {
+        TermKind::Pair(Box::new(self.normalise(l)), Box::new(self.normalise(r)))
+      },
+      TermKind::Either(ref l, ref r) => {
+        TermKind::Either(Box::new(self.normalise(l)), Box::new(self.normalise(r)))
+      },
+      TermKind::Tuple(ref l, ref r) => {
+        TermKind::Tuple(Box::new(self.normalise(l)), Box::new(self.normalise(r)))
+      },
+      TermKind::Left(ref v) => TermKind::Left(Box::new(self.normalise(v))),
+      TermKind::Right(ref v) => TermKind::Right(Box::new(self.normalise(v))),
+      TermKind::Star | TermKind::Void | TermKind::Singleton |
+      TermKind::Pt | TermKind::Unknown => return term,
+    };
+    return Term::init_at(kind, &term)
+  }
+}
@@ -0,0 +1,176 @@
+use std::path::Path;
+
+use crate::{
+  expression_trees::{
+    better_nodes::{
+      ConcretisedNode, ConcretisedNodeRepr, Import, ImportSelection, Origin, Symbol},
+    raw_syntax_nodes::{FileId, SourceLocation},
+    source_map::{LineTable, LineColumn},
+    more_text_rendering::{render_declaration, DEFAULT_LINE_WIDTH},
+  },
+  parser::new_parser::{
+    ParsingState, ParseError, ParseErrorKind, ParseContext, Token, TokenSet},
+};
+
+use super::{
+  main::ElaborationOutcome,
+  diagnostics::{DiagnosticsDelegate, ProblemReport, Kind},
+  report_rendering::{SourceView, render_report},
+  scope_analysis::concretise_expression,
+  module_system::ModuleScope,
+  semantic_terms::Term,
+  normaliser::{Normaliser, ReductionStep},
+  type_check::TypeChecker,
+  cycle_analysis::trace_dependencies,
+};
+
+
+// An expression typed in by hand, resolved against declarations
+// of an elaborated directory. Names are looked up as if every module
+// of the directory was imported, so both `x` and `M.x` work
+pub struct Query<'a> {
+  outcome: &'a ElaborationOutcome,
+  text: String,
+  // owns nodes of the expression
+  _parser: ParsingState,
+  pub expression: ConcretisedNode,
+}
+
+impl <'a> Query<'a> {
+  // Reports are given back rendered, since they can only be shown
+  // with the text of the query
+  pub fn parse(outcome: &'a ElaborationOutcome, text: &str) -> Result<Self, String> {
+    let text = text.trim().to_string();
+    let file = Self::file_of(outcome);
+    let mut parser = ParsingState::init_for_file(&text, file);
+    let mut delegate = DiagnosticsDelegate::init(file);
+    let parsed = parser.parse_expr(0).and_then(|node| {
+      parser.skip_trivia();
+      if let Some(err) = parser.take_comment_error() { return Err(err) }
+      if parser.no_more_chars() { return Ok(node) }
+      let start = parser.byte_index as u32;
+      let span = SourceLocation { primary_offset: start, secondary_offset: text.len() as u32 };
+      let kind = ParseErrorKind::Expected {
+        tokens: TokenSet::of(&[Token::LineEnd]), context: ParseContext::ExprEnd };
+      return Err(ParseError { kind, span })
+    });
+    let mut node = match parsed {
+      Ok(node) => node,
+      Err(error) => {
+        delegate.report_problem(ProblemReport { kind: Kind::MalformedSyntax(error) });
+        return Err(Self::render(outcome, &text, delegate.reports))
+      }
+    };
+    let imports = outcome.source_files.iter()
+      .filter_map(|file| file.header.name)
+      .map(|module| Import {
+        module, alias: None, selection: ImportSelection::Everything,
+        location: module.location })
+      .collect::<Vec<_>>();
+    let scope = ModuleScope {
+      global_symbols: &outcome.global_symbols,
+      constructors: &outcome.constructors,
+      own_module: None,
+      imports: imports.iter().collect(),
+    };
+    let expression = concretise_expression(&mut node, &mut delegate, &scope);
+    if !delegate.reports.is_empty() {
+      return Err(Self::render(outcome, &text, delegate.reports))
+    }
+    return Ok(Self { outcome, text, _parser: parser, expression })
+  }
+  // query is treated as one more file, after those of the directory
+  fn file_of(outcome: &ElaborationOutcome) -> FileId {
+    return FileId(outcome.source_files.len() as u32)
+  }
+  fn render(
+    outcome: &ElaborationOutcome, text: &str, reports: Vec<ProblemReport>
+  ) -> String {
+    let lines = LineTable::compute(text);
+    let mut sources = outcome.source_views();
+    sources.push(SourceView { path: Path::new("<input>"), text, lines: &lines });
+    let mut out = String::new();
+    for report in reports {
+      render_report(Self::file_of(outcome), &report, &sources, &mut out);
+    }
+    return out
+  }
+  pub fn report(&self, reports: Vec<ProblemReport>) -> String {
+    return Self::render(self.outcome, &self.text, reports)
+  }
+}
+
+pub struct Evaluation {
+  pub result: Term,
+  // empty unless they were asked for
+  pub steps: Vec<ReductionStep>,
+  // rules kept rewriting for too long
+  pub gave_up: bool,
+}
+
+// Fully normalises the expression of a query.
+// It is not type checked first, so ill typed
+// expressions just get stuck somewhere
+pub fn evaluate(query: &Query, show_steps: bool) -> Evaluation {
+  let mut normaliser = Normaliser::init(&query.outcome.symbol_table);
+  if show_steps { normaliser.record_steps() }
+  let result = normaliser.normalise(&Term::of_node(query.expression));
+  let gave_up = normaliser.ran_out_of_fuel();
+  return Evaluation { result, steps: normaliser.take_steps(), gave_up }
+}
+
+// Type of the expression of a query, with everything that was
+// inferred for implicit contexts put in. Lambdas carry no type
+// on their own, so they come out as `?`
+pub fn infer_type(query: &Query) -> Result<Term, String> {
+  let file = Query::file_of(query.outcome);
+  let mut delegate = DiagnosticsDelegate::init(file);
+  let mut checker =
+    TypeChecker::init(&query.outcome.symbol_table, file, &mut delegate);
+  let type_ = checker.synthesise(query.expression);
+  let type_ = checker.fill_in(&type_);
+  drop(checker);
+  if !delegate.reports.is_empty() {
+    return Err(query.report(delegate.reports))
+  }
+  return Ok(type_)
+}
+
+pub struct DeclarationInfo<'a> {
+  pub name: Symbol,
+  // doc comment, signature and clauses, laid out by the formatter.
+  // declarations with problems are not printed
+  pub rendered: Option<String>,
+  pub path: &'a Path,
+  pub position: LineColumn,
+  // other declarations this one uses, in order of first use
+  pub references: Vec<Symbol>,
+}
+
+// What is known about a declaration that query names
+pub fn describe<'a>(query: &Query<'a>) -> Result<DeclarationInfo<'a>, String> {
+  let outcome = query.outcome;
+  let ConcretisedNodeRepr::Reference { name, origination: Origin::GlobalScope } =
+    query.expression.kind
+  else {
+    return Err("Expected a name of a declaration".to_string())
+  };
+  let Some(decl) = outcome.symbol_table.retrieve_ref(&name) else {
+    return Err(format!("`{}` is not a declaration", name.materialise_name()))
+  };
+  let decl = unsafe { **decl };
+  let defined = decl.project_name();
+  let position = outcome.source_map.locate(defined.origin, defined.location).start;
+  let (rendered, references) = if decl.is_malformed {
+    (None, Vec::new())
+  } else {
+    let references =
+      trace_dependencies(&decl).into_iter().map(|dep| dep.name).collect();
+    (Some(render_declaration(&decl, DEFAULT_LINE_WIDTH)), references)
+  };
+  return Ok(DeclarationInfo {
+    name: defined, rendered,
+    path: &outcome.source_files[defined.origin.0 as usize].path,
+    position, references
+  })
+}
@@ -0,0 +1,285 @@
+
+use std::{path::Path, fmt::Write};
+
+use crate::expression_trees::{
+  raw_syntax_nodes::{SourceLocation, FileId},
+  better_nodes::Symbol,
+  source_map::{LineTable, LineColumn},
+};
+
+use super::diagnostics::{ProblemReport, Kind};
+
+
+// what renderer needs to know about a file.
+// index in a slice of these must match FileId
+#[derive(Clone, Copy)]
+pub struct SourceView<'a> {
+  pub path: &'a Path,
+  pub text: &'a str,
+  pub lines: &'a LineTable,
+}
+
+struct Label {
+  file: FileId,
+  span: SourceLocation,
+  message: String,
+  is_primary: bool,
+}
+
+pub fn render_report(
+  origin: FileId,
+  report: &ProblemReport,
+  sources: &[SourceView],
+  out: &mut String
+) {
+  let labels = collect_labels(origin, &report.kind, sources);
+
+  let _ = writeln!(out, "error: {}", report.kind);
+
+  let gutter_width = labels.iter().map(|label| {
+    let lines = sources[label.file.0 as usize].lines;
+    let LineColumn { line, .. } = lines.locate(label.span.primary_offset);
+    return line.to_string().len()
+  }).max().unwrap_or(0);
+  let pad = " ".repeat(gutter_width);
+
+  if labels.is_empty() {
+    // problems that arent about any one file have nowhere to point
+    let Some(source) = sources.get(origin.0 as usize) else { return };
+    let _ = writeln!(out, "{}--> {}", pad, source.path.display());
+    return;
+  }
+
+  // labels are grouped by file, in order of first appearance
+  let mut files = Vec::<FileId>::new();
+  for label in &labels {
+    if !files.contains(&label.file) { files.push(label.file) }
+  }
+
+  for (ix, file) in files.iter().enumerate() {
+    let SourceView { path, text, lines: table } = sources[file.0 as usize];
+    let locate = |offset| {
+      let LineColumn { line, column } = table.locate(offset);
+      return (line, column as usize)
+    };
+    let mut file_labels =
+      labels.iter().filter(|label| label.file == *file).collect::<Vec<_>>();
+    file_labels.sort_by_key(|label| label.span.primary_offset);
+
+    let lead = file_labels.iter().find(|label| label.is_primary)
+      .unwrap_or(&file_labels[0]);
+    let (line, col) = locate(lead.span.primary_offset);
+    let arrow = if ix == 0 { "-->" } else { ":::" };
+    let _ = writeln!(
+      out, "{}{} {}:{}:{}", pad, arrow, path.display(), line, col);
+    let _ = writeln!(out, "{} |", pad);
+
+    let mut lines =
+      file_labels.iter()
+      .map(|label| locate(label.span.primary_offset).0)
+      .collect::<Vec<_>>();
+    lines.dedup();
+
+    let mut previous_line = None;
+    for line in lines {
+      if let Some(previous) = previous_line {
+        if line > previous + 1 { let _ = writeln!(out, "..."); }
+      }
+      previous_line = Some(line);
+
+      let line_span = table.line_span(line);
+      let line_text = text[
+        line_span.primary_offset as usize .. line_span.secondary_offset as usize
+      ].trim_end_matches('\r').replace('\t', " ");
+      let _ = writeln!(
+        out, "{:>width$} | {}", line, line_text, width = gutter_width);
+
+      let on_this_line =
+        file_labels.iter()
+        .filter(|label| locate(label.span.primary_offset).0 == line)
+        .collect::<Vec<_>>();
+      let mut underline = String::new();
+      let mut taken = 0;
+      for label in &on_this_line {
+        let (_, col) = locate(label.span.primary_offset);
+        let width = span_width(text, label.span);
+        let start = col - 1;
+        if start < taken { continue; } // overlapping spans
+        underline.push_str(&" ".repeat(start - taken));
+        let mark = if label.is_primary { "^" } else { "-" };
+        underline.push_str(&mark.repeat(width));
+        taken = start + width;
+      }
+      // rightmost label gets its message inline,
+      // the rest go on separate rows under their spans
+      let (last, rest) = on_this_line.split_last().unwrap();
+      let _ = writeln!(out, "{} | {} {}", pad, underline, last.message);
+      for label in rest.iter().rev() {
+        let (_, col) = locate(label.span.primary_offset);
+        let _ = writeln!(
+          out, "{} | {}{}", pad, " ".repeat(col - 1), label.message);
+      }
+    }
+    let _ = writeln!(out, "{} |", pad);
+  }
+}
+
+fn collect_labels(
+  origin: FileId,
+  kind: &Kind,
+  sources: &[SourceView]
+) -> Vec<Label> {
+  let mut labels = Vec::new();
+  let mut at = |span: SourceLocation, message: &str, is_primary| {
+    let message = message.to_string();
+    labels.push(Label { file: origin, span, message, is_primary })
+  };
+  match kind {
+    Kind::DuplicateDecls { one, another } => {
+      labels.push(Label {
+        file: file_of_symbol(*another, sources).unwrap_or(origin),
+        span: another.location,
+        message: "redeclared here".to_string(),
+        is_primary: true
+      });
+      labels.push(Label {
+        file: file_of_symbol(*one, sources).unwrap_or(origin),
+        span: one.location,
+        message: "first declared here".to_string(),
+        is_primary: false
+      });
+    },
+    Kind::IrrelevantSymbol(symbol) => {
+      at(symbol.location, "not found in this scope", true)
+    },
+    Kind::InvalidDeconstructionPattern(symbol) => {
+      at(symbol.location, "not a constructor", true)
+    },
+    Kind::DuplicatedBinders(symbols) => {
+      for symbol in symbols {
+        at(symbol.location, "bound more than once", true)
+      }
+    },
+    Kind::DuplicatesInImpCtx(symbols) => {
+      for symbol in symbols {
+        at(symbol.location, "listed more than once", true)
+      }
+    },
+    Kind::UnusedItemsInImpCtx(symbols) => {
+      for symbol in symbols {
+        at(symbol.location, "never used", true)
+      }
+    },
+    Kind::IncorrectArity(loc) => {
+      at(*loc, "wrong number of arguments", true)
+    },
+    Kind::UnsedImpCtxAtTerminalNode(loc) => {
+      at(*loc, "cant use implicit context", true)
+    },
+    Kind::MismatchedType { type_expr, type_origin, term_expr } => {
+      at(*term_expr, "this expression", true);
+      labels.push(Label {
+        file: known_file(*type_origin, sources).unwrap_or(origin),
+        span: *type_expr,
+        message: "expected because of this type".to_string(),
+        is_primary: false
+      });
+    },
+    Kind::ArityMismatch { clause_loc, .. } => {
+      at(*clause_loc, "in this clause", true)
+    },
+    Kind::BinderShapeConflict { pattern_loc } => {
+      at(*pattern_loc, "conflicting pattern", true)
+    },
+    Kind::PatternMismatchesSignature { pattern_loc, type_loc } => {
+      at(*pattern_loc, "this pattern", true);
+      at(*type_loc, "does not fit this type", false);
+    },
+    Kind::NonfuncTypeInFuncPos(loc) => {
+      at(*loc, "not a function type", true)
+    },
+    Kind::MissingCases { name, .. } => {
+      at(name.location, "some inputs match no rule", true)
+    },
+    Kind::UnreachableRule(loc) => {
+      at(*loc, "never used", true)
+    },
+    Kind::MalformedSyntax(err) => {
+      let text = sources[origin.0 as usize].text;
+      let unexpected = err.project_unexpected_text(text);
+      let message =
+        if !unexpected.is_empty() { format!("found `{}`", unexpected) }
+        else if err.span.primary_offset as usize >= text.len() {
+          "found end of file".to_string() }
+        else { "found end of line".to_string() };
+      at(err.span, &message, true)
+    },
+    Kind::UnreadableFile(_) |
+    Kind::UnreadableDirectory(_) => (),
+    Kind::UnknownModule(module) => {
+      at(module.location, "no such module", true)
+    },
+    Kind::AmbiguousName { name, .. } => {
+      at(name.location, "ambiguous name", true)
+    },
+    Kind::ImportCycle(path) => {
+      // only the import that closes the cycle is in this file
+      if let Some(last) = path.last() {
+        at(last.location, "this import closes the cycle", true)
+      }
+    },
+    Kind::DefinitionCycle(path) => {
+      // steps can be in other files
+      for (ix, step) in path.iter().enumerate() {
+        let message =
+          if ix == 0 { "this definition never becomes a value".to_string() }
+          else { format!("unfolds into `{}` here", step.materialise_name()) };
+        labels.push(Label {
+          file: file_of_symbol(*step, sources).unwrap_or(origin),
+          span: step.location, message, is_primary: ix == 0
+        });
+      }
+    },
+    Kind::NonTerminatingRecursion { name, call_site } => {
+      at(*call_site, "this call", true);
+      at(name.location, "add `--| @partial` to allow this", false);
+    },
+    Kind::NonPositiveOccurrence { data, occurrence } => {
+      at(*occurrence, "left of an arrow or inside of another type", true);
+      at(data.location, "declared here", false);
+    },
+    Kind::ForeignConstructorType { type_loc, .. } => {
+      at(*type_loc, "this type", true)
+    },
+    Kind::ReductionOutOfFuel { type_expr, type_origin } => {
+      labels.push(Label {
+        file: known_file(*type_origin, sources).unwrap_or(origin),
+        span: *type_expr,
+        message: "this never finished reducing".to_string(),
+        is_primary: true
+      });
+    },
+    Kind::UninferableType(loc) => {
+      at(*loc, "nothing tells what type this should have", true)
+    },
+  }
+  return labels
+}
+
+fn file_of_symbol(symbol: Symbol, sources: &[SourceView]) -> Option<FileId> {
+  return known_file(symbol.origin, sources)
+}
+
+fn known_file(file: FileId, sources: &[SourceView]) -> Option<FileId> {
+  let known = (file.0 as usize) < sources.len();
+  return if known { Some(file) } else { None }
+}
+
+// spans that run over the end of the line are cut at it
+fn span_width(text: &str, span: SourceLocation) -> usize {
+  let start = (span.primary_offset as usize).min(text.len());
+  let end = (span.secondary_offset as usize).clamp(start, text.len());
+  let spanned = String::from_utf8_lossy(&text.as_bytes()[start .. end]);
+  let width = spanned.lines().next().unwrap_or("").chars().count();
+  return width.max(1)
+}
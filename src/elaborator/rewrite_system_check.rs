@@ -2,32 +2,40 @@
 
 use crate::expression_trees::{better_nodes::{
   Declaration, DeclKind,  ConcretisedNode, ConcretisedNodeRepr, ConcretisedPattern, ConcretisedPatternKind, Symbol,
+  ArrayPtr, Origin,
 }, raw_syntax_nodes::SourceLocation};
 use super::{
   diagnostics::{
     SomeDiagnosticsDelegate, ProblemReport, Kind
   },
-  // environment::PasteboardTable
+  coverage_analysis::check_coverage,
+  data_types::DataTypes,
 };
 
 
 
+// Checks that clauses of a mapping fit its signature, and that
+// names used on the right of them are bound. Gives false when they dont,
+// in which case rules cant be trusted by later stages.
+// Coverage is checked only for rules that are fine otherwise.
+// Constructors of data types must be known from every file
 pub fn check_rewrite_system(
   declaration: Declaration,
+  data_types: &DataTypes,
   diagnostic_delegate: &mut dyn SomeDiagnosticsDelegate
-) {
+) -> bool {
   if let DeclKind::WellScopedMapping {
-    given_type, rewrite_rules, ..
+    name, given_type, rewrite_rules
   } = declaration.repr {
     let type_expr = unsafe { *given_type };
-    if let ConcretisedNodeRepr::Arrow { .. } = type_expr.kind {}
+    let ConcretisedNodeRepr::Arrow { head, .. } = type_expr.kind
     else {
       let problem = ProblemReport {
         kind: Kind::NonfuncTypeInFuncPos(type_expr.location)
       };
       diagnostic_delegate.report_problem(problem);
-      return ;
-    }
+      return false;
+    };
 
     let arity = type_expr.count_arity() ;
 
@@ -35,37 +43,91 @@ pub fn check_rewrite_system(
     let lim = rewrite_rules.project_count();
 
     let mut rule_local_binders =
-      Vec::<(BindSynthTypeShape, Symbol)>::new();
+      Vec::<(Box<BindSynthTypeShape>, Symbol)>::new();
+    // conflicts make shapes meaningless for coverage
+    let mut delegate = CountingDelegate {
+      inner: diagnostic_delegate, count: 0 };
+    let diagnostic_delegate = &mut delegate;
+    let mut column_shapes = Vec::new();
+
+    // clauses with wrong number of patterns dont take part in the rest
+    let mut rows = Vec::new();
+    for row in 0 .. lim as usize {
+      let rule = unsafe { *ptr.add(row) };
+      let found = rule.matchers.project_count() as usize;
+      if found != arity {
+        let problem = ProblemReport {
+          kind: Kind::ArityMismatch {
+            expected: arity, found, clause_loc: rule.location }
+        };
+        diagnostic_delegate.report_problem(problem);
+        continue;
+      }
+      rows.push(rule);
+    }
 
-    // let mut rhs_ix = 0;
     for column in 0 .. arity {
+      let (_, arg_type) = unsafe { *head.get_ptr(column as u32) };
+      let signature = BindSynthTypeShape::of_type(arg_type, data_types);
       let mut root = BindSynthTypeShape::Variable;
 
-      for row in 0 .. lim as usize {
-        let rule = unsafe { *ptr.add(row) };
+      for rule in &rows {
         let matcher = unsafe {
           *rule.matchers.project_ptr().add(column)
         };
-
+        let mut own = BindSynthTypeShape::Variable;
         synthesise_shape_from_pattern(
-          matcher, diagnostic_delegate,
-          &mut rule_local_binders, &mut root,);
+          matcher, data_types, diagnostic_delegate,
+          &mut rule_local_binders, &mut own,);
+        if !own.agrees_with(&signature) {
+          let problem = ProblemReport {
+            kind: Kind::PatternMismatchesSignature {
+              pattern_loc: matcher.location, type_loc: arg_type.location }
+          };
+          diagnostic_delegate.report_problem(problem);
+          continue;
+        }
+        refine_shape(
+          &mut root, &own, matcher.location,
+          diagnostic_delegate);
       }
 
-      // let rhs = unsafe { *(*ptr.add(rhs_ix)).rhs };
-
-
-      // rhs_ix += 1;
+      let mut shape = root.resolve();
+      refine_shape(
+        &mut shape, &signature, arg_type.location,
+        diagnostic_delegate);
+      column_shapes.push(shape);
       rule_local_binders.clear();
     }
 
+    for rule in &rows {
+      let mut bound = Vec::new();
+      collect_binders(rule.matchers, &mut bound);
+      inspect_rhs(unsafe { *rule.rhs }, &mut bound, diagnostic_delegate);
+    }
 
-    // need to check against signature as well
+    let is_well_formed = diagnostic_delegate.count == 0;
+    if is_well_formed {
+      check_coverage(
+        name, rewrite_rules, &column_shapes, data_types, diagnostic_delegate.inner);
+    }
+    return is_well_formed
   } else {
     panic!("Unexpectedly recieved nonfunction object")
   }
 }
 
+struct CountingDelegate<'a> {
+  inner: &'a mut dyn SomeDiagnosticsDelegate,
+  count: usize,
+}
+impl SomeDiagnosticsDelegate for CountingDelegate<'_> {
+  fn report_problem(&mut self, report: ProblemReport) {
+    self.count += 1;
+    self.inner.report_problem(report)
+  }
+}
+
 impl ConcretisedNode {
   fn count_arity(&self) -> usize {
     let mut arity = 0;
@@ -80,8 +142,9 @@ impl ConcretisedNode {
 
 fn synthesise_shape_from_pattern(
   pattern: ConcretisedPattern,
+  data_types: &DataTypes,
   diagnostic_delegate: &mut dyn SomeDiagnosticsDelegate,
-  binders: &mut Vec<(BindSynthTypeShape, Symbol)>,
+  binders: &mut Vec<(Box<BindSynthTypeShape>, Symbol)>,
   type_shape: &mut BindSynthTypeShape,
 ) {
   let ConcretisedPattern { repr, location } = pattern;
@@ -92,14 +155,14 @@ fn synthesise_shape_from_pattern(
       let sing = BindSynthTypeShape::Singleton;
       refine_shape(
         type_shape, &sing,
-        location, diagnostic_delegate, binders)
+        location, diagnostic_delegate)
     },
     ConcretisedPatternKind::Left(v) => {
       let mut l = BindSynthTypeShape::Variable;
 
       let v = unsafe { *v };
       synthesise_shape_from_pattern(
-        v, diagnostic_delegate,
+        v, data_types, diagnostic_delegate,
         binders, &mut l);
 
       let either = BindSynthTypeShape::Either(
@@ -107,14 +170,14 @@ fn synthesise_shape_from_pattern(
         Box::new(BindSynthTypeShape::Variable));
 
 
-      refine_shape(type_shape, &either, v.location, diagnostic_delegate, binders);
+      refine_shape(type_shape, &either, v.location, diagnostic_delegate);
     },
     ConcretisedPatternKind::Right(v) => {
       let mut r = BindSynthTypeShape::Variable;
 
       let v = unsafe { *v };
       synthesise_shape_from_pattern(
-        v, diagnostic_delegate,
+        v, data_types, diagnostic_delegate,
         binders, &mut r);
 
       let either = BindSynthTypeShape::Either(
@@ -122,41 +185,60 @@ fn synthesise_shape_from_pattern(
         Box::new(r));
 
 
-      refine_shape(type_shape, &either, v.location, diagnostic_delegate, binders);
+      refine_shape(type_shape, &either, v.location, diagnostic_delegate);
 
     },
     ConcretisedPatternKind::Tuple(l, r) => {
       let l = unsafe { *l };
       let mut lt = BindSynthTypeShape::Variable;
 
-      synthesise_shape_from_pattern(l, diagnostic_delegate, binders, &mut lt);
+      synthesise_shape_from_pattern(l, data_types, diagnostic_delegate, binders, &mut lt);
 
       let r = unsafe { *r };
       let mut rt = BindSynthTypeShape::Variable;
 
-      synthesise_shape_from_pattern(r, diagnostic_delegate, binders, &mut rt);
+      synthesise_shape_from_pattern(r, data_types, diagnostic_delegate, binders, &mut rt);
 
       let pair = BindSynthTypeShape::Pair(
         Box::new(lt),
         Box::new(rt));
 
-      refine_shape(type_shape, &pair, location, diagnostic_delegate, binders)
+      refine_shape(type_shape, &pair, location, diagnostic_delegate)
     },
     ConcretisedPatternKind::VarBinding(symbol) => {
 
-      let fresh = BindSynthTypeShape::Variable;
+      // boxed, so that pointer stays valid when the vector grows
+      let mut fresh = Box::new(BindSynthTypeShape::Variable);
+      let fresh_ptr = &mut *fresh as *mut BindSynthTypeShape;
       binders.push((fresh, symbol));
 
+      let sh = BindSynthTypeShape::BinderRef(fresh_ptr);
 
-      let last_item_ptr = unsafe {
-        binders.as_mut_ptr().add(binders.len() - 1)
-        .cast::<BindSynthTypeShape>()
-      };
-      let sh = BindSynthTypeShape::BinderRef(last_item_ptr);
-
-      refine_shape(type_shape, &sh, location, diagnostic_delegate, binders);
+      refine_shape(type_shape, &sh, location, diagnostic_delegate);
 
     },
+    ConcretisedPatternKind::Constructor { name, arguments } => {
+      // constructors of malformed data types are left alone,
+      // their declaration has already been reported
+      let Some(ctor) = data_types.find_constructor(name) else { return };
+      if arguments.project_count() as usize != ctor.fields.len() {
+        let problem = ProblemReport {
+          kind: Kind::IncorrectArity(location)
+        };
+        diagnostic_delegate.report_problem(problem);
+        return
+      }
+      // arguments start from what their types tell
+      for (ix, field) in ctor.fields.iter().enumerate() {
+        let argument = unsafe { *arguments.get_ptr(ix as u32) };
+        let mut shape = BindSynthTypeShape::of_type(*field, data_types);
+        synthesise_shape_from_pattern(
+          argument, data_types, diagnostic_delegate, binders, &mut shape);
+      }
+      let owner = data_types.owner_of(name).unwrap();
+      let data = BindSynthTypeShape::Data(owner, None);
+      refine_shape(type_shape, &data, location, diagnostic_delegate)
+    },
   }
 }
 
@@ -169,10 +251,85 @@ pub enum BindSynthTypeShape {
   Either(Box<Self>, Box<Self>),
   Singleton,
   Sigma(Vec<Self>, Box<Self>),
-  Star
+  Star,
+  // user declared data type, and its type as written, if it is known
+  Data(Symbol, Option<ConcretisedNode>),
 }
 
 impl BindSynthTypeShape {
+  // same shape, with binders replaced by what is known about them
+  pub fn resolve(&self) -> Self {
+    let sub = |shape: &Self| Box::new(shape.resolve());
+    match self {
+      BindSynthTypeShape::BinderRef(ptr) => unsafe { (**ptr).resolve() },
+      BindSynthTypeShape::Function(head, spine) => {
+        BindSynthTypeShape::Function(head.iter().map(Self::resolve).collect(), sub(spine))
+      },
+      BindSynthTypeShape::Sigma(head, spine) => {
+        BindSynthTypeShape::Sigma(head.iter().map(Self::resolve).collect(), sub(spine))
+      },
+      BindSynthTypeShape::Pair(l, r) => BindSynthTypeShape::Pair(sub(l), sub(r)),
+      BindSynthTypeShape::Either(l, r) => BindSynthTypeShape::Either(sub(l), sub(r)),
+      BindSynthTypeShape::Variable |
+      BindSynthTypeShape::Singleton |
+      BindSynthTypeShape::Star |
+      BindSynthTypeShape::Data(..) => self.clone(),
+    }
+  }
+  // what can be told about values of a type from its syntax.
+  // aliases are not known at this point, so they tell nothing
+  pub fn of_type(type_: ConcretisedNode, data_types: &DataTypes) -> Self {
+    let sub = |node: *mut ConcretisedNode| {
+      Box::new(Self::of_type(unsafe { *node }, data_types))
+    };
+    let each = |head: ArrayPtr<(Option<Symbol>, ConcretisedNode)>| {
+      (0 .. head.project_count())
+        .map(|ix| Self::of_type(unsafe { (*head.get_ptr(ix)).1 }, data_types))
+        .collect::<Vec<_>>()
+    };
+    match type_.kind {
+      ConcretisedNodeRepr::Reference { name, origination: Origin::GlobalScope } |
+      ConcretisedNodeRepr::App { root: name, origination: Origin::GlobalScope, .. }
+      if data_types.constructors_of(name).is_some() => {
+        BindSynthTypeShape::Data(name, Some(type_))
+      },
+      ConcretisedNodeRepr::Singleton => BindSynthTypeShape::Singleton,
+      ConcretisedNodeRepr::Star => BindSynthTypeShape::Star,
+      ConcretisedNodeRepr::Either(l, r) => BindSynthTypeShape::Either(sub(l), sub(r)),
+      ConcretisedNodeRepr::Pair(l, r) => BindSynthTypeShape::Pair(sub(l), sub(r)),
+      ConcretisedNodeRepr::Arrow { head, spine, .. } => {
+        BindSynthTypeShape::Function(each(head), sub(spine))
+      },
+      ConcretisedNodeRepr::Sigma { head, spine } => {
+        BindSynthTypeShape::Sigma(each(head), sub(spine))
+      },
+      _ => BindSynthTypeShape::Variable,
+    }
+  }
+  // whether some value can have both shapes
+  pub fn agrees_with(&self, other: &Self) -> bool {
+    let all = |l: &[Self], r: &[Self]| {
+      l.len() == r.len() && l.iter().zip(r).all(|(l, r)| l.agrees_with(r))
+    };
+    match (self, other) {
+      (BindSynthTypeShape::BinderRef(ptr), _) => unsafe { (**ptr).agrees_with(other) },
+      (_, BindSynthTypeShape::BinderRef(ptr)) => unsafe { self.agrees_with(&**ptr) },
+      (BindSynthTypeShape::Variable, _) |
+      (_, BindSynthTypeShape::Variable) => true,
+      (BindSynthTypeShape::Singleton, BindSynthTypeShape::Singleton) |
+      (BindSynthTypeShape::Star, BindSynthTypeShape::Star) => true,
+      (BindSynthTypeShape::Data(a, _), BindSynthTypeShape::Data(b, _)) => a == b,
+      (BindSynthTypeShape::Pair(a, b), BindSynthTypeShape::Pair(c, d)) |
+      (BindSynthTypeShape::Either(a, b), BindSynthTypeShape::Either(c, d)) => {
+        a.agrees_with(c) && b.agrees_with(d)
+      },
+      (BindSynthTypeShape::Function(a, b), BindSynthTypeShape::Function(c, d)) |
+      (BindSynthTypeShape::Sigma(a, b), BindSynthTypeShape::Sigma(c, d)) => {
+        all(a, c) && b.agrees_with(d)
+      },
+      _ => false,
+    }
+  }
   pub fn dump(&self) {
     match self {
       BindSynthTypeShape::Variable => println!("?",),
@@ -194,7 +351,10 @@ impl BindSynthTypeShape {
       BindSynthTypeShape::Star => {
         println!("*")
       },
-      BindSynthTypeShape::BinderRef(_) => todo!(),
+      BindSynthTypeShape::Data(name, _) => {
+        println!("{}", name.materialise_name())
+      },
+      BindSynthTypeShape::BinderRef(ptr) => unsafe { (**ptr).dump() },
     }
   }
 }
@@ -204,23 +364,34 @@ fn refine_shape(
   rhs: &BindSynthTypeShape,
   pattern_loc: SourceLocation,
   diagnostic_delegate: &mut dyn SomeDiagnosticsDelegate,
-  binders: &mut Vec<(BindSynthTypeShape, Symbol)>
 ) {
   match (lhs, rhs) {
     (BindSynthTypeShape::BinderRef(ptr), rhs) => {
       unsafe {
-        refine_shape(&mut **ptr, rhs, pattern_loc, diagnostic_delegate, binders)
+        refine_shape(&mut **ptr, rhs, pattern_loc, diagnostic_delegate)
       }
     }
     (lhs@BindSynthTypeShape::Variable, k) => {
       *lhs = k.clone();
     },
     (_, BindSynthTypeShape::Variable) => (),
+    (lhs, BindSynthTypeShape::BinderRef(ptr)) => unsafe {
+      // binder learns what is known here, or tells what it knows
+      if let BindSynthTypeShape::Variable = **ptr { **ptr = lhs.clone(); return }
+      let known = (**ptr).clone();
+      refine_shape(lhs, &known, pattern_loc, diagnostic_delegate)
+    },
+    (BindSynthTypeShape::Singleton, BindSynthTypeShape::Singleton) |
+    (BindSynthTypeShape::Star, BindSynthTypeShape::Star) => (),
+    (BindSynthTypeShape::Data(a, written), BindSynthTypeShape::Data(b, other))
+    if a == b => {
+      if written.is_none() { *written = *other }
+    },
 
     (BindSynthTypeShape::Pair(a, b), BindSynthTypeShape::Pair(c, d)) |
     (BindSynthTypeShape::Either(a, b), BindSynthTypeShape::Either(c, d)) => {
-      refine_shape(a, c, pattern_loc, diagnostic_delegate, binders);
-      refine_shape(b, d, pattern_loc, diagnostic_delegate, binders);
+      refine_shape(a, c, pattern_loc, diagnostic_delegate);
+      refine_shape(b, d, pattern_loc, diagnostic_delegate);
     },
     (BindSynthTypeShape::Sigma(l_head, l_spine), BindSynthTypeShape::Sigma(r_head, r_spine)) |
     (BindSynthTypeShape::Function(l_head, l_spine),
@@ -235,9 +406,9 @@ fn refine_shape(
       for i in 0 .. l_head.len() {
         let rhs = l_head.get_mut(i).unwrap();
         let lhs = r_head.get(i).unwrap();
-        refine_shape(rhs, lhs, pattern_loc, diagnostic_delegate, binders);
+        refine_shape(rhs, lhs, pattern_loc, diagnostic_delegate);
       }
-      refine_shape(l_spine, &r_spine, pattern_loc, diagnostic_delegate, binders);
+      refine_shape(l_spine, r_spine, pattern_loc, diagnostic_delegate);
     },
     _ => {
       let problem = ProblemReport {
@@ -251,66 +422,97 @@ fn refine_shape(
 }
 
 
+fn collect_binders(
+  matchers: ArrayPtr<ConcretisedPattern>, bound: &mut Vec<Symbol>
+) {
+  for ix in 0 .. matchers.project_count() {
+    let pattern = unsafe { *matchers.get_ptr(ix) };
+    collect_pattern_binders(pattern, bound)
+  }
+}
 
+fn collect_pattern_binders(pattern: ConcretisedPattern, bound: &mut Vec<Symbol>) {
+  match pattern.repr {
+    ConcretisedPatternKind::VarBinding(symbol) => bound.push(symbol),
+    ConcretisedPatternKind::Left(v) |
+    ConcretisedPatternKind::Right(v) => {
+      collect_pattern_binders(unsafe { *v }, bound)
+    },
+    ConcretisedPatternKind::Tuple(l, r) => {
+      collect_pattern_binders(unsafe { *l }, bound);
+      collect_pattern_binders(unsafe { *r }, bound)
+    },
+    ConcretisedPatternKind::Constructor { arguments, .. } => {
+      for ix in 0 .. arguments.project_count() {
+        collect_pattern_binders(unsafe { *arguments.get_ptr(ix) }, bound)
+      }
+    },
+    ConcretisedPatternKind::Wildcard |
+    ConcretisedPatternKind::Pt => (),
+  }
+}
 
-// fn inspect_rhs(
-//   rhs: ConcretisedNode,
-//   binders: &mut Vec<(BindSynthTypeShape, Symbol)>,
-//   diagnostic_delegate: &mut dyn SomeDiagnosticsDelegate,
-// ) {
-
-
-// }
-
-// fn inspect_descend(
-//   rhs: ConcretisedNode,
-//   decls: &PasteboardTable<Symbol, Declaration>
-// ) {
-//   match rhs.kind {
-//     ConcretisedNodeRepr::Star |
-//     ConcretisedNodeRepr::Reference { .. } |
-//     ConcretisedNodeRepr::Void |
-//     ConcretisedNodeRepr::Singleton |
-//     ConcretisedNodeRepr::Pt => (),
-
-//     ConcretisedNodeRepr::App { root, arguments, origination: is_global } => {
-//       let item = decls.retrieve_ref(&root);
-//       match item {
-//         Some(item) => {
-
-//         },
-//         None => panic!(),
-//       }
-//     },
-//     ConcretisedNodeRepr::Wit { premises, conclusion } => {
-
-//     },
-//     ConcretisedNodeRepr::Sigma { head, spine } => {
-
-//     },
-//     ConcretisedNodeRepr::Arrow { head, spine, performs_introspection } => {
-
-//     },
-//     ConcretisedNodeRepr::Lam { rewrite_rules } => {
-
-//     },
-//     ConcretisedNodeRepr::Either(l, r) |
-//     ConcretisedNodeRepr::Pair(l, r) => {
-
-
-
-//     },
-//     ConcretisedNodeRepr::Tuple(l, r) => {
-//       let l = unsafe { *l };
-//       inspect_descend(l, decls);
-//       let r = unsafe { *r };
-//       inspect_descend(r, decls);
-
-//     },
-//     ConcretisedNodeRepr::Left(v) |
-//     ConcretisedNodeRepr::Right(v) => {
-//       let v = unsafe { *v };
-//       inspect_descend(v, decls);
-//     },
-//   }
-// }
\ No newline at end of file
+// every name that refers to a pattern has to be bound
+// by the clause, or by a clause of lambda around it
+fn inspect_rhs(
+  rhs: ConcretisedNode,
+  binders: &mut Vec<Symbol>,
+  diagnostic_delegate: &mut dyn SomeDiagnosticsDelegate,
+) {
+  let check = |name: Symbol, origination: Origin,
+                   diagnostic_delegate: &mut dyn SomeDiagnosticsDelegate| {
+    if let Origin::PatternBinding = origination {
+      if binders.contains(&name) { return }
+      let problem = ProblemReport { kind: Kind::IrrelevantSymbol(name) };
+      diagnostic_delegate.report_problem(problem);
+    }
+  };
+  match rhs.kind {
+    ConcretisedNodeRepr::Reference { name, origination } => {
+      check(name, origination, diagnostic_delegate)
+    },
+    ConcretisedNodeRepr::App { root, arguments, origination } => {
+      check(root, origination, diagnostic_delegate);
+      for ix in 0 .. arguments.project_count() {
+        inspect_rhs(unsafe { *arguments.get_ptr(ix) }, binders, diagnostic_delegate)
+      }
+    },
+    ConcretisedNodeRepr::Wit { premises, conclusion } => {
+      for ix in 0 .. premises.project_count() {
+        inspect_rhs(unsafe { *premises.get_ptr(ix) }, binders, diagnostic_delegate)
+      }
+      inspect_rhs(unsafe { *conclusion }, binders, diagnostic_delegate)
+    },
+    ConcretisedNodeRepr::Sigma { head, spine } |
+    ConcretisedNodeRepr::Arrow { head, spine, .. } => {
+      for ix in 0 .. head.project_count() {
+        let (_, type_) = unsafe { *head.get_ptr(ix) };
+        inspect_rhs(type_, binders, diagnostic_delegate)
+      }
+      inspect_rhs(unsafe { *spine }, binders, diagnostic_delegate)
+    },
+    ConcretisedNodeRepr::Lam { rewrite_rules } => {
+      for ix in 0 .. rewrite_rules.project_count() {
+        let rule = unsafe { *rewrite_rules.get_ptr(ix) };
+        let scope = binders.len();
+        collect_binders(rule.matchers, binders);
+        inspect_rhs(unsafe { *rule.rhs }, binders, diagnostic_delegate);
+        binders.truncate(scope);
+      }
+    },
+    ConcretisedNodeRepr::Either(l, r) |
+    ConcretisedNodeRepr::Pair(l, r) |
+    ConcretisedNodeRepr::Tuple(l, r) => {
+      inspect_rhs(unsafe { *l }, binders, diagnostic_delegate);
+      inspect_rhs(unsafe { *r }, binders, diagnostic_delegate)
+    },
+    ConcretisedNodeRepr::Left(v) |
+    ConcretisedNodeRepr::Right(v) => {
+      inspect_rhs(unsafe { *v }, binders, diagnostic_delegate)
+    },
+    ConcretisedNodeRepr::Star |
+    ConcretisedNodeRepr::Void |
+    ConcretisedNodeRepr::Singleton |
+    ConcretisedNodeRepr::Pt => (),
+  }
+}
@@ -6,7 +6,7 @@ use crate::{
       Declaration, DeclKind, RawNode,
       RawNodeRepr, ConcretisedNode, ConcretisedNodeRepr, Symbol, RawRewriteRule,
       RawPattern, ConcretisedPattern, ConcretisedRewriteRule, RawPatternKind,
-      ConcretisedPatternKind, Origin,} },};
+      ConcretisedPatternKind, Origin, ConcretisedConstructor, ArrayPtr,} },};
 
 use super::{
   diagnostics::{
@@ -16,11 +16,40 @@ use super::{
 };
 
 
+// What a name written in some place refers to
+#[derive(Debug, Clone)]
+pub enum NameResolution {
+  // canonical name of a declaration
+  Found(Symbol),
+  Missing,
+  Ambiguous(Vec<Symbol>),
+  // qualifier of a name is not a module in scope
+  UnknownModule(Symbol),
+}
+
+pub trait NameScope {
+  fn resolve(&self, name: Symbol) -> NameResolution;
+  // takes canonical name
+  fn is_constructor(&self, name: Symbol) -> bool;
+}
+
+// flat scope where names are taken as written.
+// it knows of no data types
+impl NameScope for PresenseSet<Symbol> {
+  fn resolve(&self, name: Symbol) -> NameResolution {
+    if self.check_out(&name) { return NameResolution::Found(name) }
+    return NameResolution::Missing
+  }
+  fn is_constructor(&self, _: Symbol) -> bool {
+    return false
+  }
+}
+
 
 pub fn concretise_declaration(
   given_decl: &mut Declaration,
   diagnostic_delegate: &mut dyn SomeDiagnosticsDelegate,
-  global_symbols: &PresenseSet<Symbol>
+  global_symbols: &dyn NameScope
 ) {
   match given_decl.repr {
     DeclKind::RawMapping { name, given_type: type_, rewrite_rules } => {
@@ -75,14 +104,46 @@ pub fn concretise_declaration(
         DeclKind::WellScopedDefinition { name, given_type: saned_type, value: saned_value };
       given_decl.repr = saned_def;
     },
+    DeclKind::RawData { name, given_type: type_, constructors } => {
+      concretise_expr(
+        type_, diagnostic_delegate,
+        global_symbols, &HashSet::new(), &HashSet::new());
+      let saned_type = type_.cast::<ConcretisedNode>();
+
+      for i in 0 .. constructors.project_count() {
+        let ctor = unsafe { *constructors.get_ptr(i) };
+        concretise_expr(
+          ctor.given_type, diagnostic_delegate,
+          global_symbols, &HashSet::new(), &HashSet::new());
+      }
+      let checked_ctors =
+        constructors.cast::<ConcretisedConstructor>();
+
+      given_decl.repr = DeclKind::WellScopedData {
+        name, given_type: saned_type, constructors: checked_ctors
+      };
+    },
     _ => panic!("No need to sanitise things twice")
   }
 }
 
+// for expressions that dont belong to any declaration,
+// like ones typed in by hand
+pub fn concretise_expression(
+  expr_ptr: *mut RawNode,
+  diagnostic_delegate: &mut dyn SomeDiagnosticsDelegate,
+  global_symbols: &dyn NameScope
+) -> ConcretisedNode {
+  concretise_expr(
+    expr_ptr, diagnostic_delegate,
+    global_symbols, &HashSet::new(), &HashSet::new());
+  return unsafe { *expr_ptr.cast::<ConcretisedNode>() }
+}
+
 fn concretise_expr(
   expr_ptr: *mut RawNode,
   diagnostic_delegate: &mut dyn SomeDiagnosticsDelegate,
-  global_symbols: &PresenseSet<Symbol>,
+  global_symbols: &dyn NameScope,
   context_symbols: &HashSet<Symbol>,
   pattern_binders: &HashSet<Symbol>
 ) {
@@ -158,18 +219,14 @@ fn concretise_expr(
             name: symbol, origination: Origin::ContextBinding
           }
         },
-        _ if global_symbols.check_out(&symbol) => {
+        _ => {
+          let Some(name) =
+            resolve_global(symbol, global_symbols, diagnostic_delegate)
+          else { return () };
           checked_kind = ConcretisedNodeRepr::Reference {
             origination: Origin::GlobalScope,
-            name: symbol
+            name
           }
-        },
-        _ => {
-          let problem = ProblemReport {
-            kind: Kind::IrrelevantSymbol(symbol)
-          };
-          diagnostic_delegate.report_problem(problem);
-          return ()
         }
       }
     },
@@ -186,6 +243,18 @@ fn concretise_expr(
       let checked_args =
         arguments.cast::<ConcretisedNode>();
 
+      let builtin_arity = match root.materialise_name() {
+        "Either" | "Pair" | "two" => Some(2),
+        "inl" | "inr" => Some(1),
+        _ => None
+      };
+      if builtin_arity.is_some_and(|arity| arity != lim) {
+        let problem = ProblemReport {
+          kind: Kind::IncorrectArity(location)
+        };
+        diagnostic_delegate.report_problem(problem);
+        return
+      }
       match root.materialise_name() {
         "Either" => {
           checked_kind = ConcretisedNodeRepr::Either(
@@ -199,8 +268,21 @@ fn concretise_expr(
             checked_args.get_ptr(1)
           )
         },
+        "two" => {
+          checked_kind = ConcretisedNodeRepr::Tuple(
+            checked_args.get_ptr(0),
+            checked_args.get_ptr(1)
+          )
+        },
+        "inl" => {
+          checked_kind = ConcretisedNodeRepr::Left(checked_args.get_ptr(0))
+        },
+        "inr" => {
+          checked_kind = ConcretisedNodeRepr::Right(checked_args.get_ptr(0))
+        },
         _ => {
           let origination: Origin;
+          let mut root = root;
           match () {
             _ if pattern_binders.contains(&root) => {
               origination = Origin::PatternBinding
@@ -208,15 +290,12 @@ fn concretise_expr(
             _ if context_symbols.contains(&root) => {
               origination = Origin::ContextBinding
             },
-            _ if global_symbols.check_out(&root) => {
-              origination = Origin::GlobalScope;
-            },
             _ => {
-              let problem = ProblemReport {
-                kind: Kind::IrrelevantSymbol(root)
-              };
-              diagnostic_delegate.report_problem(problem);
-              return
+              let Some(name) =
+                resolve_global(root, global_symbols, diagnostic_delegate)
+              else { return };
+              root = name;
+              origination = Origin::GlobalScope;
             }
           }
           checked_kind = ConcretisedNodeRepr::App {
@@ -322,10 +401,27 @@ fn concretise_expr(
 
 }
 
+// global references are rewritten to canonical names of what they refer to
+fn resolve_global(
+  symbol: Symbol,
+  global_symbols: &dyn NameScope,
+  diagnostic_delegate: &mut dyn SomeDiagnosticsDelegate,
+) -> Option<Symbol> {
+  let kind = match global_symbols.resolve(symbol) {
+    NameResolution::Found(name) => return Some(name),
+    NameResolution::Missing => Kind::IrrelevantSymbol(symbol),
+    NameResolution::Ambiguous(candidates) =>
+      Kind::AmbiguousName { name: symbol, candidates },
+    NameResolution::UnknownModule(module) => Kind::UnknownModule(module),
+  };
+  diagnostic_delegate.report_problem(ProblemReport { kind });
+  return None
+}
+
 fn concretise_rewrite_rule(
   rule: *mut RawRewriteRule,
   diagnostic_delegate: &mut dyn SomeDiagnosticsDelegate,
-  global_symbols: &PresenseSet<Symbol>,
+  global_symbols: &dyn NameScope,
   context_symbols: &HashSet<Symbol>,
   pattern_binders: &HashSet<Symbol>,
 ) {
@@ -339,7 +435,7 @@ fn concretise_rewrite_rule(
   for i in 0 .. count as usize{
     let ptr = unsafe { ptr.add(i) };
     concretise_pattern(
-      ptr, diagnostic_delegate,
+      ptr, diagnostic_delegate, global_symbols,
       &mut rule_local_binders, &mut duplicated_binders)
   }
   if !duplicated_binders.is_empty() {
@@ -348,8 +444,7 @@ fn concretise_rewrite_rule(
     };
     diagnostic_delegate.report_problem(problem)
   }
-  let checked_matchers =
-    matchers.cast::<ConcretisedPattern>();
+  let checked_matchers = pack_patterns(matchers);
 
   concretise_expr(
     lhs, diagnostic_delegate,
@@ -365,9 +460,32 @@ fn concretise_rewrite_rule(
   unsafe { *rule.cast() = checked_rule }
 }
 
+// concretised patterns are smaller than raw ones,
+// so they have to be packed before array can be read with their stride
+fn pack_patterns(patterns: ArrayPtr<RawPattern>) -> ArrayPtr<ConcretisedPattern> {
+  let ptr = patterns.project_ptr();
+  let packed = ptr.cast::<ConcretisedPattern>();
+  for i in 0 .. patterns.project_count() as usize {
+    unsafe {
+      let pattern = ptr.add(i).cast::<ConcretisedPattern>().read();
+      packed.add(i).write(pattern);
+    }
+  }
+  return patterns.cast::<ConcretisedPattern>()
+}
+
+// canonical name of a pattern head, if it is a constructor in scope.
+// other names in patterns bind variables
+fn resolve_constructor(symbol: Symbol, global_symbols: &dyn NameScope) -> Option<Symbol> {
+  let NameResolution::Found(name) = global_symbols.resolve(symbol) else { return None };
+  if !global_symbols.is_constructor(name) { return None }
+  return Some(name)
+}
+
 fn concretise_pattern(
   pattern: *mut RawPattern,
   diagnostic_delegate: &mut dyn SomeDiagnosticsDelegate,
+  global_symbols: &dyn NameScope,
   local_symbols: &mut HashSet<Symbol>,
   duplicated_binders: &mut HashSet<Symbol>,
 ) {
@@ -386,7 +504,7 @@ fn concretise_pattern(
       for i in 0 .. lim as usize{
         let ptr = unsafe { ptr.add(i) };
         concretise_pattern(
-          ptr, diagnostic_delegate,
+          ptr, diagnostic_delegate, global_symbols,
           local_symbols, duplicated_binders);
       }
 
@@ -427,20 +545,29 @@ fn concretise_pattern(
           checked_repr = ConcretisedPatternKind::Right(r.cast());
         },
         _ => {
-          let problem = ProblemReport {
-            kind: Kind::InvalidDeconstructionPattern(head)
+          let Some(name) = resolve_constructor(head, global_symbols) else {
+            let problem = ProblemReport {
+              kind: Kind::InvalidDeconstructionPattern(head)
+            };
+            diagnostic_delegate.report_problem(problem);
+            return
           };
-          diagnostic_delegate.report_problem(problem);
-          return
+          checked_repr = ConcretisedPatternKind::Constructor {
+            name, arguments: pack_patterns(subexpressions) }
         }
       }
     },
     RawPatternKind::Mono(symbol) => {
       let ref_ = symbol.materialise_name();
-      match ref_ {
-        "pt" => {
+      let constructor = resolve_constructor(symbol, global_symbols);
+      match (ref_, constructor) {
+        ("pt", _) => {
           checked_repr = ConcretisedPatternKind::Pt
         },
+        (_, Some(name)) => {
+          checked_repr = ConcretisedPatternKind::Constructor {
+            name, arguments: ArrayPtr::init(std::ptr::null_mut(), 0) }
+        },
         _ => {
           let new = local_symbols.insert(symbol);
           if !new {
@@ -0,0 +1,496 @@
+use std::{
+  fmt::{Display, Formatter, self},
+  sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::expression_trees::{
+  better_nodes::{
+    Symbol, ConcretisedNode, ConcretisedNodeRepr, ConcretisedRewriteRule,
+    ArrayPtr, Origin},
+  raw_syntax_nodes::{SourceLocation, FileId},
+};
+
+
+// Owned form of expressions that passes after scope analysis
+// can take apart, substitute into and compare.
+// Trees made by the parser are shared and must stay as written,
+// so everything that needs to build new expressions works on these.
+#[derive(Debug, Clone)]
+pub struct Term {
+  pub kind: TermKind,
+  // where this term, or the one it was computed from, was written
+  pub location: SourceLocation,
+  // file of the location, when it is not the one being worked on
+  pub origin: Option<FileId>,
+}
+
+#[derive(Debug, Clone)]
+pub enum TermKind {
+  Star, Void, Singleton, Pt,
+  // something that cant be taken apart, applied to arguments
+  Neutral {
+    head: Head,
+    arguments: Vec<Term>,
+  },
+  // application of something that is not a name,
+  // which appears when a binder is substituted with a lambda
+  Apply {
+    function: Box<Term>,
+    arguments: Vec<Term>,
+  },
+  // implicit context in front of a term.
+  // binders are locals, or fresh variables that replaced them
+  Forall {
+    context: Vec<(Head, Term)>,
+    body: Box<Term>,
+  },
+  Pi {
+    head: Vec<(Option<Head>, Term)>,
+    spine: Box<Term>,
+  },
+  Sigma {
+    head: Vec<(Option<Head>, Term)>,
+    spine: Box<Term>,
+  },
+  Wit {
+    premises: Vec<Term>,
+    conclusion: Box<Term>,
+  },
+  // rules keep referring to the tree they were written in.
+  // values of free binders in them are in the environment
+  Lam {
+    rewrite_rules: ArrayPtr<ConcretisedRewriteRule>,
+    environment: Vec<(Symbol, Term)>,
+  },
+  Pair(Box<Term>, Box<Term>),
+  Either(Box<Term>, Box<Term>),
+  Tuple(Box<Term>, Box<Term>),
+  Left(Box<Term>),
+  Right(Box<Term>),
+  // stands for something that already failed to check.
+  // it is the same as nothing, but comparisons with it
+  // arent reported, so that one mistake is reported once
+  Unknown,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Head {
+  Global(Symbol),
+  Local(Symbol),
+  // made up variable that cant clash with anything written
+  Fresh(u32),
+  // placeholder that unification can fill in
+  Hole(u32),
+}
+impl PartialEq for Head {
+  fn eq(&self, other: &Self) -> bool {
+    match (self, other) {
+      (Head::Global(a), Head::Global(b)) |
+      (Head::Local(a), Head::Local(b)) => a == b,
+      (Head::Fresh(a), Head::Fresh(b)) |
+      (Head::Hole(a), Head::Hole(b)) => a == b,
+      _ => false
+    }
+  }
+}
+
+impl Term {
+  pub fn init(kind: TermKind, location: SourceLocation) -> Self {
+    Self { kind, location, origin: None }
+  }
+  // new term that stands where another one was written
+  pub fn init_at(kind: TermKind, place: &Term) -> Self {
+    Self { kind, location: place.location, origin: place.origin }
+  }
+  pub fn unknown(location: SourceLocation) -> Self {
+    Self::init(TermKind::Unknown, location)
+  }
+  pub fn star(location: SourceLocation) -> Self {
+    Self::init(TermKind::Star, location)
+  }
+  pub fn variable(head: Head, location: SourceLocation) -> Self {
+    Self::init(TermKind::Neutral { head, arguments: Vec::new() }, location)
+  }
+  pub fn is_unknown(&self) -> bool {
+    matches!(self.kind, TermKind::Unknown)
+  }
+
+  pub fn of_node(node: ConcretisedNode) -> Self {
+    let location = node.location;
+    if let Some(ctx) = node.implicit_context {
+      let context = collect(ctx, |(name, type_)| {
+        let type_ = type_.map_or(Term::star(name.location), Term::of_node);
+        return (Head::Local(name), type_)
+      });
+      let body = Term::of_node(ConcretisedNode { implicit_context: None, ..node });
+      return Term::init(TermKind::Forall { context, body: Box::new(body) }, location)
+    }
+    let sub = |node: *mut ConcretisedNode| Box::new(Term::of_node(unsafe { *node }));
+    let head_of = |name: Symbol, origination: Origin| match origination {
+      Origin::GlobalScope => Head::Global(name),
+      Origin::PatternBinding | Origin::ContextBinding => Head::Local(name),
+    };
+    let kind = match node.kind {
+      ConcretisedNodeRepr::Star => TermKind::Star,
+      ConcretisedNodeRepr::Void => TermKind::Void,
+      ConcretisedNodeRepr::Singleton => TermKind::Singleton,
+      ConcretisedNodeRepr::Pt => TermKind::Pt,
+      ConcretisedNodeRepr::Reference { name, origination } => {
+        TermKind::Neutral { head: head_of(name, origination), arguments: Vec::new() }
+      },
+      ConcretisedNodeRepr::App { root, arguments, origination } => {
+        TermKind::Neutral {
+          head: head_of(root, origination),
+          arguments: collect(arguments, Term::of_node)
+        }
+      },
+      ConcretisedNodeRepr::Wit { premises, conclusion } => {
+        TermKind::Wit {
+          premises: collect(premises, Term::of_node), conclusion: sub(conclusion) }
+      },
+      ConcretisedNodeRepr::Arrow { head, spine, .. } => {
+        let head = collect(head, |(name, type_)| {
+          return (name.map(Head::Local), Term::of_node(type_))
+        });
+        TermKind::Pi { head, spine: sub(spine) }
+      },
+      ConcretisedNodeRepr::Sigma { head, spine } => {
+        let head = collect(head, |(name, type_)| {
+          return (name.map(Head::Local), Term::of_node(type_))
+        });
+        TermKind::Sigma { head, spine: sub(spine) }
+      },
+      ConcretisedNodeRepr::Lam { rewrite_rules } => {
+        TermKind::Lam { rewrite_rules, environment: Vec::new() }
+      },
+      ConcretisedNodeRepr::Pair(l, r) => TermKind::Pair(sub(l), sub(r)),
+      ConcretisedNodeRepr::Either(l, r) => TermKind::Either(sub(l), sub(r)),
+      ConcretisedNodeRepr::Tuple(l, r) => TermKind::Tuple(sub(l), sub(r)),
+      ConcretisedNodeRepr::Left(v) => TermKind::Left(sub(v)),
+      ConcretisedNodeRepr::Right(v) => TermKind::Right(sub(v)),
+    };
+    return Term::init(kind, location)
+  }
+}
+
+fn collect<T: Copy, K>(array: ArrayPtr<T>, fun: impl FnMut(T) -> K) -> Vec<K> {
+  let ptr = array.project_ptr();
+  let items = (0 .. array.project_count() as usize).map(|ix| unsafe { *ptr.add(ix) });
+  return items.map(fun).collect()
+}
+
+
+/// Substitution
+// Applies a term to arguments, keeping neutral terms flat
+pub fn apply(function: Term, mut arguments: Vec<Term>) -> Term {
+  if arguments.is_empty() { return function }
+  let (location, origin) = (function.location, function.origin);
+  match function.kind {
+    TermKind::Neutral { head, arguments: mut prefix } => {
+      prefix.append(&mut arguments);
+      let kind = TermKind::Neutral { head, arguments: prefix };
+      return Term { kind, location, origin }
+    },
+    TermKind::Apply { function, arguments: mut prefix } => {
+      prefix.append(&mut arguments);
+      let kind = TermKind::Apply { function, arguments: prefix };
+      return Term { kind, location, origin }
+    },
+    TermKind::Unknown => return function,
+    _ => {
+      let kind = TermKind::Apply { function: Box::new(function), arguments };
+      return Term { kind, location, origin }
+    }
+  }
+}
+
+static FRESH_COUNT: AtomicU32 = AtomicU32::new(0);
+
+// Made up variables are numbered across all checks,
+// so the ones that rename binders never meet the ones of a checker
+pub fn fresh_head() -> Head {
+  return Head::Fresh(FRESH_COUNT.fetch_add(1, Ordering::Relaxed))
+}
+
+// Replaces free binders. Binders of lifts and implicit contexts
+// shadow the ones being replaced. When a value mentions a local
+// with the name of a binder it goes under, the binder is renamed
+// to a fresh variable, so that the value keeps meaning what it did
+pub fn substitute(term: &Term, mapping: &[(Head, Term)]) -> Term {
+  if mapping.is_empty() { return term.clone() }
+  let sub = |term: &Term| Box::new(substitute(term, mapping));
+  let kind = match &term.kind {
+    TermKind::Neutral { head, arguments } => {
+      let arguments = arguments.iter().map(|arg| substitute(arg, mapping)).collect();
+      let replacement = mapping.iter().rev().find(|(bound, _)| bound == head);
+      if let Some((_, value)) = replacement {
+        return apply(value.clone(), arguments)
+      }
+      TermKind::Neutral { head: *head, arguments }
+    },
+    TermKind::Apply { function, arguments } => {
+      let arguments = arguments.iter().map(|arg| substitute(arg, mapping)).collect();
+      return apply(substitute(function, mapping), arguments)
+    },
+    TermKind::Forall { context, body } => {
+      let mut mapping = mapping.to_vec();
+      let context = context.iter().map(|(name, type_)| {
+        let type_ = substitute(type_, &mapping);
+        return (bind_under(&mut mapping, *name, &type_), type_)
+      }).collect();
+      TermKind::Forall { context, body: Box::new(substitute(body, &mapping)) }
+    },
+    TermKind::Pi { head, spine } | TermKind::Sigma { head, spine } => {
+      let mut mapping = mapping.to_vec();
+      let head = head.iter().map(|(name, type_)| {
+        let type_ = substitute(type_, &mapping);
+        let name = name.map(|name| bind_under(&mut mapping, name, &type_));
+        return (name, type_)
+      }).collect();
+      let spine = Box::new(substitute(spine, &mapping));
+      match term.kind {
+        TermKind::Pi { .. } => TermKind::Pi { head, spine },
+        _ => TermKind::Sigma { head, spine },
+      }
+    },
+    TermKind::Wit { premises, conclusion } => {
+      let premises = premises.iter().map(|prem| substitute(prem, mapping)).collect();
+      TermKind::Wit { premises, conclusion: sub(conclusion) }
+    },
+    TermKind::Lam { rewrite_rules, environment } => {
+      let mut environment = environment.iter()
+        .map(|(name, value)| (*name, substitute(value, mapping)))
+        .collect::<Vec<_>>();
+      // rules refer to binders by name, made up ones cant appear in them
+      for (head, value) in mapping {
+        let Head::Local(name) = head else { continue };
+        if !environment.iter().any(|(bound, _)| bound == name) {
+          environment.push((*name, value.clone()))
+        }
+      }
+      TermKind::Lam { rewrite_rules: *rewrite_rules, environment }
+    },
+    TermKind::Pair(l, r) => TermKind::Pair(sub(l), sub(r)),
+    TermKind::Either(l, r) => TermKind::Either(sub(l), sub(r)),
+    TermKind::Tuple(l, r) => TermKind::Tuple(sub(l), sub(r)),
+    TermKind::Left(v) => TermKind::Left(sub(v)),
+    TermKind::Right(v) => TermKind::Right(sub(v)),
+    TermKind::Star | TermKind::Void | TermKind::Singleton |
+    TermKind::Pt | TermKind::Unknown => return term.clone(),
+  };
+  return Term::init_at(kind, term)
+}
+
+// Takes a binder out of the mapping for the scope it opens.
+// Gives back the name it is bound with from now on
+fn bind_under(mapping: &mut Vec<(Head, Term)>, name: Head, type_: &Term) -> Head {
+  mapping.retain(|(bound, _)| *bound != name);
+  let mentions = |term: &Term| matches!(
+    term.kind, TermKind::Neutral { head, .. } if head == name);
+  if !mapping.iter().any(|(_, value)| contains(value, &mentions)) { return name }
+  let fresh = fresh_head();
+  mapping.push((name, Term::variable(fresh, type_.location)));
+  return fresh
+}
+
+// Terms that come from other files point to places in them,
+// so they have to say which file that is
+pub fn mark_origin(term: &mut Term, origin: FileId) {
+  term.origin = Some(origin);
+  let each = |terms: &mut Vec<Term>| {
+    for term in terms { mark_origin(term, origin) }
+  };
+  match &mut term.kind {
+    TermKind::Neutral { arguments, .. } => each(arguments),
+    TermKind::Apply { function, arguments } => {
+      mark_origin(function, origin);
+      each(arguments);
+    },
+    TermKind::Forall { context, body } => {
+      for (_, type_) in context { mark_origin(type_, origin) }
+      mark_origin(body, origin);
+    },
+    TermKind::Pi { head, spine } | TermKind::Sigma { head, spine } => {
+      for (_, type_) in head { mark_origin(type_, origin) }
+      mark_origin(spine, origin);
+    },
+    TermKind::Wit { premises, conclusion } => {
+      each(premises);
+      mark_origin(conclusion, origin);
+    },
+    TermKind::Lam { environment, .. } => {
+      for (_, value) in environment { mark_origin(value, origin) }
+    },
+    TermKind::Pair(l, r) | TermKind::Either(l, r) | TermKind::Tuple(l, r) => {
+      mark_origin(l, origin);
+      mark_origin(r, origin);
+    },
+    TermKind::Left(v) | TermKind::Right(v) => mark_origin(v, origin),
+    TermKind::Star | TermKind::Void | TermKind::Singleton |
+    TermKind::Pt | TermKind::Unknown => (),
+  }
+}
+
+// Puts solutions in place of holes, so that nothing refers to them
+pub fn fill_holes(term: &mut Term, holes: &[Option<Term>]) {
+  // solution can be another hole
+  while let TermKind::Neutral { head: Head::Hole(ix), arguments } = &term.kind {
+    let Some(Some(solution)) = holes.get(*ix as usize) else { break };
+    let (location, origin) = (term.location, term.origin);
+    *term = apply(solution.clone(), arguments.clone());
+    term.location = location;
+    term.origin = origin;
+  }
+  let each = |terms: &mut Vec<Term>| {
+    for term in terms { fill_holes(term, holes) }
+  };
+  match &mut term.kind {
+    TermKind::Neutral { arguments, .. } => each(arguments),
+    TermKind::Apply { function, arguments } => {
+      fill_holes(function, holes);
+      each(arguments);
+    },
+    TermKind::Forall { context, body } => {
+      for (_, type_) in context { fill_holes(type_, holes) }
+      fill_holes(body, holes);
+    },
+    TermKind::Pi { head, spine } | TermKind::Sigma { head, spine } => {
+      for (_, type_) in head { fill_holes(type_, holes) }
+      fill_holes(spine, holes);
+    },
+    TermKind::Wit { premises, conclusion } => {
+      each(premises);
+      fill_holes(conclusion, holes);
+    },
+    TermKind::Lam { environment, .. } => {
+      for (_, value) in environment { fill_holes(value, holes) }
+    },
+    TermKind::Pair(l, r) | TermKind::Either(l, r) | TermKind::Tuple(l, r) => {
+      fill_holes(l, holes);
+      fill_holes(r, holes);
+    },
+    TermKind::Left(v) | TermKind::Right(v) => fill_holes(v, holes),
+    TermKind::Star | TermKind::Void | TermKind::Singleton |
+    TermKind::Pt | TermKind::Unknown => (),
+  }
+}
+
+// Looks for a subterm, the term itself included, that fits the predicate
+pub fn contains(term: &Term, predicate: &dyn Fn(&Term) -> bool) -> bool {
+  if predicate(term) { return true }
+  let any = |terms: &[Term]| terms.iter().any(|term| contains(term, predicate));
+  match &term.kind {
+    TermKind::Neutral { arguments, .. } => any(arguments),
+    TermKind::Apply { function, arguments } => {
+      contains(function, predicate) || any(arguments)
+    },
+    TermKind::Forall { context, body } => {
+      context.iter().any(|(_, type_)| contains(type_, predicate)) ||
+      contains(body, predicate)
+    },
+    TermKind::Pi { head, spine } | TermKind::Sigma { head, spine } => {
+      head.iter().any(|(_, type_)| contains(type_, predicate)) ||
+      contains(spine, predicate)
+    },
+    TermKind::Wit { premises, conclusion } => {
+      any(premises) || contains(conclusion, predicate)
+    },
+    TermKind::Lam { environment, .. } => {
+      environment.iter().any(|(_, value)| contains(value, predicate))
+    },
+    TermKind::Pair(l, r) | TermKind::Either(l, r) | TermKind::Tuple(l, r) => {
+      contains(l, predicate) || contains(r, predicate)
+    },
+    TermKind::Left(v) | TermKind::Right(v) => contains(v, predicate),
+    TermKind::Star | TermKind::Void | TermKind::Singleton |
+    TermKind::Pt | TermKind::Unknown => false,
+  }
+}
+
+
+/// Printing
+// Written the way the parser would accept, except for
+// made up variables and holes, which have no syntax
+impl Display for Term {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    let items = |f: &mut Formatter<'_>, head: &Vec<(Option<Head>, Term)>| {
+      for (ix, (name, type_)) in head.iter().enumerate() {
+        if ix != 0 { write!(f, ", ")? }
+        if let Some(name) = name { write!(f, "{} : ", name)? }
+        write!(f, "{}", type_)?
+      }
+      return Ok(())
+    };
+    match &self.kind {
+      TermKind::Star => write!(f, "*"),
+      TermKind::Void => write!(f, "Void"),
+      TermKind::Singleton => write!(f, "Dot"),
+      TermKind::Pt => write!(f, "pt"),
+      TermKind::Unknown => write!(f, "?"),
+      TermKind::Neutral { head, arguments } => {
+        write!(f, "{}", head)?;
+        for arg in arguments { write!(f, " {}", Argument(arg))? }
+        return Ok(())
+      },
+      TermKind::Apply { function, arguments } => {
+        write!(f, "({})", function)?;
+        for arg in arguments { write!(f, " {}", Argument(arg))? }
+        return Ok(())
+      },
+      TermKind::Forall { context, body } => {
+        write!(f, "{{")?;
+        for (ix, (name, type_)) in context.iter().enumerate() {
+          if ix != 0 { write!(f, ", ")? }
+          write!(f, "{}", name)?;
+          if !matches!(type_.kind, TermKind::Star) { write!(f, " : {}", type_)? }
+        }
+        write!(f, "}} {}", Argument(body))
+      },
+      TermKind::Pi { head, spine } => {
+        write!(f, "(")?; items(f, head)?; write!(f, ") -> {}", spine)
+      },
+      TermKind::Sigma { head, spine } => {
+        write!(f, "(")?; items(f, head)?; write!(f, ") |- {}", spine)
+      },
+      TermKind::Wit { premises, conclusion } => {
+        write!(f, "[| ")?;
+        for (ix, prem) in premises.iter().enumerate() {
+          if ix != 0 { write!(f, ", ")? }
+          write!(f, "{}", prem)?
+        }
+        write!(f, "; {} |]", conclusion)
+      },
+      TermKind::Lam { .. } => write!(f, "\\{{ .. }}"),
+      TermKind::Pair(l, r) => write!(f, "Pair {} {}", Argument(l), Argument(r)),
+      TermKind::Either(l, r) => write!(f, "Either {} {}", Argument(l), Argument(r)),
+      TermKind::Tuple(l, r) => write!(f, "two {} {}", Argument(l), Argument(r)),
+      TermKind::Left(v) => write!(f, "inl {}", Argument(v)),
+      TermKind::Right(v) => write!(f, "inr {}", Argument(v)),
+    }
+  }
+}
+
+impl Display for Head {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self {
+      Head::Global(name) | Head::Local(name) => write!(f, "{}", name.materialise_name()),
+      Head::Fresh(ix) => write!(f, "'{}", ix),
+      Head::Hole(ix) => write!(f, "?{}", ix),
+    }
+  }
+}
+
+// term in argument position gets parenthesised unless it is atomic
+struct Argument<'a>(&'a Term);
+impl Display for Argument<'_> {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    let is_atom = match &self.0.kind {
+      TermKind::Neutral { arguments, .. } => arguments.is_empty(),
+      TermKind::Void | TermKind::Singleton | TermKind::Pt |
+      TermKind::Unknown => true,
+      _ => false
+    };
+    if is_atom { return write!(f, "{}", self.0) }
+    return write!(f, "({})", self.0)
+  }
+}
@@ -0,0 +1,234 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::expression_trees::{
+  better_nodes::{
+    Declaration, DeclKind, Symbol, ConcretisedNode, ConcretisedNodeRepr,
+    ConcretisedPattern, ConcretisedPatternKind, ConcretisedRewriteRule,
+    ArrayPtr, Origin},
+  raw_syntax_nodes::SourceLocation};
+
+use super::diagnostics::{DiagnosticService, ProblemReport, Kind};
+
+
+// How arguments of a call relate to parameters of the caller.
+// Arc (i, j, true) says that argument j is strictly inside of
+// parameter i, and (i, j, false) says they are the same
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SizeChange {
+  from: usize,
+  to: usize,
+  arcs: Vec<(usize, usize, bool)>,
+}
+
+impl SizeChange {
+  fn init(from: usize, to: usize, mut arcs: Vec<(usize, usize, bool)>) -> Self {
+    // strict arc wins over same one that is not
+    arcs.sort_by_key(|(i, j, strict)| (*i, *j, !*strict));
+    arcs.dedup_by_key(|(i, j, _)| (*i, *j));
+    return SizeChange { from, to, arcs }
+  }
+  // change made by a call, followed by a call from callee of it
+  fn then(&self, next: &SizeChange) -> SizeChange {
+    let mut arcs = Vec::new();
+    for (i, j, s1) in &self.arcs {
+      for (k, l, s2) in &next.arcs {
+        if j == k { arcs.push((*i, *l, *s1 || *s2)) }
+      }
+    }
+    return SizeChange::init(self.from, next.to, arcs)
+  }
+  fn decreases(&self) -> bool {
+    return self.arcs.iter().any(|(i, j, strict)| i == j && *strict)
+  }
+}
+
+struct Call {
+  change: SizeChange,
+  site: SourceLocation,
+}
+
+
+// Checks that members of a cycle from the call graph, found by
+// cycle analysis, cant call each other without end.
+// This is the size-change principle: every way to get back
+// to where one started must make some argument strictly smaller.
+// Arguments are known to be smaller only when they are
+// variables bound inside of a constructor pattern, so recursion
+// that goes through anything else has to be marked as partial.
+// Calls from partial declarations are trusted, which
+// opts every cycle through them out of the check
+pub fn check_termination(
+  members: &[*mut Declaration],
+  diagnostics: &DiagnosticService,
+) {
+  let indices = members.iter().enumerate()
+    .map(|(ix, decl)| (unsafe { &**decl }.project_name(), ix))
+    .collect::<HashMap<_, _>>();
+  let mut calls = Vec::new();
+  for (ix, decl) in members.iter().enumerate() {
+    let decl = unsafe { &**decl };
+    if decl.is_malformed || decl.is_marked_partial() { continue }
+    let mut collector = CallCollector { caller: ix, indices: &indices, calls: &mut calls };
+    match decl.repr {
+      DeclKind::WellScopedMapping { rewrite_rules, .. } => {
+        for rule_ix in 0 .. rewrite_rules.project_count() {
+          let rule = unsafe { *rewrite_rules.get_ptr(rule_ix) };
+          let mut sizes = Vec::new();
+          for column in 0 .. rule.matchers.project_count() {
+            let pattern = unsafe { *rule.matchers.get_ptr(column) };
+            note_sizes(pattern, column as usize, false, &mut sizes);
+          }
+          collector.visit(unsafe { *rule.rhs }, &sizes);
+        }
+      },
+      // definitions have no parameters to decrease
+      DeclKind::WellScopedDefinition { value, .. } => {
+        collector.visit(unsafe { *value }, &[])
+      },
+      // data types only mention themselves in types
+      DeclKind::WellScopedData { .. } => (),
+      _ => panic!("Termination is checked only after scope analysis")
+    }
+  }
+
+  // all changes that some sequence of calls makes
+  let mut known = HashSet::new();
+  let mut closure = Vec::<(SizeChange, SourceLocation)>::new();
+  for call in &calls {
+    if known.insert(call.change.clone()) {
+      closure.push((call.change.clone(), call.site))
+    }
+  }
+  let mut ix = 0;
+  while ix < closure.len() {
+    let (change, site) = closure[ix].clone();
+    for call in calls.iter().filter(|call| call.change.from == change.to) {
+      let longer = change.then(&call.change);
+      if known.insert(longer.clone()) { closure.push((longer, site)) }
+    }
+    ix += 1;
+  }
+
+  let mut reported = HashSet::new();
+  for (change, site) in &closure {
+    let is_loop = change.from == change.to && change.then(change) == *change;
+    if !is_loop || change.decreases() { continue }
+    // definitions that unfold forever are found by cycle analysis,
+    // so only mappings are reported here
+    let decl = unsafe { &*members[change.from] };
+    let DeclKind::WellScopedMapping { name, .. } = decl.repr else { continue };
+    if !reported.insert(change.from) { continue }
+    let problem = ProblemReport {
+      kind: Kind::NonTerminatingRecursion { name, call_site: *site } };
+    diagnostics.report_problem(name.origin, problem);
+  }
+}
+
+// binders of a pattern, and whether they are strictly inside of it
+fn note_sizes(
+  pattern: ConcretisedPattern, column: usize, is_inside: bool,
+  sizes: &mut Vec<(Symbol, usize, bool)>,
+) {
+  match pattern.repr {
+    ConcretisedPatternKind::VarBinding(name) => sizes.push((name, column, is_inside)),
+    ConcretisedPatternKind::Left(v) |
+    ConcretisedPatternKind::Right(v) => note_sizes(unsafe { *v }, column, true, sizes),
+    ConcretisedPatternKind::Tuple(l, r) => {
+      note_sizes(unsafe { *l }, column, true, sizes);
+      note_sizes(unsafe { *r }, column, true, sizes)
+    },
+    ConcretisedPatternKind::Constructor { arguments, .. } => {
+      for ix in 0 .. arguments.project_count() {
+        note_sizes(unsafe { *arguments.get_ptr(ix) }, column, true, sizes)
+      }
+    },
+    ConcretisedPatternKind::Wildcard |
+    ConcretisedPatternKind::Pt => (),
+  }
+}
+
+struct CallCollector<'a> {
+  caller: usize,
+  indices: &'a HashMap<Symbol, usize>,
+  calls: &'a mut Vec<Call>,
+}
+
+impl CallCollector<'_> {
+  fn note(
+    &mut self, callee: Symbol, arguments: &[ConcretisedNode],
+    sizes: &[(Symbol, usize, bool)], site: SourceLocation,
+  ) {
+    let Some(callee) = self.indices.get(&callee) else { return };
+    let mut arcs = Vec::new();
+    for (arg_ix, arg) in arguments.iter().enumerate() {
+      let ConcretisedNodeRepr::Reference {
+        name, origination: Origin::PatternBinding } = arg.kind else { continue };
+      if let Some((_, column, is_inside)) = sizes.iter().find(|(var, ..)| *var == name) {
+        arcs.push((*column, arg_ix, *is_inside))
+      }
+    }
+    let change = SizeChange::init(self.caller, *callee, arcs);
+    self.calls.push(Call { change, site })
+  }
+  fn visit(&mut self, node: ConcretisedNode, sizes: &[(Symbol, usize, bool)]) {
+    let each = |this: &mut Self, array: ArrayPtr<ConcretisedNode>| {
+      for ix in 0 .. array.project_count() {
+        this.visit(unsafe { *array.get_ptr(ix) }, sizes)
+      }
+    };
+    match node.kind {
+      ConcretisedNodeRepr::Reference { name, origination: Origin::GlobalScope } => {
+        self.note(name, &[], sizes, node.location)
+      },
+      ConcretisedNodeRepr::App { root, arguments, origination } => {
+        if let Origin::GlobalScope = origination {
+          let arguments_ = (0 .. arguments.project_count())
+            .map(|ix| unsafe { *arguments.get_ptr(ix) })
+            .collect::<Vec<_>>();
+          self.note(root, &arguments_, sizes, node.location)
+        }
+        each(self, arguments)
+      },
+      ConcretisedNodeRepr::Wit { premises, conclusion } => {
+        each(self, premises);
+        self.visit(unsafe { *conclusion }, sizes)
+      },
+      ConcretisedNodeRepr::Sigma { head, spine } |
+      ConcretisedNodeRepr::Arrow { head, spine, .. } => {
+        for ix in 0 .. head.project_count() {
+          let (_, type_) = unsafe { *head.get_ptr(ix) };
+          self.visit(type_, sizes)
+        }
+        self.visit(unsafe { *spine }, sizes)
+      },
+      // calls from a lambda happen when it is applied,
+      // and its own binders hide ones from outside
+      ConcretisedNodeRepr::Lam { rewrite_rules } => {
+        for ix in 0 .. rewrite_rules.project_count() {
+          let rule: ConcretisedRewriteRule = unsafe { *rewrite_rules.get_ptr(ix) };
+          let mut hidden = Vec::new();
+          for column in 0 .. rule.matchers.project_count() {
+            let pattern = unsafe { *rule.matchers.get_ptr(column) };
+            note_sizes(pattern, 0, false, &mut hidden);
+          }
+          let visible = sizes.iter()
+            .filter(|(name, ..)| !hidden.iter().any(|(other, ..)| other == name))
+            .copied()
+            .collect::<Vec<_>>();
+          self.visit(unsafe { *rule.rhs }, &visible)
+        }
+      },
+      ConcretisedNodeRepr::Pair(l, r) |
+      ConcretisedNodeRepr::Tuple(l, r) |
+      ConcretisedNodeRepr::Either(l, r) => {
+        self.visit(unsafe { *l }, sizes);
+        self.visit(unsafe { *r }, sizes)
+      },
+      ConcretisedNodeRepr::Left(v) |
+      ConcretisedNodeRepr::Right(v) => self.visit(unsafe { *v }, sizes),
+      ConcretisedNodeRepr::Reference { .. } |
+      ConcretisedNodeRepr::Star | ConcretisedNodeRepr::Void |
+      ConcretisedNodeRepr::Singleton | ConcretisedNodeRepr::Pt => (),
+    }
+  }
+}
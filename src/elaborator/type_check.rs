@@ -0,0 +1,647 @@
+use std::mem::{take, replace};
+
+use crate::expression_trees::{
+  better_nodes::{
+    Symbol, Declaration, DeclKind, ConcretisedNode, ConcretisedNodeRepr,
+    ConcretisedRewriteRule, ConcretisedPattern, ConcretisedPatternKind,
+    ConcretisedImplicitCtx, ArrayPtr, Origin},
+  raw_syntax_nodes::{SourceLocation, FileId},
+};
+
+use super::{
+  diagnostics::{SomeDiagnosticsDelegate, ProblemReport, Kind},
+  semantic_terms::{
+    Term, TermKind, Head, substitute, mark_origin, fill_holes, contains, fresh_head},
+  normaliser::{Normaliser, DEFAULT_FUEL},
+};
+
+pub use super::normaliser::GlobalDeclarations;
+
+// Checks value of a definition, or every rule of a mapping,
+// against the type it was given.
+// Declaration must be concretised without problems
+pub fn check_declaration_types(
+  declaration: &Declaration,
+  globals: &dyn GlobalDeclarations,
+  diagnostic_delegate: &mut dyn SomeDiagnosticsDelegate,
+) {
+  if declaration.is_malformed { return }
+  let file = declaration.project_name().origin;
+  let mut checker = TypeChecker::init(globals, file, diagnostic_delegate);
+  match declaration.repr {
+    DeclKind::WellScopedDefinition { given_type, value, .. } => {
+      let type_ = unsafe { *given_type };
+      let star = Term::star(type_.location);
+      checker.check(type_, &star);
+      checker.check(unsafe { *value }, &Term::of_node(type_));
+    },
+    DeclKind::WellScopedMapping { given_type, rewrite_rules, .. } => {
+      let type_ = unsafe { *given_type };
+      let star = Term::star(type_.location);
+      checker.check(type_, &star);
+      checker.check_rewrite_rules(rewrite_rules, &Term::of_node(type_), false);
+    },
+    DeclKind::WellScopedData { given_type, constructors, .. } => {
+      let type_ = unsafe { *given_type };
+      checker.check(type_, &Term::star(type_.location));
+      for ix in 0 .. constructors.project_count() {
+        let ctor_type = unsafe { *(*constructors.get_ptr(ix)).given_type };
+        checker.check(ctor_type, &Term::star(ctor_type.location));
+      }
+    },
+    _ => panic!("Types can only be checked after scope analysis")
+  }
+}
+
+
+pub struct TypeChecker<'a> {
+  globals: &'a dyn GlobalDeclarations,
+  // file of the declaration being checked.
+  // terms from declarations of other files say where they come from
+  file: FileId,
+  diagnostic_delegate: &'a mut dyn SomeDiagnosticsDelegate,
+  // types of binders that are in scope, innermost last
+  locals: Vec<(Symbol, Term)>,
+  // also keeps the holes
+  normaliser: Normaliser<'a>,
+  // Constructor patterns learn what indices of a type are
+  // in the clause they are in. Those are gathered while results
+  // of constructors are compared with types of their columns
+  refinements: Option<Vec<(Head, Term)>>,
+  // binders of the clause whose patterns are checked start here.
+  // only they can be refined, the rest are fixed by outer scopes
+  clause_scope: usize,
+  // types that were reported to reduce for too long,
+  // so that every comparison with them doesnt report it again
+  gave_up_on: Vec<(FileId, SourceLocation)>,
+}
+
+impl <'a> TypeChecker<'a> {
+  pub fn init(
+    globals: &'a dyn GlobalDeclarations,
+    file: FileId,
+    diagnostic_delegate: &'a mut dyn SomeDiagnosticsDelegate,
+  ) -> Self {
+    return Self {
+      globals, file, diagnostic_delegate,
+      locals: Vec::new(), normaliser: Normaliser::init(globals),
+      refinements: None, clause_scope: 0, gave_up_on: Vec::new(),
+    }
+  }
+  fn report(&mut self, kind: Kind) {
+    self.diagnostic_delegate.report_problem(ProblemReport { kind })
+  }
+  // types with unknown parts already failed somewhere else
+  fn mismatch(&mut self, expected: &Term, term_loc: SourceLocation) {
+    if self.has_unknown(expected) { return }
+    self.report(Kind::MismatchedType {
+      type_expr: expected.location, type_origin: self.origin_of(expected),
+      term_expr: term_loc })
+  }
+  fn origin_of(&self, term: &Term) -> FileId {
+    return term.origin.unwrap_or(self.file)
+  }
+  fn has_unknown(&self, term: &Term) -> bool {
+    return contains(&self.fill_in(term), &Term::is_unknown)
+  }
+  fn fresh(&mut self, location: SourceLocation) -> Term {
+    return Term::variable(fresh_head(), location)
+  }
+  fn hole(&mut self, location: SourceLocation) -> Term {
+    let holes = &mut self.normaliser.holes;
+    holes.push(None);
+    return Term::variable(Head::Hole(holes.len() as u32 - 1), location)
+  }
+  // made up binders cant be referred to, only locals are bound
+  fn bind_context(&mut self, context: &[(Head, Term)]) {
+    for (name, type_) in context {
+      if let Head::Local(name) = name { self.locals.push((*name, type_.clone())) }
+    }
+  }
+  // items of implicit context are types, unless said otherwise
+  fn bind_node_context(&mut self, context: ConcretisedImplicitCtx) {
+    for ix in 0 .. context.project_count() {
+      let (name, type_) = unsafe { *context.get_ptr(ix) };
+      let type_ = match type_ {
+        Some(type_) => {
+          self.check(type_, &Term::star(type_.location));
+          Term::of_node(type_)
+        },
+        None => Term::star(name.location),
+      };
+      self.locals.push((name, type_));
+    }
+  }
+}
+
+/// Evaluation
+impl TypeChecker<'_> {
+  // Reduces the top of a term with definitions, rules and filled holes.
+  // Result keeps the location of the given term,
+  // so reports point to the type as it was written.
+  // Terms that reduce for too long are reported and treated as unknown
+  pub fn whnf(&mut self, term: &Term) -> Term {
+    self.normaliser.set_fuel(DEFAULT_FUEL);
+    let term = self.normaliser.whnf(term);
+    if self.normaliser.ran_out_of_fuel() {
+      let (origin, location) = (self.origin_of(&term), term.location);
+      let reported = self.gave_up_on.iter().any(|(file, other)| {
+        return *file == origin &&
+               other.primary_offset == location.primary_offset &&
+               other.secondary_offset == location.secondary_offset
+      });
+      if !reported {
+        self.gave_up_on.push((origin, location));
+        self.report(Kind::ReductionOutOfFuel { type_expr: location, type_origin: origin })
+      }
+      return Term::unknown(location)
+    }
+    return term
+  }
+  // term as it is known now, with filled holes replaced
+  pub fn fill_in(&self, term: &Term) -> Term {
+    let mut term = term.clone();
+    fill_holes(&mut term, &self.normaliser.holes);
+    return term
+  }
+  // type of a declaration, with its implicit context made into holes
+  fn global_type(&mut self, name: Symbol) -> Term {
+    let Some(decl) = self.globals.find_declaration(name) else {
+      return Term::unknown(name.location)
+    };
+    let type_ = match decl.repr {
+      _ if decl.is_malformed => return Term::unknown(name.location),
+      DeclKind::WellScopedMapping { given_type, .. } |
+      DeclKind::WellScopedDefinition { given_type, .. } => given_type,
+      DeclKind::WellScopedData { name: data, given_type, .. } if data == name => given_type,
+      DeclKind::WellScopedData { .. } => match decl.find_constructor(name) {
+        Some(ctor) => ctor.given_type,
+        None => return Term::unknown(name.location)
+      },
+      _ => return Term::unknown(name.location)
+    };
+    let mut type_ = Term::of_node(unsafe { *type_ });
+    if decl.project_name().origin != self.file {
+      mark_origin(&mut type_, decl.project_name().origin)
+    }
+    return self.instantiate(type_)
+  }
+  fn instantiate(&mut self, type_: Term) -> Term {
+    let TermKind::Forall { context, body } = type_.kind else { return type_ };
+    let holes = context.iter()
+      .map(|(name, _)| {
+        let mut hole = self.hole(type_.location);
+        hole.origin = type_.origin;
+        return (*name, hole)
+      })
+      .collect::<Vec<_>>();
+    return substitute(&body, &holes)
+  }
+  fn local_type(&mut self, name: Symbol) -> Term {
+    let found = self.locals.iter().rev().find(|(bound, _)| *bound == name);
+    let Some((_, type_)) = found else { return Term::unknown(name.location) };
+    let type_ = type_.clone();
+    return self.instantiate(type_)
+  }
+}
+
+/// Conversion
+impl TypeChecker<'_> {
+  // Decides if two terms are the same, filling holes on the way.
+  // Binders are compared by position, not by name.
+  // Unknown terms are the same as nothing
+  pub fn convertible(&mut self, left: &Term, right: &Term) -> bool {
+    let left = self.whnf(left);
+    let right = self.whnf(right);
+    match (&left.kind, &right.kind) {
+      (TermKind::Unknown, _) | (_, TermKind::Unknown) => false,
+      (TermKind::Neutral { head: Head::Hole(ix), arguments }, _)
+      if arguments.is_empty() => {
+        self.fill_hole(*ix, right)
+      },
+      (_, TermKind::Neutral { head: Head::Hole(ix), arguments })
+      if arguments.is_empty() => {
+        self.fill_hole(*ix, left)
+      },
+      // index of a column is whatever constructor says it is
+      (TermKind::Neutral { head: Head::Local(name), arguments }, _)
+      if arguments.is_empty() && self.is_refinable(*name) => {
+        self.refinements.as_mut().unwrap().push((Head::Local(*name), right));
+        true
+      },
+      (TermKind::Star, TermKind::Star) |
+      (TermKind::Void, TermKind::Void) |
+      (TermKind::Singleton, TermKind::Singleton) |
+      (TermKind::Pt, TermKind::Pt) => true,
+      (TermKind::Neutral { head: lh, arguments: la },
+       TermKind::Neutral { head: rh, arguments: ra }) => {
+        lh == rh && self.all_convertible(la, ra)
+      },
+      (TermKind::Apply { function: lf, arguments: la },
+       TermKind::Apply { function: rf, arguments: ra }) => {
+        self.convertible(lf, rf) && self.all_convertible(la, ra)
+      },
+      (TermKind::Forall { context: lc, body: lb },
+       TermKind::Forall { context: rc, body: rb }) => {
+        let lc = lc.iter().map(|(name, type_)| (Some(*name), type_.clone())).collect();
+        let rc = rc.iter().map(|(name, type_)| (Some(*name), type_.clone())).collect();
+        self.binders_convertible(lc, lb, rc, rb)
+      },
+      (TermKind::Pi { head: lh, spine: ls }, TermKind::Pi { head: rh, spine: rs }) |
+      (TermKind::Sigma { head: lh, spine: ls }, TermKind::Sigma { head: rh, spine: rs }) => {
+        self.binders_convertible(lh.clone(), ls, rh.clone(), rs)
+      },
+      (TermKind::Wit { premises: lp, conclusion: lc },
+       TermKind::Wit { premises: rp, conclusion: rc }) => {
+        self.all_convertible(lp, rp) && self.convertible(lc, rc)
+      },
+      (TermKind::Lam { rewrite_rules: lr, .. }, TermKind::Lam { rewrite_rules: rr, .. }) => {
+        lr.project_ptr() == rr.project_ptr()
+      },
+      (TermKind::Pair(ll, lr), TermKind::Pair(rl, rr)) |
+      (TermKind::Either(ll, lr), TermKind::Either(rl, rr)) |
+      (TermKind::Tuple(ll, lr), TermKind::Tuple(rl, rr)) => {
+        self.convertible(ll, rl) && self.convertible(lr, rr)
+      },
+      (TermKind::Left(l), TermKind::Left(r)) |
+      (TermKind::Right(l), TermKind::Right(r)) => self.convertible(l, r),
+      _ => false
+    }
+  }
+  fn all_convertible(&mut self, left: &[Term], right: &[Term]) -> bool {
+    if left.len() != right.len() { return false }
+    return left.iter().zip(right).all(|(l, r)| self.convertible(l, r))
+  }
+  // names bound on both sides are replaced with the same fresh variable
+  fn binders_convertible(
+    &mut self,
+    left: Vec<(Option<Head>, Term)>, left_spine: &Term,
+    right: Vec<(Option<Head>, Term)>, right_spine: &Term,
+  ) -> bool {
+    if left.len() != right.len() { return false }
+    let mut left_names = Vec::new();
+    let mut right_names = Vec::new();
+    for ((ln, lt), (rn, rt)) in left.iter().zip(&right) {
+      let lt = substitute(lt, &left_names);
+      let rt = substitute(rt, &right_names);
+      if !self.convertible(&lt, &rt) { return false }
+      let fresh = self.fresh(lt.location);
+      if let Some(name) = ln { left_names.push((*name, fresh.clone())) }
+      if let Some(name) = rn { right_names.push((*name, fresh)) }
+    }
+    let left_spine = substitute(left_spine, &left_names);
+    let right_spine = substitute(right_spine, &right_names);
+    return self.convertible(&left_spine, &right_spine)
+  }
+  fn is_refinable(&self, name: Symbol) -> bool {
+    if self.refinements.is_none() { return false }
+    return self.locals[self.clause_scope ..].iter().any(|(bound, _)| *bound == name)
+  }
+  // hole cant be solved with a term that has the same hole inside,
+  // filling it in would never end
+  fn fill_hole(&mut self, ix: u32, solution: Term) -> bool {
+    let solution = self.fill_in(&solution);
+    let is_hole = |term: &Term| matches!(
+      term.kind, TermKind::Neutral { head: Head::Hole(other), .. } if other == ix);
+    let is_same_hole = matches!(
+      solution.kind,
+      TermKind::Neutral { head: Head::Hole(other), ref arguments }
+      if other == ix && arguments.is_empty());
+    if is_same_hole { return true }
+    if contains(&solution, &is_hole) { return false }
+    self.normaliser.holes[ix as usize] = Some(solution);
+    return true
+  }
+}
+
+/// Checking
+impl TypeChecker<'_> {
+  pub fn check(&mut self, node: ConcretisedNode, expected: &Term) {
+    let scope = self.locals.len();
+    self.check_node(node, expected);
+    self.locals.truncate(scope);
+  }
+  fn check_node(&mut self, node: ConcretisedNode, expected: &Term) {
+    if let Some(context) = node.implicit_context {
+      self.bind_node_context(context);
+      let node = ConcretisedNode { implicit_context: None, ..node };
+      return self.check_node(node, expected)
+    }
+    let expected = self.whnf(expected);
+    if let TermKind::Forall { context, body } = expected.kind {
+      self.bind_context(&context);
+      return self.check_node(node, &body)
+    }
+    let location = node.location;
+    match (node.kind, &expected.kind) {
+      (ConcretisedNodeRepr::Lam { rewrite_rules }, TermKind::Pi { .. }) => {
+        self.check_rewrite_rules(rewrite_rules, &expected, true)
+      },
+      (ConcretisedNodeRepr::Tuple(l, r), TermKind::Pair(lt, rt)) => {
+        self.check(unsafe { *l }, lt);
+        self.check(unsafe { *r }, rt);
+      },
+      (ConcretisedNodeRepr::Left(v), TermKind::Either(lt, _)) => {
+        self.check(unsafe { *v }, lt)
+      },
+      (ConcretisedNodeRepr::Right(v), TermKind::Either(_, rt)) => {
+        self.check(unsafe { *v }, rt)
+      },
+      (ConcretisedNodeRepr::Pt, TermKind::Singleton) => (),
+      (ConcretisedNodeRepr::Wit { premises, conclusion }, TermKind::Sigma { head, spine })
+      if premises.project_count() as usize == head.len() => {
+        let mut values = Vec::new();
+        for (ix, (name, type_)) in head.iter().enumerate() {
+          let premise = unsafe { *premises.get_ptr(ix as u32) };
+          self.check(premise, &substitute(type_, &values));
+          if let Some(name) = name { values.push((*name, Term::of_node(premise))) }
+        }
+        self.check(unsafe { *conclusion }, &substitute(spine, &values));
+      },
+      (ConcretisedNodeRepr::Lam { .. } | ConcretisedNodeRepr::Wit { .. } |
+       ConcretisedNodeRepr::Tuple(..) | ConcretisedNodeRepr::Left(_) |
+       ConcretisedNodeRepr::Right(_) | ConcretisedNodeRepr::Pt, _)
+      if !matches!(expected.kind, TermKind::Neutral { head: Head::Hole(_), .. }) => {
+        self.mismatch(&expected, location)
+      },
+      _ => {
+        let found = self.synthesise(node);
+        if !self.convertible(&found, &expected) && !self.has_unknown(&found) {
+          self.mismatch(&expected, location)
+        }
+      }
+    }
+  }
+  // Lambdas report wrong number of patterns here.
+  // For declarations that is a job of rewrite system check
+  fn check_rewrite_rules(
+    &mut self,
+    rewrite_rules: ArrayPtr<ConcretisedRewriteRule>,
+    expected: &Term,
+    report_arity: bool,
+  ) {
+    let scope = self.locals.len();
+    let outer_clause = replace(&mut self.clause_scope, scope);
+    let mut expected = self.whnf(expected);
+    if let TermKind::Forall { context, body } = expected.kind {
+      self.bind_context(&context);
+      expected = self.whnf(&body);
+    }
+    let TermKind::Pi { ref head, ref spine } = expected.kind else {
+      self.locals.truncate(scope);
+      self.clause_scope = outer_clause;
+      return
+    };
+    for ix in 0 .. rewrite_rules.project_count() {
+      let rule = unsafe { *rewrite_rules.get_ptr(ix) };
+      let count = rule.matchers.project_count() as usize;
+      if count != head.len() {
+        if report_arity {
+          self.report(Kind::ArityMismatch {
+            expected: head.len(), found: count, clause_loc: rule.location })
+        }
+        continue;
+      }
+      let rule_scope = self.locals.len();
+      let mut values = Vec::new();
+      let mut refined = Vec::new();
+      for (column, (name, type_)) in head.iter().enumerate() {
+        let pattern = unsafe { *rule.matchers.get_ptr(column as u32) };
+        let type_ = substitute(&substitute(type_, &values), &refined);
+        let value = self.check_pattern(pattern, &type_, &mut refined);
+        if let Some(name) = name { values.push((*name, value)) }
+      }
+      // binders of the clause see indices as they were refined
+      for (_, type_) in &mut self.locals[rule_scope ..] {
+        *type_ = substitute(type_, &refined)
+      }
+      let spine = substitute(&substitute(spine, &values), &refined);
+      self.check(unsafe { *rule.rhs }, &spine);
+      self.locals.truncate(rule_scope);
+    }
+    self.locals.truncate(scope);
+    self.clause_scope = outer_clause;
+  }
+  // Binds variables of a pattern and gives back the value it stands for.
+  // What constructors tell about indices is added to refinements
+  fn check_pattern(
+    &mut self, pattern: ConcretisedPattern, expected: &Term,
+    refined: &mut Vec<(Head, Term)>,
+  ) -> Term {
+    let location = pattern.location;
+    let mut expected = self.whnf(expected);
+    // column of a type that is not known yet gets the shape of the pattern
+    if let TermKind::Neutral { head: Head::Hole(ix), ref arguments } = expected.kind {
+      if arguments.is_empty() {
+        if let Some(shape) = self.shape_of_pattern(pattern, location) {
+          self.fill_hole(ix, shape.clone());
+          expected = shape;
+        }
+      }
+    }
+    let agrees = |expected: &Term| matches!(expected.kind, TermKind::Unknown);
+    let shape_of = |self_: &mut Self, is_shape: bool| {
+      if !is_shape && !agrees(&expected) { self_.mismatch(&expected, location) }
+    };
+    let unknown = Term::unknown(location);
+    let kind = match pattern.repr {
+      ConcretisedPatternKind::Wildcard => return self.fresh(location),
+      ConcretisedPatternKind::VarBinding(name) => {
+        self.locals.push((name, expected));
+        return Term::variable(Head::Local(name), location)
+      },
+      ConcretisedPatternKind::Pt => {
+        shape_of(self, matches!(expected.kind, TermKind::Singleton));
+        TermKind::Pt
+      },
+      ConcretisedPatternKind::Left(inner) => {
+        let type_ = match expected.kind {
+          TermKind::Either(ref l, _) => l.as_ref(),
+          _ => { shape_of(self, false); &unknown }
+        };
+        TermKind::Left(Box::new(self.check_pattern(unsafe { *inner }, type_, refined)))
+      },
+      ConcretisedPatternKind::Right(inner) => {
+        let type_ = match expected.kind {
+          TermKind::Either(_, ref r) => r.as_ref(),
+          _ => { shape_of(self, false); &unknown }
+        };
+        TermKind::Right(Box::new(self.check_pattern(unsafe { *inner }, type_, refined)))
+      },
+      ConcretisedPatternKind::Tuple(l, r) => {
+        let (lt, rt) = match expected.kind {
+          TermKind::Pair(ref l, ref r) => (l.as_ref(), r.as_ref()),
+          _ => { shape_of(self, false); (&unknown, &unknown) }
+        };
+        let l = self.check_pattern(unsafe { *l }, lt, refined);
+        let r = self.check_pattern(unsafe { *r }, rt, refined);
+        TermKind::Tuple(Box::new(l), Box::new(r))
+      },
+      ConcretisedPatternKind::Constructor { name, arguments } => {
+        let ctor_type = self.global_type(Symbol { location, ..name });
+        let ctor_type = self.whnf(&ctor_type);
+        let (head, result) = match ctor_type.kind {
+          TermKind::Pi { head, spine } => (head, *spine),
+          _ => (Vec::new(), ctor_type),
+        };
+        // arity was reported by rewrite system check
+        if head.len() != arguments.project_count() as usize {
+          return Term::unknown(location)
+        }
+        // result is compared first, so that holes in types of fields
+        // are filled with what is known about the column.
+        // only indices can be refined, a column whose type is
+        // a parameter cant be taken apart with a constructor
+        let result = self.whnf(&result);
+        let fits = match (&expected.kind, &result.kind) {
+          (TermKind::Neutral { head: Head::Global(data), arguments: indices },
+           TermKind::Neutral { head: Head::Global(built), arguments: built_indices })
+          if data == built => {
+            self.refinements = Some(take(refined));
+            let fits = self.all_convertible(indices, built_indices);
+            *refined = self.refinements.take().unwrap();
+            fits
+          },
+          _ => self.convertible(&expected, &result),
+        };
+        if !fits && !self.has_unknown(&result) { self.mismatch(&expected, location) }
+        let mut values = Vec::new();
+        let mut fields = Vec::new();
+        for (ix, (field, type_)) in head.iter().enumerate() {
+          let pattern = unsafe { *arguments.get_ptr(ix as u32) };
+          let type_ = substitute(&substitute(type_, &values), refined);
+          let value = self.check_pattern(pattern, &type_, refined);
+          if let Some(field) = field { values.push((*field, value.clone())) }
+          fields.push(value);
+        }
+        TermKind::Neutral { head: Head::Global(name), arguments: fields }
+      },
+    };
+    return Term::init(kind, location)
+  }
+  // type of values that a pattern of fixed shape can match
+  fn shape_of_pattern(
+    &mut self, pattern: ConcretisedPattern, location: SourceLocation
+  ) -> Option<Term> {
+    let kind = match pattern.repr {
+      ConcretisedPatternKind::Pt => TermKind::Singleton,
+      ConcretisedPatternKind::Left(_) | ConcretisedPatternKind::Right(_) => {
+        TermKind::Either(Box::new(self.hole(location)), Box::new(self.hole(location)))
+      },
+      ConcretisedPatternKind::Tuple(..) => {
+        TermKind::Pair(Box::new(self.hole(location)), Box::new(self.hole(location)))
+      },
+      _ => return None
+    };
+    return Some(Term::init(kind, location))
+  }
+}
+
+/// Synthesis
+impl TypeChecker<'_> {
+  // Works out a type of an expression. Things like lambdas
+  // cant tell their type on their own, so that is reported
+  // and they are given unknown one
+  pub fn synthesise(&mut self, node: ConcretisedNode) -> Term {
+    let scope = self.locals.len();
+    let type_ = self.synthesise_node(node);
+    self.locals.truncate(scope);
+    return type_
+  }
+  fn synthesise_node(&mut self, node: ConcretisedNode) -> Term {
+    let location = node.location;
+    if let Some(node_context) = node.implicit_context {
+      let TermKind::Forall { context, .. } = Term::of_node(node).kind else { unreachable!() };
+      self.bind_node_context(node_context);
+      let body = self.synthesise_node(ConcretisedNode { implicit_context: None, ..node });
+      if matches!(self.whnf(&body).kind, TermKind::Star) { return body }
+      let kind = TermKind::Forall { context, body: Box::new(body) };
+      return Term::init(kind, location)
+    }
+    let star = Term::star(location);
+    match node.kind {
+      ConcretisedNodeRepr::Star |
+      ConcretisedNodeRepr::Void |
+      ConcretisedNodeRepr::Singleton => star,
+      ConcretisedNodeRepr::Pt => Term::init(TermKind::Singleton, location),
+      ConcretisedNodeRepr::Pair(l, r) |
+      ConcretisedNodeRepr::Either(l, r) => {
+        self.check(unsafe { *l }, &star);
+        self.check(unsafe { *r }, &star);
+        star
+      },
+      ConcretisedNodeRepr::Arrow { head, spine, .. } |
+      ConcretisedNodeRepr::Sigma { head, spine } => {
+        for ix in 0 .. head.project_count() {
+          let (name, type_) = unsafe { *head.get_ptr(ix) };
+          self.check(type_, &star);
+          if let Some(name) = name { self.locals.push((name, Term::of_node(type_))) }
+        }
+        self.check(unsafe { *spine }, &star);
+        star
+      },
+      ConcretisedNodeRepr::Reference { name, origination } => {
+        match origination {
+          Origin::GlobalScope =>
+            self.global_type(name),
+          _ => self.local_type(name),
+        }
+      },
+      ConcretisedNodeRepr::App { root, arguments, origination } => {
+        let root_type = match origination {
+          Origin::GlobalScope =>
+            self.global_type(root),
+          _ => self.local_type(root),
+        };
+        self.synthesise_application(root_type, arguments, location)
+      },
+      ConcretisedNodeRepr::Tuple(l, r) => {
+        let l = self.synthesise(unsafe { *l });
+        let r = self.synthesise(unsafe { *r });
+        Term::init(TermKind::Pair(Box::new(l), Box::new(r)), location)
+      },
+      ConcretisedNodeRepr::Left(v) => {
+        let l = self.synthesise(unsafe { *v });
+        let r = self.hole(location);
+        Term::init(TermKind::Either(Box::new(l), Box::new(r)), location)
+      },
+      ConcretisedNodeRepr::Right(v) => {
+        let l = self.hole(location);
+        let r = self.synthesise(unsafe { *v });
+        Term::init(TermKind::Either(Box::new(l), Box::new(r)), location)
+      },
+      ConcretisedNodeRepr::Wit { .. } |
+      ConcretisedNodeRepr::Lam { .. } => {
+        self.report(Kind::UninferableType(location));
+        Term::unknown(location)
+      },
+    }
+  }
+  fn synthesise_application(
+    &mut self,
+    root_type: Term,
+    arguments: ArrayPtr<ConcretisedNode>,
+    location: SourceLocation,
+  ) -> Term {
+    let root_type = self.whnf(&root_type);
+    let TermKind::Pi { head, spine } = root_type.kind else {
+      if !root_type.is_unknown() {
+        self.report(Kind::MismatchedType {
+          type_expr: root_type.location, type_origin: self.origin_of(&root_type),
+          term_expr: location })
+      }
+      return Term::unknown(location)
+    };
+    if arguments.project_count() as usize != head.len() {
+      self.report(Kind::IncorrectArity(location));
+      return Term::unknown(location)
+    }
+    let mut values = Vec::new();
+    for (ix, (name, type_)) in head.iter().enumerate() {
+      let argument = unsafe { *arguments.get_ptr(ix as u32) };
+      self.check(argument, &substitute(type_, &values));
+      if let Some(name) = name { values.push((*name, Term::of_node(argument))) }
+    }
+    return substitute(&spine, &values)
+  }
+}
@@ -113,6 +113,9 @@ pub struct WorkGroupSharedData {
   delegated_executors: Vec<JoinHandle<()>>,
   task_queue: WorkQueue<Task>,
   was_signaled_to_stop: AtomicBool,
+  // executors look at each other through delegated_executors,
+  // which is only complete after the last of them was spawned
+  executors_are_ready: AtomicBool,
   liveness_count: AtomicU16,
   initiator_thread: Thread,
 }
@@ -127,11 +130,16 @@ fn task_processor_runloop
   threads: *mut Vec<JoinHandle<()>>,
   liveness_count: &AtomicU16,
   initiator_thread_handle: &Thread,
+  ready_flag_ref: &AtomicBool,
 ) {
   assert!(
     TASK_CACHE_SIZE <= u8::MAX as usize,
     "Too much of cache is bad for anyone!");
 
+  while !ready_flag_ref.load(Ordering::Acquire) {
+    thread::yield_now();
+  }
+
   let mut task_frame_allocator =
     GranularSlabAllocator::init();
   let mut task_cache: [MaybeUninit<Task> ; TASK_CACHE_SIZE] =
@@ -401,6 +409,7 @@ impl WorkGroup {
         Box::<MaybeUninit<WorkGroupSharedData>>::new(MaybeUninit::uninit());
       let data = &mut *wg.as_mut_ptr() ;
       data.was_signaled_to_stop.store(false, Ordering::Relaxed);
+      data.executors_are_ready.store(false, Ordering::Relaxed);
       data.liveness_count.store(core_count, Ordering::Relaxed);
       let q_ptr = addr_of_mut!(data.task_queue);
       let mut threads = Vec::<JoinHandle<()>>::new();
@@ -422,15 +431,17 @@ impl WorkGroup {
         let threads_ptr = addr_of_mut!(data.delegated_executors) as usize;
         let lc = &data.liveness_count;
         let init_thread = &data.initiator_thread;
+        let ready_flag_ref = &data.executors_are_ready;
         let thread = spawn(move || {
           core_affinity::set_for_current(core_id);
           task_processor_runloop::<4>(
             stop_flag_ref, queue_ref,
-            threads_ptr as *mut _, lc, init_thread);
+            threads_ptr as *mut _, lc, init_thread, ready_flag_ref);
         });
         threads.push(thread);
       }
       addr_of_mut!(data.delegated_executors).write(threads);
+      data.executors_are_ready.store(true, Ordering::Release);
       return WorkGroup(transmute(wg));
     } }
   pub fn await_completion(self) {
@@ -1,48 +1,59 @@
 use std::{
-  marker::PhantomData, intrinsics::transmute, hash::Hash
+  marker::PhantomData, intrinsics::transmute, hash::Hash,
+  mem::{size_of, align_of},
 };
 
 use crate::{
   support_structures::{homemade_slice::Slice, tagged_ptr::TaggedPtr},
 };
 
-use super::raw_syntax_nodes::SourceLocation;
+use super::{
+  raw_syntax_nodes::{SourceLocation, FileId},
+  symbol_interner::{InternedName, global_interner},
+};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Symbol {
-  pub chars_ptr: Slice<u8>,
-  pub location: SourceLocation
+  pub location: SourceLocation,
+  pub name: InternedName,
+  pub origin: FileId,
 }
 
 impl Hash for Symbol {
   fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-    let data = self.materialise_name();
-    state.write(data.as_bytes())
+    self.name.hash(state)
   }
 }
 impl Eq for Symbol {}
 impl PartialEq for Symbol {
   fn eq(&self, other: &Self) -> bool {
-    // fix by interning
-    let l = self.materialise_name();
-    let r = other.materialise_name();
-    return l == r
+    return self.name == other.name
   }
 }
 
 impl Symbol {
-  pub fn materialise_name<'a>(&self) -> &'a str {
-    let Slice { source_data, span } = self.chars_ptr;
-    let slice = unsafe {
-      std::slice::from_raw_parts(source_data, span as usize)
-    };
-    let SourceLocation { primary_offset, secondary_offset } = self.location;
-    let slice = &slice[primary_offset as usize .. secondary_offset as usize ];
-    let str = std::str::from_utf8(slice).unwrap();
-    return str
+  pub fn materialise_name(&self) -> &'static str {
+    return global_interner().resolve(self.name)
   }
 }
 
+// Short arrays keep their length in the tag.
+// Longer ones put SPILLED_LENGTH there and keep the actual length
+// in a word right in front of the first item
+pub const SPILLED_LENGTH : u8 = u8::MAX;
+
+// How many bytes must be reserved in front of an array of given length
+pub fn length_prefix_size<T>(count: usize) -> usize {
+  if count < SPILLED_LENGTH as usize { return 0 }
+  return size_of::<u64>().max(align_of::<T>())
+}
+pub(crate) unsafe fn write_length_prefix<T>(items: *mut T, count: usize) {
+  items.cast::<u64>().sub(1).write(count as u64)
+}
+pub(crate) unsafe fn read_length_prefix<T>(items: *mut T) -> usize {
+  return items.cast::<u64>().sub(1).read() as usize
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct ArrayMtd {
   pub length: u8
@@ -51,17 +62,22 @@ pub struct ArrayMtd {
 #[derive(Debug, Clone, Copy)]
 pub struct ArrayPtr<T>(pub TaggedPtr<ArrayMtd, T>, PhantomData<T>);
 impl <T> ArrayPtr<T> {
-  pub fn init(ptr: *mut T, count: u8) -> Self {
-    let mtd = ArrayMtd { length: count };
+  // arrays of SPILLED_LENGTH items or more must have
+  // their length already written in front of them
+  pub fn init(ptr: *mut T, count: usize) -> Self {
+    let length = count.min(SPILLED_LENGTH as usize) as u8;
+    let mtd = ArrayMtd { length };
     return Self(TaggedPtr::init_from_ptr(mtd, ptr), PhantomData);
   }
-  pub fn project_count(&self) -> u8 {
-    self.0.project_tag().length
+  pub fn project_count(&self) -> u32 {
+    let length = self.0.project_tag().length;
+    if length != SPILLED_LENGTH { return length as u32 }
+    return unsafe { read_length_prefix(self.project_ptr()) as u32 }
   }
   pub fn project_ptr(&self) -> *mut T {
     self.0.project_ptr()
   }
-  pub fn get_ptr(&self, index: u8) -> *mut T {
+  pub fn get_ptr(&self, index: u32) -> *mut T {
     let ptr = self.project_ptr();
     return unsafe { ptr.add(index as usize) };
   }
@@ -133,6 +149,13 @@ pub struct RawRewriteRule {
   pub location: SourceLocation
 }
 
+// `| name : type` line of a data declaration
+#[derive(Debug, Clone, Copy)]
+pub struct RawConstructor {
+  pub name: Symbol,
+  pub given_type: *mut RawNode,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum DeclKind {
   RawMapping {
@@ -154,6 +177,71 @@ pub enum DeclKind {
     name: Symbol,
     given_type : *mut ConcretisedNode,
     value: *mut ConcretisedNode
+  },
+  RawData {
+    name: Symbol,
+    given_type: *mut RawNode,
+    constructors: ArrayPtr<RawConstructor>
+  },
+  WellScopedData {
+    name: Symbol,
+    given_type: *mut ConcretisedNode,
+    constructors: ArrayPtr<ConcretisedConstructor>
+  }
+}
+
+// Run of `--|` lines right above a declaration
+#[derive(Debug, Clone, Copy)]
+pub struct DocComment {
+  pub chars_ptr: Slice<u8>,
+  pub location: SourceLocation
+}
+impl DocComment {
+  pub fn materialise_text(&self) -> String {
+    let Slice { source_data, span } = self.chars_ptr;
+    let slice = unsafe {
+      std::slice::from_raw_parts(source_data, span as usize)
+    };
+    let SourceLocation { primary_offset, secondary_offset } = self.location;
+    let slice = &slice[primary_offset as usize .. secondary_offset as usize ];
+    let str = std::str::from_utf8(slice).unwrap();
+    let lines = str.lines().map(|line| {
+      let line = line.trim_start().trim_start_matches("--|");
+      return line.strip_prefix(' ').unwrap_or(line).trim_end()
+    }).collect::<Vec<_>>();
+    return lines.join("\n")
+  }
+}
+
+// `module M` and `import` lines that precede declarations of a file.
+// A file without a module line belongs to the unnamed module
+#[derive(Debug, Clone, Default)]
+pub struct ModuleHeader {
+  pub name: Option<Symbol>,
+  pub imports: Vec<Import>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Import {
+  pub module: Symbol,
+  pub alias: Option<Symbol>,
+  pub selection: ImportSelection,
+  pub location: SourceLocation,
+}
+
+#[derive(Debug, Clone)]
+pub enum ImportSelection {
+  Everything,
+  Only(Vec<Symbol>),
+  Hiding(Vec<Symbol>),
+}
+impl ImportSelection {
+  pub fn admits(&self, name: Symbol) -> bool {
+    match self {
+      ImportSelection::Everything => true,
+      ImportSelection::Only(names) => names.contains(&name),
+      ImportSelection::Hiding(names) => !names.contains(&name),
+    }
   }
 }
 
@@ -161,6 +249,66 @@ pub enum DeclKind {
 pub struct Declaration {
   pub repr: DeclKind,
   pub participate_in_cycle_formation: bool,
+  // Set when scope analysis found problems in this declaration.
+  // Trees of such declaration are only partially concretised
+  // and must not be inspected by subsequent passes.
+  pub is_malformed: bool,
+  pub doc_comment: Option<DocComment>,
+}
+impl Declaration {
+  pub fn project_name(&self) -> Symbol {
+    match self.repr {
+      DeclKind::RawMapping { name, .. } |
+      DeclKind::RawDefinition { name, .. } |
+      DeclKind::WellScopedMapping { name, .. } |
+      DeclKind::WellScopedDefinition { name, .. } |
+      DeclKind::RawData { name, .. } |
+      DeclKind::WellScopedData { name, .. } => name,
+    }
+  }
+  // names of constructors, if this declares a data type
+  pub fn project_constructor_names(&self) -> Vec<Symbol> {
+    match self.repr {
+      DeclKind::RawData { constructors, .. } => {
+        return (0 .. constructors.project_count())
+          .map(|ix| unsafe { *constructors.get_ptr(ix) }.name).collect()
+      },
+      DeclKind::WellScopedData { constructors, .. } => {
+        return (0 .. constructors.project_count())
+          .map(|ix| unsafe { *constructors.get_ptr(ix) }.name).collect()
+      },
+      _ => return Vec::new()
+    }
+  }
+  pub fn find_constructor(&self, name: Symbol) -> Option<ConcretisedConstructor> {
+    let DeclKind::WellScopedData { constructors, .. } = self.repr else { return None };
+    return (0 .. constructors.project_count())
+      .map(|ix| unsafe { *constructors.get_ptr(ix) })
+      .find(|constructor| constructor.name == name)
+  }
+  // line `@partial` in doc comment opts out of termination check
+  pub fn is_marked_partial(&self) -> bool {
+    let Some(doc_comment) = self.doc_comment else { return false };
+    return doc_comment.materialise_text().lines().any(|line| line.trim() == "@partial")
+  }
+  pub fn rename(&mut self, new_name: Symbol) {
+    match self.repr {
+      DeclKind::RawMapping { ref mut name, .. } |
+      DeclKind::RawDefinition { ref mut name, .. } |
+      DeclKind::WellScopedMapping { ref mut name, .. } |
+      DeclKind::WellScopedDefinition { ref mut name, .. } |
+      DeclKind::RawData { ref mut name, .. } |
+      DeclKind::WellScopedData { ref mut name, .. } => *name = new_name,
+    }
+  }
+  // constructors are named in place, like the declaration itself
+  pub fn rename_constructors(&mut self, mut new_name: impl FnMut(Symbol) -> Symbol) {
+    let DeclKind::RawData { constructors, .. } = self.repr else { return };
+    for ix in 0 .. constructors.project_count() {
+      let constructor = unsafe { &mut *constructors.get_ptr(ix) };
+      constructor.name = new_name(constructor.name)
+    }
+  }
 }
 
 
@@ -240,6 +388,12 @@ pub struct ConcretisedRewriteRule {
   pub location: SourceLocation
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct ConcretisedConstructor {
+  pub name: Symbol,
+  pub given_type: *mut ConcretisedNode,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct ConcretisedPattern {
   pub repr: ConcretisedPatternKind,
@@ -253,7 +407,12 @@ pub enum ConcretisedPatternKind {
   Left(*mut ConcretisedPattern),
   Right(*mut ConcretisedPattern),
   Tuple(*mut ConcretisedPattern, *mut ConcretisedPattern),
-  VarBinding(Symbol)
+  VarBinding(Symbol),
+  // constructor of a data type, by its canonical name
+  Constructor {
+    name: Symbol,
+    arguments: ArrayPtr<ConcretisedPattern>
+  }
 }
 
 
@@ -1,4 +1,6 @@
 pub mod raw_syntax_nodes;
+pub mod source_map;
+pub mod symbol_interner;
 
 pub mod more_text_rendering;
 pub mod better_nodes;
\ No newline at end of file
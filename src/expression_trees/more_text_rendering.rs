@@ -1,81 +1,510 @@
-use crate::support_structures::{homemade_slice::Slice, raw_array_iter::RawArrayIter};
+use crate::support_structures::raw_array_iter::RawArrayIter;
 
-use super::{
-  better_nodes::{
-    RawNode, RawNodeRepr, Symbol
-  },
-  raw_syntax_nodes::SourceLocation
+use super::better_nodes::{
+  RawNode, RawNodeRepr, Symbol, RawImplicitCtx, RawRewriteRule,
+  RawPattern, RawPatternKind, ConcretisedNode, ConcretisedNodeRepr,
+  ConcretisedImplicitCtx, ConcretisedRewriteRule, ConcretisedPattern,
+  ConcretisedPatternKind, Declaration, DeclKind, ArrayPtr,
+  ModuleHeader, ImportSelection,
 };
 
 
+pub const DEFAULT_LINE_WIDTH : usize = 80;
+
+// Output of every printer here is accepted by the parser
+// and yields the same tree back.
+// The grammar is indentation sensitive: a line that continues
+// an expression has to be indented deeper than the line where
+// enclosing clause or item begins. Every break point below
+// is nested at least two columns in relative to its construct,
+// which is enough to keep the parser on track.
 
 pub fn render_expr_tree(expr: RawNode, output: &mut String) {
-  match expr.kind {
-    RawNodeRepr::Star => {
-      output.push('*')
-    },
-    RawNodeRepr::Ref(symbol) => {
-      write_symbol(symbol, output)
-    },
-    RawNodeRepr::App { root, arguments } => {
-      output.push_str("(");
-      write_symbol(root, output);
-      output.push_str(" [");
-      for node in RawArrayIter::from_array_ptr(arguments) {
-        render_expr_tree(node, output);
-        output.push_str(", ");
+  output.push_str(&render_raw_expr(expr, DEFAULT_LINE_WIDTH))
+}
+
+pub fn render_raw_expr(expr: RawNode, width: usize) -> String {
+  let mut output = String::new();
+  layout(&raw_expr_doc(expr), width, &mut output);
+  return output
+}
+
+pub fn render_concretised_expr(expr: ConcretisedNode, width: usize) -> String {
+  let mut output = String::new();
+  layout(&concretised_expr_doc(expr), width, &mut output);
+  return output
+}
+
+pub fn render_raw_pattern(pattern: RawPattern) -> String {
+  let mut output = String::new();
+  write_raw_pattern(pattern, false, &mut output);
+  return output
+}
+
+pub fn render_concretised_pattern(pattern: ConcretisedPattern) -> String {
+  let mut output = String::new();
+  write_concretised_pattern(pattern, false, &mut output);
+  return output
+}
+
+// Declaration together with its doc comment.
+// Malformed declarations have partially concretised trees,
+// so they must not be given here
+pub fn render_declaration(decl: &Declaration, width: usize) -> String {
+  let mut output = String::new();
+  layout(&declaration_doc(decl), width, &mut output);
+  return output
+}
+// header lines cant be broken, so they are printed as they are.
+// imports are set apart from the module line by a blank line
+pub fn render_module_header(header: &ModuleHeader) -> String {
+  let mut lines = Vec::new();
+  if let Some(name) = header.name {
+    lines.push(format!("module {}", name.materialise_name()));
+    if !header.imports.is_empty() { lines.push(String::new()) }
+  }
+  for import in &header.imports {
+    let mut line = format!("import {}", import.module.materialise_name());
+    if let Some(alias) = import.alias {
+      line.push_str(" as ");
+      line.push_str(alias.materialise_name());
+    }
+    let names = |names: &Vec<Symbol>| {
+      let names =
+        names.iter().map(|name| name.materialise_name()).collect::<Vec<_>>();
+      return format!("({})", names.join(", "))
+    };
+    match import.selection {
+      ImportSelection::Everything => (),
+      ImportSelection::Only(ref only) => {
+        line.push(' ');
+        line.push_str(&names(only));
+      },
+      ImportSelection::Hiding(ref hidden) => {
+        line.push_str(" hiding ");
+        line.push_str(&names(hidden));
+      },
+    }
+    lines.push(line);
+  }
+  return lines.join("\n")
+}
+
+
+/// Layout engine
+// Wadler style documents. A group is printed on one line if it fits,
+// otherwise each of its line breaks starts a new line
+enum Doc {
+  Text(String),
+  // becomes `flat` when group fits on a line
+  Line { flat: &'static str },
+  HardLine,
+  Nest(u32, Box<Doc>),
+  Group(Box<Doc>),
+  Seq(Vec<Doc>),
+}
+
+fn text(str: &str) -> Doc { Doc::Text(str.to_string()) }
+fn line() -> Doc { Doc::Line { flat: " " } }
+fn softline() -> Doc { Doc::Line { flat: "" } }
+fn nest(depth: u32, doc: Doc) -> Doc { Doc::Nest(depth, Box::new(doc)) }
+fn group(doc: Doc) -> Doc { Doc::Group(Box::new(doc)) }
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode { Flat, Break }
+
+fn layout(doc: &Doc, width: usize, output: &mut String) {
+  let mut column = 0;
+  // indentation is written lazily, so empty lines get no trailing spaces
+  let mut pending_indent = None;
+  let mut stack = vec![(0, Mode::Break, doc)];
+  while let Some((indent, mode, doc)) = stack.pop() {
+    match doc {
+      Doc::Text(str) => {
+        if let Some(indent) = pending_indent.take() {
+          output.push_str(&" ".repeat(indent as usize));
+        }
+        output.push_str(str);
+        column += str.chars().count();
+      },
+      Doc::Line { flat } if mode == Mode::Flat => {
+        output.push_str(flat);
+        column += flat.len();
+      },
+      Doc::Line { .. } | Doc::HardLine => {
+        output.push('\n');
+        pending_indent = Some(indent);
+        column = indent as usize;
+      },
+      Doc::Nest(depth, inner) => {
+        stack.push((indent + depth, mode, inner))
+      },
+      Doc::Group(inner) => {
+        let room = width as isize - column as isize;
+        let fits =
+          mode == Mode::Flat || fits(room, (indent, Mode::Flat, inner), &stack);
+        stack.push((indent, if fits { Mode::Flat } else { Mode::Break }, inner))
+      },
+      Doc::Seq(items) => {
+        for item in items.iter().rev() { stack.push((indent, mode, item)) }
+      },
+    }
+  }
+}
+
+// checks that everything up to the next line break fits into given room
+fn fits(
+  mut room: isize,
+  first: (u32, Mode, &Doc),
+  rest: &[(u32, Mode, &Doc)]
+) -> bool {
+  let mut pending = vec![first];
+  let mut rest_index = rest.len();
+  loop {
+    if room < 0 { return false }
+    let (indent, mode, doc) = match pending.pop() {
+      Some(item) => item,
+      None => {
+        if rest_index == 0 { return true }
+        rest_index -= 1;
+        rest[rest_index]
       }
-      output.push_str("])")
+    };
+    match doc {
+      Doc::Text(str) => room -= str.chars().count() as isize,
+      Doc::Line { flat } => {
+        if mode == Mode::Break { return true }
+        room -= flat.len() as isize
+      },
+      Doc::HardLine => return mode == Mode::Break,
+      Doc::Nest(depth, inner) => pending.push((indent + depth, mode, inner)),
+      Doc::Group(inner) => pending.push((indent, mode, inner)),
+      Doc::Seq(items) => {
+        for item in items.iter().rev() { pending.push((indent, mode, item)) }
+      },
+    }
+  }
+}
+
+
+/// Shapes shared by both kinds of trees
+fn parens(doc: Doc) -> Doc {
+  Doc::Seq(vec![text("("), doc, text(")")])
+}
+
+fn app_doc(root: &str, args: Vec<Doc>) -> Doc {
+  let mut tail = Vec::new();
+  for arg in args {
+    tail.push(line());
+    tail.push(arg);
+  }
+  return group(Doc::Seq(vec![text(root), nest(2, Doc::Seq(tail))]))
+}
+
+fn comma_separated(items: Vec<Doc>) -> Doc {
+  let mut seq = Vec::new();
+  for (ix, item) in items.into_iter().enumerate() {
+    if ix != 0 {
+      seq.push(text(","));
+      seq.push(line());
+    }
+    seq.push(item);
+  }
+  return Doc::Seq(seq)
+}
+
+fn ctx_doc(items: Vec<(Symbol, Option<Doc>)>) -> Doc {
+  let items = items.into_iter().map(|(name, type_)| {
+    match type_ {
+      Some(type_) => Doc::Seq(vec![text(name.materialise_name()), text(" : "), type_]),
+      None => text(name.materialise_name()),
+    }
+  }).collect();
+  return group(Doc::Seq(vec![
+    text("{"), nest(2, Doc::Seq(vec![softline(), comma_separated(items)])),
+    softline(), text("}")]))
+}
+
+fn lift_doc(items: Vec<(Option<Symbol>, Doc)>, arrow: &str, spine: Doc) -> Doc {
+  let items = items.into_iter().map(|(name, expr)| {
+    match name {
+      Some(name) => Doc::Seq(vec![text(name.materialise_name()), text(" : "), expr]),
+      None => expr,
+    }
+  }).collect();
+  let head = group(Doc::Seq(vec![
+    text("("), nest(2, Doc::Seq(vec![softline(), comma_separated(items)])),
+    softline(), text(")")]));
+  return Doc::Seq(vec![head, text(" "), text(arrow), text(" "), spine])
+}
+
+fn wit_doc(premises: Vec<Doc>, conclusion: Doc) -> Doc {
+  return group(Doc::Seq(vec![
+    text("[|"),
+    nest(2, Doc::Seq(vec![
+      line(), comma_separated(premises), text(";"), line(), conclusion])),
+    line(), text("|]")]))
+}
+
+fn clause_doc(patterns: Vec<String>, rhs: Doc) -> Doc {
+  return Doc::Seq(vec![
+    text("| "), text(&patterns.join(", ")), text(" =>"),
+    nest(2, group(Doc::Seq(vec![line(), rhs])))])
+}
+
+fn constructor_doc(name: Symbol, type_: Doc) -> Doc {
+  return Doc::Seq(vec![
+    text("| "), text(name.materialise_name()), text(" : "), nest(2, type_)])
+}
+
+fn lambda_doc(clauses: Vec<Doc>) -> Doc {
+  let mut body = Vec::new();
+  for clause in clauses {
+    body.push(line());
+    body.push(clause);
+  }
+  return group(Doc::Seq(vec![
+    text("\\{"), nest(2, Doc::Seq(body)), line(), text("}")]))
+}
+
+// a context can only be put in front of a name or a parenthesis
+fn with_ctx(ctx: Option<Doc>, body: Doc, body_is_bracketed: bool) -> Doc {
+  let Some(ctx) = ctx else { return body };
+  let body = if body_is_bracketed { parens(body) } else { body };
+  return Doc::Seq(vec![ctx, text(" "), body])
+}
+
+fn collect<T: Clone, K>(array: ArrayPtr<T>, fun: impl FnMut(T) -> K) -> Vec<K> {
+  return RawArrayIter::from_array_ptr(array).map(fun).collect()
+}
+
+
+/// Raw trees
+fn raw_expr_doc(expr: RawNode) -> Doc {
+  let needs_parens_after_ctx = matches!(
+    expr.kind,
+    RawNodeRepr::Star | RawNodeRepr::Wit { .. } | RawNodeRepr::Lam { .. });
+  let body = match expr.kind {
+    RawNodeRepr::Star => text("*"),
+    RawNodeRepr::Ref(symbol) => text(symbol.materialise_name()),
+    RawNodeRepr::App { root, arguments } => {
+      app_doc(root.materialise_name(), collect(arguments, raw_arg_doc))
     },
     RawNodeRepr::Wit { premises, conclusion } => {
-      output.push_str("[| ");
-      for node in RawArrayIter::from_array_ptr(premises) {
-        render_expr_tree(node, output);
-        output.push(',');
-      }
-      output.push_str(" ; ");
-      render_expr_tree(unsafe { *conclusion }, output);
-      output.push_str(" |]");
+      wit_doc(collect(premises, raw_expr_doc), raw_expr_doc(unsafe { *conclusion }))
     },
     RawNodeRepr::Fun { head, spine } => {
-      output.push('(');
-      for (name, expr) in RawArrayIter::from_array_ptr(head) {
-        if let Some(symbol) = name {
-          write_symbol(symbol, output);
-          output.push_str(" : ");
-        }
-        render_expr_tree(expr, output);
-        output.push_str(", ")
-      }
-      output.push_str(") -> ");
-      render_expr_tree(unsafe { *spine }, output);
+      let items = collect(head, |(name, expr)| (name, raw_expr_doc(expr)));
+      lift_doc(items, "->", raw_expr_doc(unsafe { *spine }))
     },
     RawNodeRepr::Sigma { head, spine } => {
-      output.push('(');
-      for (name, expr) in RawArrayIter::from_array_ptr(head) {
-        if let Some(symbol) = name {
-          write_symbol(symbol, output);
-          output.push_str(" : ");
-        }
-        render_expr_tree(expr, output);
-        output.push_str(", ")
-      }
-      output.push_str(") |- ");
-      render_expr_tree(unsafe { *spine }, output);
+      let items = collect(head, |(name, expr)| (name, raw_expr_doc(expr)));
+      lift_doc(items, "|-", raw_expr_doc(unsafe { *spine }))
     },
     RawNodeRepr::Lam { rewrite_rules } => {
-      output.push_str("\\{ ... }")
+      lambda_doc(collect(rewrite_rules, raw_clause_doc))
+    },
+  };
+  let ctx = expr.implicit_context.map(raw_ctx_doc);
+  return with_ctx(ctx, body, needs_parens_after_ctx)
+}
+
+fn raw_arg_doc(arg: RawNode) -> Doc {
+  if let (RawNodeRepr::Ref(symbol), None) = (arg.kind, arg.implicit_context) {
+    return text(symbol.materialise_name())
+  }
+  return parens(raw_expr_doc(arg))
+}
+
+fn raw_ctx_doc(ctx: RawImplicitCtx) -> Doc {
+  return ctx_doc(collect(ctx, |(name, type_)| (name, type_.map(raw_expr_doc))))
+}
+
+fn raw_clause_doc(clause: RawRewriteRule) -> Doc {
+  let patterns = collect(clause.matchers, render_raw_pattern);
+  return clause_doc(patterns, raw_expr_doc(unsafe { *clause.lhs }))
+}
+
+fn write_raw_pattern(pattern: RawPattern, is_arg: bool, output: &mut String) {
+  match pattern.repr {
+    RawPatternKind::Wildcard => output.push('_'),
+    RawPatternKind::Mono(symbol) => {
+      output.push_str(symbol.materialise_name())
+    },
+    RawPatternKind::Compound { head, subexpressions } => {
+      if is_arg { output.push('(') }
+      output.push_str(head.materialise_name());
+      for sub in RawArrayIter::from_array_ptr(subexpressions) {
+        output.push(' ');
+        write_raw_pattern(sub, true, output);
+      }
+      if is_arg { output.push(')') }
+    },
+  }
+}
+
+
+/// Concretised trees
+// Builtins are printed back with the names they were recognised by
+fn concretised_expr_doc(expr: ConcretisedNode) -> Doc {
+  let needs_parens_after_ctx = matches!(
+    expr.kind,
+    ConcretisedNodeRepr::Star | ConcretisedNodeRepr::Wit { .. } |
+    ConcretisedNodeRepr::Lam { .. });
+  let pair = |name: &str, l: *mut ConcretisedNode, r: *mut ConcretisedNode| {
+    let args = unsafe { vec![concretised_arg_doc(*l), concretised_arg_doc(*r)] };
+    return app_doc(name, args)
+  };
+  let body = match expr.kind {
+    ConcretisedNodeRepr::Star => text("*"),
+    ConcretisedNodeRepr::Void => text("Void"),
+    ConcretisedNodeRepr::Singleton => text("Dot"),
+    ConcretisedNodeRepr::Pt => text("pt"),
+    ConcretisedNodeRepr::Reference { name, .. } => text(name.materialise_name()),
+    ConcretisedNodeRepr::App { root, arguments, .. } => {
+      app_doc(root.materialise_name(), collect(arguments, concretised_arg_doc))
+    },
+    ConcretisedNodeRepr::Pair(l, r) => pair("Pair", l, r),
+    ConcretisedNodeRepr::Either(l, r) => pair("Either", l, r),
+    ConcretisedNodeRepr::Tuple(l, r) => pair("two", l, r),
+    ConcretisedNodeRepr::Left(v) => {
+      app_doc("inl", vec![concretised_arg_doc(unsafe { *v })])
+    },
+    ConcretisedNodeRepr::Right(v) => {
+      app_doc("inr", vec![concretised_arg_doc(unsafe { *v })])
+    },
+    ConcretisedNodeRepr::Wit { premises, conclusion } => {
+      wit_doc(
+        collect(premises, concretised_expr_doc),
+        concretised_expr_doc(unsafe { *conclusion }))
+    },
+    ConcretisedNodeRepr::Arrow { head, spine, .. } => {
+      let items = collect(head, |(name, expr)| (name, concretised_expr_doc(expr)));
+      lift_doc(items, "->", concretised_expr_doc(unsafe { *spine }))
     },
+    ConcretisedNodeRepr::Sigma { head, spine } => {
+      let items = collect(head, |(name, expr)| (name, concretised_expr_doc(expr)));
+      lift_doc(items, "|-", concretised_expr_doc(unsafe { *spine }))
+    },
+    ConcretisedNodeRepr::Lam { rewrite_rules } => {
+      lambda_doc(collect(rewrite_rules, concretised_clause_doc))
+    },
+  };
+  let ctx = expr.implicit_context.map(concretised_ctx_doc);
+  return with_ctx(ctx, body, needs_parens_after_ctx)
+}
+
+fn concretised_arg_doc(arg: ConcretisedNode) -> Doc {
+  let is_name = matches!(
+    arg.kind,
+    ConcretisedNodeRepr::Reference { .. } | ConcretisedNodeRepr::Void |
+    ConcretisedNodeRepr::Singleton | ConcretisedNodeRepr::Pt);
+  if is_name && arg.implicit_context.is_none() {
+    return concretised_expr_doc(arg)
   }
+  return parens(concretised_expr_doc(arg))
+}
+
+fn concretised_ctx_doc(ctx: ConcretisedImplicitCtx) -> Doc {
+  return ctx_doc(collect(ctx, |(name, type_)| (name, type_.map(concretised_expr_doc))))
 }
 
-fn write_symbol(symbol: Symbol, output: &mut String) {
-  let Slice { source_data, span } = symbol.chars_ptr;
-  let slice = unsafe {
-    std::slice::from_raw_parts(source_data, span as usize)
+fn concretised_clause_doc(clause: ConcretisedRewriteRule) -> Doc {
+  let patterns = collect(clause.matchers, render_concretised_pattern);
+  return clause_doc(patterns, concretised_expr_doc(unsafe { *clause.rhs }))
+}
+
+fn write_concretised_pattern(
+  pattern: ConcretisedPattern, is_arg: bool, output: &mut String
+) {
+  let compound = |head: &str, subs: &[*mut ConcretisedPattern], output: &mut String| {
+    if is_arg { output.push('(') }
+    output.push_str(head);
+    for sub in subs {
+      output.push(' ');
+      write_concretised_pattern(unsafe { **sub }, true, output);
+    }
+    if is_arg { output.push(')') }
   };
-  let SourceLocation { primary_offset, secondary_offset } = symbol.location;
-  let slice = &slice[primary_offset as usize .. secondary_offset as usize ];
-  let str = std::str::from_utf8(slice).unwrap();
-  output.push_str(str);
-}
\ No newline at end of file
+  match pattern.repr {
+    ConcretisedPatternKind::Wildcard => output.push('_'),
+    ConcretisedPatternKind::Pt => output.push_str("pt"),
+    ConcretisedPatternKind::VarBinding(symbol) => {
+      output.push_str(symbol.materialise_name())
+    },
+    ConcretisedPatternKind::Left(v) => compound("inl", &[v], output),
+    ConcretisedPatternKind::Right(v) => compound("inr", &[v], output),
+    ConcretisedPatternKind::Tuple(l, r) => compound("two", &[l, r], output),
+    ConcretisedPatternKind::Constructor { name, arguments } => {
+      let args = (0 .. arguments.project_count())
+        .map(|ix| arguments.get_ptr(ix))
+        .collect::<Vec<_>>();
+      if args.is_empty() { output.push_str(name.materialise_name()) }
+      else { compound(name.materialise_name(), &args, output) }
+    },
+  }
+}
+
+
+/// Declarations
+fn declaration_doc(decl: &Declaration) -> Doc {
+  let mut seq = Vec::new();
+  if let Some(doc_comment) = decl.doc_comment {
+    for line in doc_comment.materialise_text().lines() {
+      let line =
+        if line.is_empty() { "--|".to_string() } else { format!("--| {line}") };
+      seq.push(Doc::Text(line));
+      seq.push(Doc::HardLine);
+    }
+  }
+  let name = decl.project_name();
+  if let DeclKind::RawData { .. } | DeclKind::WellScopedData { .. } = decl.repr {
+    seq.push(text("data "));
+  }
+  seq.push(text(name.materialise_name()));
+  seq.push(text(" : "));
+  let (type_, body) = match decl.repr {
+    DeclKind::RawMapping { given_type, rewrite_rules, .. } => {
+      let type_ = raw_expr_doc(unsafe { *given_type });
+      (type_, Err(collect(rewrite_rules, raw_clause_doc)))
+    },
+    DeclKind::RawDefinition { given_type, value, .. } => {
+      let type_ = raw_expr_doc(unsafe { *given_type });
+      (type_, Ok(raw_expr_doc(unsafe { *value })))
+    },
+    DeclKind::WellScopedMapping { given_type, rewrite_rules, .. } => {
+      let type_ = concretised_expr_doc(unsafe { *given_type });
+      (type_, Err(collect(rewrite_rules, concretised_clause_doc)))
+    },
+    DeclKind::WellScopedDefinition { given_type, value, .. } => {
+      let type_ = concretised_expr_doc(unsafe { *given_type });
+      (type_, Ok(concretised_expr_doc(unsafe { *value })))
+    },
+    DeclKind::RawData { given_type, constructors, .. } => {
+      let type_ = raw_expr_doc(unsafe { *given_type });
+      (type_, Err(collect(constructors, |ctor| {
+        constructor_doc(ctor.name, raw_expr_doc(unsafe { *ctor.given_type }))
+      })))
+    },
+    DeclKind::WellScopedData { given_type, constructors, .. } => {
+      let type_ = concretised_expr_doc(unsafe { *given_type });
+      (type_, Err(collect(constructors, |ctor| {
+        constructor_doc(ctor.name, concretised_expr_doc(unsafe { *ctor.given_type }))
+      })))
+    },
+  };
+  match body {
+    Ok(value) => {
+      seq.push(group(nest(2, Doc::Seq(vec![
+        type_, line(), text("= "), value]))));
+    },
+    Err(clauses) => {
+      seq.push(nest(2, type_));
+      for clause in clauses {
+        seq.push(Doc::HardLine);
+        seq.push(clause);
+      }
+    },
+  }
+  return Doc::Seq(seq)
+}
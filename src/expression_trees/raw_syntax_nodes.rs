@@ -4,7 +4,8 @@ use std::{intrinsics::transmute};
 use crate::parser::{
   node_allocator::SomeEntangledPtr, };
 
-use super::better_nodes::Symbol;
+use super::better_nodes::{
+  Symbol, SPILLED_LENGTH, read_length_prefix};
 
 pub trait Locatable {
   type Location: Eq + Copy;
@@ -17,6 +18,16 @@ pub struct SourceLocation {
   pub secondary_offset: u32
 }
 
+// Index of a source file within single elaboration session
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FileId(pub u32);
+
+impl FileId {
+  // origin of text that comes from no file at all,
+  // like input of the repl. Never used as an index of a file
+  pub const DETACHED: FileId = FileId(u32::MAX);
+}
+
 #[repr(u8)] #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum RawKind {
   Ref,
@@ -42,12 +53,14 @@ impl ExprPtr {
   }
   pub fn init_null() -> Self { Self(0) }
   pub fn is_null(&self) -> bool { self.0 == 0 }
+  // when there are SPILLED_LENGTH args or more,
+  // their count must be already written in front of ptr
   pub fn init_counted_node(
     kind: RawKind, ptr: *mut (), arg_num:usize
   ) -> Self {
-    if arg_num > u8::MAX as usize { panic!("Too many args have been given") }
+    let count = arg_num.min(SPILLED_LENGTH as usize) as u8;
     let mut val = (ptr as u64) << 8;
-    val += arg_num as u64;
+    val += count as u64;
     val = val << 8;
     val += kind as u64;
     return Self(val);
@@ -68,8 +81,10 @@ impl ExprPtr {
     };
     return (self.0 >> 8) as *mut _ ;
   }
-  pub fn project_count(&self) -> u8 {
-    (self.0 >> 8) as u8
+  pub fn project_count(&self) -> u32 {
+    let count = (self.0 >> 8) as u8;
+    if count != SPILLED_LENGTH { return count as u32 }
+    return unsafe { read_length_prefix(self.project_ptr()) as u32 }
   }
 }
 
@@ -0,0 +1,119 @@
+
+use std::{path::{PathBuf, Path}, fmt::{Display, Formatter, self}};
+
+use super::raw_syntax_nodes::{SourceLocation, FileId};
+
+
+// both are 1-based. column counts chars, not bytes
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LineColumn {
+  pub line: u32,
+  pub column: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineColumnRange {
+  pub start: LineColumn,
+  pub end: LineColumn,
+}
+
+impl Display for LineColumn {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "{}:{}", self.line, self.column)
+  }
+}
+
+// Layout of lines within a single file
+#[derive(Clone, Debug)]
+pub struct LineTable {
+  line_starts: Vec<u32>,
+  // offset and byte length of every char that is wider than one byte.
+  // lets offsets be turned into columns without keeping the text around
+  multibyte_chars: Vec<(u32, u8)>,
+  text_len: u32,
+}
+
+impl LineTable {
+  pub fn compute(text: &str) -> Self {
+    let mut line_starts = vec![0];
+    let mut multibyte_chars = Vec::new();
+    for (offset, char) in text.char_indices() {
+      let width = char.len_utf8();
+      if width > 1 {
+        multibyte_chars.push((offset as u32, width as u8));
+      }
+      if char == '\n' {
+        line_starts.push((offset + 1) as u32);
+      }
+    }
+    return Self {
+      line_starts, multibyte_chars, text_len: text.len() as u32
+    }
+  }
+  pub fn line_count(&self) -> usize {
+    return self.line_starts.len()
+  }
+  // byte range of a given line, without its line break
+  pub fn line_span(&self, line: u32) -> SourceLocation {
+    let ix = (line as usize - 1).min(self.line_starts.len() - 1);
+    let start = self.line_starts[ix];
+    let end = match self.line_starts.get(ix + 1) {
+      Some(next_start) => next_start - 1,
+      None => self.text_len,
+    };
+    return SourceLocation { primary_offset: start, secondary_offset: end }
+  }
+  pub fn locate(&self, offset: u32) -> LineColumn {
+    let offset = offset.min(self.text_len);
+    let ix = match self.line_starts.binary_search(&offset) {
+      Ok(ix) => ix,
+      Err(ix) => ix - 1,
+    };
+    let line_start = self.line_starts[ix];
+    let lo =
+      self.multibyte_chars.partition_point(|(pos, _)| *pos < line_start);
+    let hi =
+      self.multibyte_chars.partition_point(|(pos, _)| *pos < offset);
+    let extra_bytes =
+      self.multibyte_chars[lo .. hi].iter()
+      .map(|(_, width)| *width as u32 - 1).sum::<u32>();
+    let column = offset - line_start - extra_bytes + 1;
+    return LineColumn { line: ix as u32 + 1, column }
+  }
+  pub fn locate_span(&self, span: SourceLocation) -> LineColumnRange {
+    return LineColumnRange {
+      start: self.locate(span.primary_offset),
+      end: self.locate(span.secondary_offset),
+    }
+  }
+}
+
+struct MappedFile {
+  path: PathBuf,
+  lines: LineTable,
+}
+
+// Line layouts of all files in a session, indexed by FileId
+pub struct SourceMap {
+  files: Vec<MappedFile>
+}
+
+impl SourceMap {
+  pub fn init() -> Self {
+    Self { files: Vec::new() }
+  }
+  pub fn register_file(&mut self, path: PathBuf, lines: LineTable) -> FileId {
+    let id = FileId(self.files.len() as u32);
+    self.files.push(MappedFile { path, lines });
+    return id
+  }
+  pub fn path_of(&self, file: FileId) -> &Path {
+    return &self.files[file.0 as usize].path
+  }
+  pub fn lines_of(&self, file: FileId) -> &LineTable {
+    return &self.files[file.0 as usize].lines
+  }
+  pub fn locate(&self, file: FileId, span: SourceLocation) -> LineColumnRange {
+    return self.lines_of(file).locate_span(span)
+  }
+}
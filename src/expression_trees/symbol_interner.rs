@@ -0,0 +1,106 @@
+
+use std::{
+  collections::{HashMap, hash_map::DefaultHasher},
+  hash::{Hash, Hasher},
+  sync::{Mutex, OnceLock, atomic::{AtomicU32, AtomicPtr, Ordering}},
+  ptr::null_mut,
+};
+
+
+// Compact handle of a distinct name.
+// Two names are equal iff their ids are equal
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct InternedName(pub u32);
+
+const SHARD_COUNT : usize = 64;
+const CHUNK_SIZE : usize = 1024;
+const CHUNK_COUNT : usize = 4096;
+
+type NameChunk = [OnceLock<&'static str>; CHUNK_SIZE];
+
+// Concurrent name -> id map with reverse lookup.
+// Interning goes through one of several independently locked shards,
+// so parsers working on different files rarely wait on each other.
+// Looking names up by id never locks.
+//
+// Interned text is never freed. The set of names in a program
+// is small and the interner lives as long as the process does.
+pub struct SymbolInterner {
+  shards: [Mutex<HashMap<&'static str, InternedName>>; SHARD_COUNT],
+  next_id: AtomicU32,
+  chunks: [AtomicPtr<NameChunk>; CHUNK_COUNT],
+}
+
+impl SymbolInterner {
+  pub fn init() -> Self {
+    return Self {
+      shards: std::array::from_fn(|_| Mutex::new(HashMap::new())),
+      next_id: AtomicU32::new(0),
+      chunks: std::array::from_fn(|_| AtomicPtr::new(null_mut())),
+    }
+  }
+  pub fn intern(&self, name: &str) -> InternedName {
+    let mut hasher = DefaultHasher::new();
+    name.hash(&mut hasher);
+    let shard = &self.shards[hasher.finish() as usize % SHARD_COUNT];
+
+    let mut map = shard.lock().unwrap();
+    if let Some(id) = map.get(name) { return *id }
+
+    let text : &'static str = Box::leak(name.to_string().into_boxed_str());
+    let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+    if id as usize >= CHUNK_SIZE * CHUNK_COUNT {
+      panic!("Too many distinct names to intern")
+    }
+    let _ = self.slot_of(id, true).unwrap().set(text);
+    let id = InternedName(id);
+    map.insert(text, id);
+    return id
+  }
+  pub fn resolve(&self, name: InternedName) -> &'static str {
+    let Some(slot) = self.slot_of(name.0, false) else {
+      panic!("Name {} was not produced by this interner", name.0)
+    };
+    return slot.get().unwrap()
+  }
+  pub fn interned_count(&self) -> usize {
+    return self.next_id.load(Ordering::Relaxed) as usize
+  }
+  fn slot_of(
+    &self, id: u32, create_if_missing: bool
+  ) -> Option<&OnceLock<&'static str>> { unsafe {
+    let chunk_ref = &self.chunks[id as usize / CHUNK_SIZE];
+    let mut chunk = chunk_ref.load(Ordering::Acquire);
+    if chunk.is_null() {
+      if !create_if_missing { return None }
+      let fresh =
+        Box::into_raw(Box::new(std::array::from_fn(|_| OnceLock::new())));
+      match chunk_ref.compare_exchange(
+        null_mut(), fresh, Ordering::AcqRel, Ordering::Acquire
+      ) {
+        Ok(_) => chunk = fresh,
+        Err(recent) => { // someone did it already
+          drop(Box::from_raw(fresh));
+          chunk = recent
+        }
+      }
+    }
+    return Some(&(*chunk)[id as usize % CHUNK_SIZE])
+  } }
+}
+
+impl Drop for SymbolInterner {
+  fn drop(&mut self) {
+    for chunk in &self.chunks {
+      let ptr = chunk.load(Ordering::Relaxed);
+      if !ptr.is_null() { drop(unsafe { Box::from_raw(ptr) }) }
+    }
+  }
+}
+
+// Symbols carry only an id, so every one of them
+// has to be resolvable from wherever it ends up
+pub fn global_interner() -> &'static SymbolInterner {
+  static INTERNER : OnceLock<SymbolInterner> = OnceLock::new();
+  return INTERNER.get_or_init(SymbolInterner::init)
+}
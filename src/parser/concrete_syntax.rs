@@ -0,0 +1,269 @@
+use crate::expression_trees::{
+  better_nodes::Declaration,
+  raw_syntax_nodes::SourceLocation,
+};
+
+use super::new_parser::{ParsingState, ParsedSource, ParseError, comment_length};
+
+
+// Lossless view of a source text. Compact trees drop whitespace,
+// comments and punctuation, which is fine for checking,
+// but tools that write text back need all of it.
+//
+// Nodes are recorded by the same parser that builds compact trees,
+// so both views always agree on the shape of the text.
+// Concatenation of every token with its trivia gives back
+// the original text exactly, even if some of it could not be parsed.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+  Name,
+  Colon, Comma, Semicolon, Equals, Bar,
+  FatArrow, ThinArrow, Turnstile,
+  OpenParen, CloseParen, OpenBrace, CloseBrace,
+  OpenLambda, OpenWitness, CloseWitness,
+  Star, Wildcard,
+  // text that was skipped while recovering from a parse error
+  Unparsed,
+  // holds trivia that follows the last token
+  EndOfText,
+}
+impl TokenKind {
+  pub(crate) fn of_punctuation(text: &str) -> Self {
+    match text {
+      ":" => TokenKind::Colon, "," => TokenKind::Comma,
+      ";" => TokenKind::Semicolon, "=" => TokenKind::Equals,
+      "|" => TokenKind::Bar, "=>" => TokenKind::FatArrow,
+      "->" => TokenKind::ThinArrow, "|-" => TokenKind::Turnstile,
+      "(" => TokenKind::OpenParen, ")" => TokenKind::CloseParen,
+      "{" => TokenKind::OpenBrace, "}" => TokenKind::CloseBrace,
+      "\\{" => TokenKind::OpenLambda, "[|" => TokenKind::OpenWitness,
+      "|]" => TokenKind::CloseWitness, "*" => TokenKind::Star,
+      "_" => TokenKind::Wildcard,
+      _ => unreachable!("{text} is not a punctuation"),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxKind {
+  Source,
+  ModuleHeader,
+  Import,
+  Declaration,
+  Constructor,
+  Clause,
+  Pattern,
+  Expression,
+  ImplicitContext,
+  Witness,
+  Lift,
+  Lambda,
+  // declaration that failed to parse
+  Error,
+}
+
+// Trailing trivia is whatever follows a token on the same line.
+// Everything else before a token, including doc comments, is leading trivia
+#[derive(Debug, Clone, Copy)]
+pub struct SyntaxToken {
+  pub kind: TokenKind,
+  pub range: SourceLocation,
+  pub leading_trivia: SourceLocation,
+  pub trailing_trivia: SourceLocation,
+}
+
+#[derive(Debug, Clone)]
+pub enum SyntaxElement {
+  Node(SyntaxNode),
+  // index into tokens of a tree
+  Token(u32),
+}
+
+#[derive(Debug, Clone)]
+pub struct SyntaxNode {
+  pub kind: SyntaxKind,
+  pub children: Vec<SyntaxElement>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum SyntaxEvent {
+  Start(SyntaxKind),
+  Finish,
+  Token(TokenKind, SourceLocation),
+}
+
+pub struct SyntaxTree {
+  text: String,
+  tokens: Vec<SyntaxToken>,
+  root: SyntaxNode,
+  pub errors: Vec<ParseError>,
+  // compact trees built by the parse that recorded nodes,
+  // one for every declaration node, in the same order
+  lowered: Vec<Declaration>,
+  // owns nodes of lowered declarations
+  _parser: ParsingState,
+}
+
+impl SyntaxTree {
+  pub fn parse(text: &str) -> Self {
+    let text = text.to_string();
+    let mut ps = ParsingState::init(&text);
+    ps.record_syntax_events();
+    let ParsedSource { declarations, errors, .. } = ps.parse_decls_with_recovery();
+    let events = ps.take_syntax_events();
+    let (tokens, root) = Self::build(&text, events);
+    return Self { text, tokens, root, errors, lowered: declarations, _parser: ps }
+  }
+  fn build(text: &str, events: Vec<SyntaxEvent>) -> (Vec<SyntaxToken>, SyntaxNode) {
+    let mut tokens = Vec::new();
+    let mut stack = vec![SyntaxNode { kind: SyntaxKind::Source, children: Vec::new() }];
+    for event in events {
+      match event {
+        SyntaxEvent::Start(kind) => {
+          stack.push(SyntaxNode { kind, children: Vec::new() })
+        },
+        SyntaxEvent::Finish => {
+          let node = stack.pop().unwrap();
+          stack.last_mut().unwrap().children.push(SyntaxElement::Node(node));
+        },
+        SyntaxEvent::Token(kind, range) => {
+          let empty = SourceLocation { primary_offset: 0, secondary_offset: 0 };
+          let token = SyntaxToken {
+            kind, range, leading_trivia: empty, trailing_trivia: empty };
+          stack.last_mut().unwrap().children.push(
+            SyntaxElement::Token(tokens.len() as u32));
+          tokens.push(token);
+        },
+      }
+    }
+    assert!(stack.len() == 1, "unbalanced syntax events");
+    let mut root = stack.pop().unwrap();
+
+    let end = text.len() as u32;
+    let end_of_text = SourceLocation { primary_offset: end, secondary_offset: end };
+    root.children.push(SyntaxElement::Token(tokens.len() as u32));
+    tokens.push(SyntaxToken {
+      kind: TokenKind::EndOfText, range: end_of_text,
+      leading_trivia: end_of_text, trailing_trivia: end_of_text });
+
+    let mut trivia_start = 0;
+    for token in &mut tokens {
+      let SourceLocation { primary_offset: start, secondary_offset: end } =
+        token.range;
+      token.leading_trivia =
+        SourceLocation { primary_offset: trivia_start, secondary_offset: start };
+      let trailing_end = end + same_line_trivia_length(&text[end as usize ..]) as u32;
+      token.trailing_trivia =
+        SourceLocation { primary_offset: end, secondary_offset: trailing_end };
+      trivia_start = trailing_end;
+    }
+
+    return (tokens, root)
+  }
+}
+
+/// Access
+impl SyntaxTree {
+  pub fn text(&self) -> &str {
+    &self.text
+  }
+  pub fn root(&self) -> &SyntaxNode {
+    &self.root
+  }
+  pub fn tokens(&self) -> &[SyntaxToken] {
+    &self.tokens
+  }
+  pub fn slice(&self, range: SourceLocation) -> &str {
+    let SourceLocation { primary_offset, secondary_offset } = range;
+    return &self.text[primary_offset as usize .. secondary_offset as usize]
+  }
+  pub fn token_text(&self, token: &SyntaxToken) -> &str {
+    self.slice(token.range)
+  }
+  // span from the first token of a node to its last one, without trivia
+  pub fn node_range(&self, node: &SyntaxNode) -> SourceLocation {
+    let mut tokens = Vec::new();
+    node.collect_tokens(&mut tokens);
+    let (Some(first), Some(last)) = (tokens.first(), tokens.last()) else {
+      return SourceLocation { primary_offset: 0, secondary_offset: 0 }
+    };
+    return SourceLocation {
+      primary_offset: self.tokens[*first as usize].range.primary_offset,
+      secondary_offset: self.tokens[*last as usize].range.secondary_offset,
+    }
+  }
+  pub fn node_text(&self, node: &SyntaxNode) -> &str {
+    self.slice(self.node_range(node))
+  }
+  pub fn declarations(&self) -> impl Iterator<Item = &SyntaxNode> {
+    return self.root.child_nodes()
+      .filter(|node| node.kind == SyntaxKind::Declaration)
+  }
+  // puts every token back together with its trivia
+  pub fn render(&self) -> String {
+    let mut output = String::new();
+    for token in &self.tokens {
+      output.push_str(self.slice(token.leading_trivia));
+      output.push_str(self.slice(token.range));
+      output.push_str(self.slice(token.trailing_trivia));
+    }
+    return output
+  }
+}
+
+impl SyntaxNode {
+  pub fn child_nodes(&self) -> impl Iterator<Item = &SyntaxNode> {
+    return self.children.iter().filter_map(|child| match child {
+      SyntaxElement::Node(node) => Some(node),
+      SyntaxElement::Token(_) => None,
+    })
+  }
+  pub fn collect_tokens(&self, tokens: &mut Vec<u32>) {
+    for child in &self.children {
+      match child {
+        SyntaxElement::Node(node) => node.collect_tokens(tokens),
+        SyntaxElement::Token(index) => tokens.push(*index),
+      }
+    }
+  }
+}
+
+/// Lowering
+impl SyntaxTree {
+  // Compact tree that was built together with a declaration node.
+  // It lives as long as this syntax tree does.
+  // Nodes of other trees and nodes of other kinds have none
+  pub fn lower_declaration(&self, node: &SyntaxNode) -> Option<Declaration> {
+    let index = self.declarations().position(|decl| std::ptr::eq(decl, node))?;
+    return self.lowered.get(index).copied()
+  }
+  pub fn lower(&self) -> &[Declaration] {
+    &self.lowered
+  }
+}
+
+
+// spaces and comments up to the end of line
+fn same_line_trivia_length(text: &str) -> usize {
+  let mut index = 0;
+  while index < text.len() {
+    if let Some(len) = comment_length(text, index) { index += len; continue; }
+    if text.as_bytes()[index] != b' ' { break; }
+    index += 1;
+  }
+  return index
+}
+
+// where the last thing that is not trivia ends
+pub(crate) fn last_token_end(text: &str, start: usize, end: usize) -> usize {
+  let mut index = start;
+  let mut last_token_end = start;
+  while index < end {
+    if let Some(len) = comment_length(text, index) { index += len; continue; }
+    let char = text[index ..].chars().next().unwrap();
+    index += char.len_utf8();
+    if char != ' ' && char != '\n' { last_token_end = index }
+  }
+  return last_token_end
+}
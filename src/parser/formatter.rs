@@ -0,0 +1,398 @@
+use std::{fs, io, path::{Path, PathBuf}};
+
+use crate::{
+  expression_trees::{
+    more_text_rendering::{
+      render_declaration, render_module_header, DEFAULT_LINE_WIDTH},
+    raw_syntax_nodes::SourceLocation,
+  },
+  elaborator::main::collect_source_paths,
+};
+
+use super::{
+  new_parser::{ParsingState, ParsedSource, ParseError, comment_length},
+  concrete_syntax::{SyntaxTree, SyntaxToken, TokenKind},
+};
+
+
+// Rewrites source text in canonical layout.
+// Text is only reformatted if it parses without errors.
+//
+// Comments between declarations are kept where they are.
+// Trees dont remember comments inside of declarations,
+// so those are attached to tokens and put back around them (see weave_comments)
+pub fn format_source(text: &str, width: usize) -> Result<String, Vec<ParseError>> {
+  let source = text.to_string();
+  let mut ps = ParsingState::init(&source);
+  // start over to collect every error, not just the first one
+  let all_errors = || {
+    let mut ps = ParsingState::init(&source);
+    let ParsedSource { errors, .. } = ps.parse_decls_with_recovery();
+    return Err(errors)
+  };
+  ps.skip_trivia();
+  let header_start = ps.byte_index;
+  let (header, header_errors) = ps.parse_module_header();
+  if !header_errors.is_empty() { return all_errors() }
+  let header_end = ps.byte_index;
+  // only needed to find tokens that comments stick to
+  let mut syntax_tree = None;
+  let mut tokens_of = |start: usize, end: usize| {
+    let tree = syntax_tree.get_or_insert_with(|| SyntaxTree::parse(&source));
+    return source_tokens(tree, start, end)
+  };
+  let mut pieces = Vec::new();
+  loop {
+    ps.skip_trivia();
+    if ps.no_more_chars() { break; }
+    let name_start = ps.byte_index;
+    match ps.parse_decl() {
+      Ok(decl) => pieces.push((decl, name_start, ps.byte_index)),
+      Err(_) => return all_errors()
+    }
+  }
+
+  let mut output = Layout { text: String::new(), last_was_decl: false };
+  let mut gap_start = 0;
+  if header.name.is_some() || !header.imports.is_empty() {
+    let line_breaks = output.put_comments(&source[.. header_start]);
+    let (end, has_comments_inside) =
+      scan_declaration(&source, header_start, header_end);
+    let mut rendered = render_module_header(&header);
+    if has_comments_inside {
+      rendered = weave_comments(&source, &tokens_of(header_start, end), &rendered)
+    }
+    output.put_block(&rendered, line_breaks > 1);
+    output.last_was_decl = true;
+    gap_start = end;
+  }
+  for (decl, name_start, parse_end) in pieces {
+    let decl_start = decl.doc_comment
+      .map_or(name_start, |doc| doc.location.primary_offset as usize);
+    let line_breaks = output.put_comments(&source[gap_start .. decl_start]);
+
+    let (decl_end, has_comments_inside) =
+      scan_declaration(&source, name_start, parse_end);
+    let mut rendered = render_declaration(&decl, width);
+    if has_comments_inside {
+      rendered = weave_comments(&source, &tokens_of(name_start, decl_end), &rendered)
+    }
+    output.put_block(&rendered, output.last_was_decl || line_breaks > 1);
+    output.last_was_decl = true;
+    gap_start = decl_end;
+  }
+  output.put_comments(&source[gap_start ..]);
+  if !output.text.is_empty() { output.text.push('\n') }
+  return Ok(output.text)
+}
+
+struct Layout {
+  text: String,
+  last_was_decl: bool,
+}
+impl Layout {
+  fn put_block(&mut self, block: &str, blank_line_before: bool) {
+    if !self.text.is_empty() {
+      self.text.push('\n');
+      if blank_line_before { self.text.push('\n') }
+    }
+    self.text.push_str(block);
+  }
+  // comment that started on the line of a previous thing stays there,
+  // unless that line ends in a line comment already.
+  // gives back how many line breaks follow the last comment
+  fn put_comments(&mut self, gap: &str) -> usize {
+    let mut index = 0;
+    let mut line_breaks = 0;
+    while index < gap.len() {
+      if let Some(len) = comment_length(gap, index) {
+        let comment = gap[index .. index + len].trim_end();
+        if line_breaks == 0 && !self.text.is_empty() && !ends_in_line_comment(&self.text) {
+          self.text.push(' ');
+          self.text.push_str(comment);
+        } else {
+          self.put_block(comment, line_breaks > 1);
+        }
+        self.last_was_decl = false;
+        line_breaks = 0;
+        index += len;
+        continue;
+      }
+      if gap.as_bytes()[index] == b'\n' { line_breaks += 1 }
+      index += 1;
+    }
+    return line_breaks
+  }
+}
+
+fn ends_in_line_comment(text: &str) -> bool {
+  let mut index = text.rfind('\n').map_or(0, |line_break| line_break + 1);
+  while index < text.len() {
+    if let Some(len) = comment_length(text, index) {
+      if text[index ..].starts_with("--") { return true }
+      index += len;
+      continue;
+    }
+    index += 1;
+  }
+  return false
+}
+
+// parser may step over trivia that follows a declaration,
+// so the real end is where the last token of it ends
+fn scan_declaration(text: &str, start: usize, end: usize) -> (usize, bool) {
+  let mut index = start;
+  let mut last_token_end = start;
+  let mut saw_comment = false;
+  let mut has_comments_inside = false;
+  while index < end {
+    if text[index ..].starts_with("\\{") {
+      index += 2;
+      last_token_end = index;
+      continue;
+    }
+    if let Some(len) = comment_length(text, index) {
+      index += len;
+      saw_comment = true;
+      continue;
+    }
+    let char = text[index ..].chars().next().unwrap();
+    index += char.len_utf8();
+    if char == ' ' || char == '\n' { continue; }
+    last_token_end = index;
+    has_comments_inside |= saw_comment;
+  }
+  return (last_token_end, has_comments_inside)
+}
+
+
+fn source_tokens(tree: &SyntaxTree, start: usize, end: usize) -> Vec<SyntaxToken> {
+  return tree.tokens().iter()
+    .filter(|token| token.kind != TokenKind::EndOfText)
+    .filter(|token| {
+      start <= token.range.primary_offset as usize &&
+      token.range.secondary_offset as usize <= end
+    })
+    .copied().collect()
+}
+
+// Puts comments that were inside of a declaration into its rendered text.
+// Every comment sticks to a token: one that was on lines of its own
+// sticks to the token that follows, and one that followed a token
+// on the same line sticks to that token.
+// Tokens of source and of rendered text are matched up by
+// their longest common run, so that parens dropped by the printer
+// dont throw the rest off.
+//
+// Comments of the first kind are put on lines of their own above the line
+// of their token, comments of the second kind go right after their token
+// if a space is there anyway, or to the end of its line otherwise.
+// Rendered lines are never broken or joined, and comments are trivia,
+// so the result parses to the same declaration
+fn weave_comments(source: &str, tokens: &[SyntaxToken], rendered: &str) -> String {
+  let rendered_tree = SyntaxTree::parse(rendered);
+  let rendered_tokens =
+    source_tokens(&rendered_tree, 0, rendered.len());
+  let key = |text: &str, token: &SyntaxToken| {
+    let range = token.range.primary_offset as usize .. token.range.secondary_offset as usize;
+    return (token.kind, text[range].to_string())
+  };
+  let matched = align(
+    &tokens.iter().map(|token| key(source, token)).collect::<Vec<_>>(),
+    &rendered_tokens.iter().map(|token| key(rendered, token)).collect::<Vec<_>>());
+
+  let mut before = vec![Vec::new(); rendered_tokens.len()];
+  let mut after = vec![Vec::new(); rendered_tokens.len()];
+  let mut pending = Vec::new();
+  let mut last_matched = None;
+  for (index, token) in tokens.iter().enumerate() {
+    // the ones around a whole declaration belong to the gaps
+    if index != 0 { pending.extend(comments_in(source, token.leading_trivia)) }
+    if let Some(target) = matched[index] {
+      before[target].append(&mut pending);
+      last_matched = Some(target);
+    }
+    if index + 1 == tokens.len() { continue; }
+    let trailing = comments_in(source, token.trailing_trivia);
+    match last_matched {
+      Some(target) => after[target].extend(trailing),
+      None => pending.extend(trailing),
+    }
+  }
+  if let Some(last) = after.last_mut() { last.append(&mut pending) }
+
+  let mut output = String::new();
+  let mut line_start = 0;
+  let mut token_index = 0;
+  let first_token_start = rendered_tokens.first()
+    .map_or(0, |token| token.range.primary_offset as usize);
+  let lines = rendered.split('\n').collect::<Vec<_>>();
+  for (line_index, line) in lines.iter().enumerate() {
+    let line_end = line_start + line.len();
+    let indent = indentation(line);
+    // comments that dont fit on a line go above the next one
+    let next_indent = lines.get(line_index + 1).map_or(indent, |next| indentation(next));
+    let mut line_tokens = token_index;
+    while line_tokens < rendered_tokens.len() &&
+          rendered_tokens[line_tokens].range.primary_offset as usize <= line_end {
+      line_tokens += 1;
+    }
+    let mut above = before[token_index .. line_tokens].concat();
+    let last_above = above.iter().map(|(offset, _)| *offset).max();
+    let is_first = line_start <= first_token_start && first_token_start <= line_end;
+    let mut at_end = Vec::new();
+    let mut text = String::new();
+    let mut cursor = line_start;
+    for token_index in token_index .. line_tokens {
+      let token_end = rendered_tokens[token_index].range.secondary_offset as usize;
+      text.push_str(&rendered[cursor .. token_end]);
+      for &(offset, comment) in &after[token_index] {
+        let spaced = rendered.as_bytes().get(token_end) == Some(&b' ');
+        // staying inline mustnt put it before comments that were written earlier
+        let in_order = at_end.is_empty() && if is_first {
+          above.iter().all(|(above_offset, _)| offset < *above_offset)
+        } else {
+          last_above.is_none_or(|last| last < offset)
+        };
+        if comment.starts_with("{-") && spaced && token_index + 1 < line_tokens && in_order {
+          text.push(' ');
+          text.push_str(comment);
+        } else {
+          at_end.push((offset, comment))
+        }
+      }
+      cursor = token_end;
+    }
+    token_index = line_tokens;
+    text.push_str(&rendered[cursor .. line_end]);
+    // doc comment has to stay right above the name,
+    // so comments of the first line follow it, in the order they were written
+    if is_first {
+      at_end.append(&mut above);
+    } else {
+      // comments written before some of those above go above too
+      let written_before = at_end.iter()
+        .take_while(|(offset, _)| Some(*offset) < last_above).count();
+      above.extend(at_end.drain(.. written_before));
+      above.sort_by_key(|(offset, _)| *offset);
+    }
+    at_end.sort_by_key(|(offset, _)| *offset);
+    for (_, comment) in above {
+      output.push_str(indent);
+      output.push_str(comment);
+      output.push('\n');
+    }
+    output.push_str(&text);
+    let mut line_is_over = false;
+    for (_, comment) in at_end {
+      if line_is_over {
+        output.push('\n');
+        output.push_str(next_indent);
+      } else {
+        output.push(' ');
+      }
+      output.push_str(comment);
+      // once a line comment ended it, each comment gets a line of its own
+      line_is_over |= comment.starts_with("--");
+    }
+    output.push('\n');
+    line_start = line_end + 1;
+  }
+  output.pop();
+  return output
+}
+
+fn indentation(line: &str) -> &str {
+  return &line[.. line.len() - line.trim_start().len()]
+}
+
+// comments of a trivia range, with offsets they start at
+fn comments_in(text: &str, range: SourceLocation) -> Vec<(usize, &str)> {
+  let mut comments = Vec::new();
+  let mut index = range.primary_offset as usize;
+  while index < range.secondary_offset as usize {
+    if let Some(len) = comment_length(text, index) {
+      comments.push((index, text[index .. index + len].trim_end()));
+      index += len;
+      continue;
+    }
+    index += 1;
+  }
+  return comments
+}
+
+// for every item on the left, index of the item on the right it is paired with
+fn align<T: PartialEq>(left: &[T], right: &[T]) -> Vec<Option<usize>> {
+  // lengths of longest common runs of every pair of suffixes
+  let width = right.len() + 1;
+  let mut table = vec![0u32; (left.len() + 1) * width];
+  for l in (0 .. left.len()).rev() {
+    for r in (0 .. right.len()).rev() {
+      table[l * width + r] = if left[l] == right[r] {
+        table[(l + 1) * width + r + 1] + 1
+      } else {
+        table[(l + 1) * width + r].max(table[l * width + r + 1])
+      };
+    }
+  }
+  let mut matched = vec![None; left.len()];
+  let (mut l, mut r) = (0, 0);
+  while l < left.len() && r < right.len() {
+    if left[l] == right[r] {
+      matched[l] = Some(r);
+      l += 1;
+      r += 1;
+    } else if table[(l + 1) * width + r] >= table[l * width + r + 1] {
+      l += 1
+    } else {
+      r += 1
+    }
+  }
+  return matched
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormatMode {
+  Rewrite,
+  // only tell which files would change
+  Check,
+}
+
+#[derive(Debug)]
+pub enum FormatStatus {
+  Unchanged,
+  Reformatted,
+  Unparsable(Vec<ParseError>),
+}
+
+#[derive(Debug)]
+pub struct FormatReport {
+  pub path: PathBuf,
+  pub status: FormatStatus,
+}
+
+// Formats every source file in a given folder.
+// Files that would change are reported as reformatted,
+// and in check mode they are left untouched
+pub fn format_directory(
+  folder: &Path, mode: FormatMode
+) -> io::Result<Vec<FormatReport>> {
+  let mut paths = Vec::new();
+  collect_source_paths(folder, &mut paths)?;
+  paths.sort();
+  let mut reports = Vec::new();
+  for path in paths {
+    let text = fs::read_to_string(&path)?;
+    let status = match format_source(&text, DEFAULT_LINE_WIDTH) {
+      Err(errors) => FormatStatus::Unparsable(errors),
+      Ok(formatted) if formatted == text => FormatStatus::Unchanged,
+      Ok(formatted) => {
+        if mode == FormatMode::Rewrite { fs::write(&path, formatted)? }
+        FormatStatus::Reformatted
+      }
+    };
+    reports.push(FormatReport { path, status });
+  }
+  return Ok(reports)
+}
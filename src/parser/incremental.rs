@@ -0,0 +1,408 @@
+use std::rc::Rc;
+
+use crate::{
+  expression_trees::{
+    better_nodes::{
+      Declaration, DeclKind, RawNode, RawNodeRepr, RawRewriteRule,
+      RawPattern, RawPatternKind, Symbol, ArrayPtr, ModuleHeader},
+    raw_syntax_nodes::{SourceLocation, FileId},
+  },
+  support_structures::homemade_slice::Slice,
+};
+
+use super::{
+  new_parser::{ParsingState, ParseError, ParseErrorKind},
+  concrete_syntax::last_token_end,
+};
+
+
+// Source text that is kept parsed across edits.
+//
+// Text is split into segments, one per top level declaration.
+// A segment starts at doc comment of its declaration (or its name),
+// and lasts till the next one. An edit can only affect segments
+// which parsing has looked at edited text, and those after them.
+// Parsing goes on from the first of these, and stops once it gets
+// to an untouched declaration. Everything else is kept as it was,
+// with locations moved to account for the edit.
+//
+// Trees of declarations are owned by this source and
+// must not be concretised in place.
+pub struct IncrementalSource {
+  text: String,
+  origin: FileId,
+  header: Header,
+  segments: Vec<Segment>,
+}
+
+// module and import lines, which can only come first
+struct Header {
+  content: ModuleHeader,
+  errors: Vec<ParseError>,
+  // till the end of the last header line
+  end: usize,
+}
+
+struct Segment {
+  start: usize,
+  name_start: usize,
+  // how far into text parsing of it has looked
+  reach: usize,
+  content: Result<Declaration, ParseError>,
+  // keeps nodes of the declaration alive
+  _parser: Rc<ParsingState>,
+}
+
+pub struct TextEdit {
+  pub range: SourceLocation,
+  pub replacement: String,
+}
+
+// Names of declarations that downstream passes should look at again
+#[derive(Debug, Default)]
+pub struct DeclarationChanges {
+  pub added: Vec<Symbol>,
+  pub removed: Vec<Symbol>,
+  pub changed: Vec<Symbol>,
+  // names of all declarations may mean something else now
+  pub header_changed: bool,
+}
+
+impl IncrementalSource {
+  pub fn parse(text: &str, origin: FileId) -> Self {
+    let header = Header { content: ModuleHeader::default(), errors: Vec::new(), end: 0 };
+    let mut source =
+      Self { text: text.to_string(), origin, header, segments: Vec::new() };
+    source.header = source.parse_header();
+    (source.segments, _) = source.parse_segments(source.header.end, |_, _| false);
+    return source
+  }
+  pub fn header(&self) -> &ModuleHeader {
+    &self.header.content
+  }
+  pub fn text(&self) -> &str {
+    &self.text
+  }
+  pub fn declarations(&self) -> Vec<Declaration> {
+    return self.segments.iter()
+      .filter_map(|segment| segment.content.ok())
+      .collect()
+  }
+  // in order of their offsets, like a full parse gives them
+  pub fn errors(&self) -> Vec<ParseError> {
+    let segment_errors =
+      self.segments.iter().filter_map(|segment| segment.content.err());
+    let mut errors = self.header.errors.iter().copied()
+      .chain(segment_errors)
+      .collect::<Vec<_>>();
+    errors.sort_by_key(|err| err.span.primary_offset);
+    return errors
+  }
+  fn parse_header(&self) -> Header {
+    let mut parser = ParsingState::init_for_file(&self.text, self.origin);
+    let (content, errors) = parser.parse_module_header();
+    let end = last_token_end(&self.text, 0, parser.byte_index);
+    return Header { content, errors, end }
+  }
+  // Parses segments starting from a given offset,
+  // until the end of text or a segment whose start and name offsets
+  // satisfy `stop`. Gives back where parsing has stopped
+  fn parse_segments(
+    &self, from: usize, stop: impl Fn(usize, usize) -> bool
+  ) -> (Vec<Segment>, usize) {
+    let mut parser = ParsingState::init_for_file(&self.text, self.origin);
+    parser.start_at(from);
+    let mut found: Vec<(usize, usize, Result<Declaration, ParseError>, usize)> =
+      Vec::new();
+    loop {
+      parser.skip_trivia();
+      // trivia after a declaration is looked at while parsing it
+      if let Some((.., reach)) = found.last_mut() { *reach = parser.furthest_read() }
+      if parser.no_more_chars() { break; }
+      let name_start = parser.byte_index;
+      let start = parser.find_doc_comment()
+        .map_or(name_start, |doc| doc.location.primary_offset as usize);
+      // segments after an unterminated comment dont know about it
+      if !parser.has_comment_error() && stop(start, name_start) { break; }
+      match parser.parse_decl() {
+        Ok(decl) => found.push((start, name_start, Ok(decl), 0)),
+        Err(err) => {
+          parser.resync_at_next_decl(name_start);
+          found.push((start, name_start, Err(err), 0));
+        }
+      }
+    }
+    // such comment is a segment of its own. it may be inside
+    // of a broken declaration, that recovery has stepped over
+    if let Some(err) = parser.take_comment_error() {
+      let start = err.span.primary_offset as usize;
+      let index = found.partition_point(|(other, ..)| *other <= start);
+      found.insert(index, (start, start, Err(err), self.text.len()));
+    }
+    let stopped_at = parser.byte_index;
+    let parser = Rc::new(parser);
+    let segments = found.into_iter().map(|(start, name_start, content, reach)| {
+      Segment { start, name_start, reach, content, _parser: parser.clone() }
+    }).collect();
+    return (segments, stopped_at)
+  }
+  // index of a segment that covers given offset.
+  // text before the first declaration counts as part of it
+  fn segment_at(&self, offset: usize) -> usize {
+    let after = self.segments.partition_point(|segment| segment.start <= offset);
+    return after.saturating_sub(1)
+  }
+  // closest segment at or before given one, that parsing can start at
+  fn restart_point(&self, mut index: usize) -> usize {
+    while index > 0 && self.starts_inside(index) { index -= 1 }
+    return index
+  }
+  // parsing cant start at such segment. comment lies inside of
+  // the declaration before it, and a declaration that follows
+  // another one on the same line gets doc comment of that line.
+  // recovery from an error skips lines regardless of comments,
+  // so the declaration it gets to may be inside of one
+  fn starts_inside(&self, index: usize) -> bool {
+    let segment = &self.segments[index];
+    let at_line_start =
+      segment.name_start == 0 || self.text.as_bytes()[segment.name_start - 1] == b'\n';
+    let after_error = self.segments[index - 1].content.is_err();
+    return segment.is_comment() || !at_line_start || after_error
+  }
+}
+
+impl Segment {
+  fn is_comment(&self) -> bool {
+    return matches!(
+      self.content,
+      Err(ParseError { kind: ParseErrorKind::UnterminatedComment, .. }))
+  }
+}
+
+/// Edits
+impl IncrementalSource {
+  pub fn apply_edit(&mut self, edit: &TextEdit) -> DeclarationChanges {
+    let edit_start = edit.range.primary_offset as usize;
+    let edit_end = edit.range.secondary_offset as usize;
+    let delta = edit.replacement.len() as isize - (edit_end - edit_start) as isize;
+
+    // parsing starts at the first segment that has looked at edited text,
+    // or the one that edit is in, if it is in trivia that nobody looked at
+    let mut first = self.segments.iter()
+      .position(|segment| edit_start <= segment.reach)
+      .unwrap_or(self.segments.len().saturating_sub(1));
+    while first > 0 && self.segments[first].start > edit_start { first -= 1 }
+    first = self.restart_point(first);
+    let last = (self.segment_at(edit_end) + 2).min(self.segments.len());
+    let mut from = if first == 0 { 0 } else { self.segments[first].start };
+    let mut old = self.named_texts(first .. last);
+    let old_header = self.text[.. self.header.end].to_string();
+
+    self.text.replace_range(edit_start .. edit_end, &edit.replacement);
+    // text of segments after the edit has only moved
+    for segment in &mut self.segments[last ..] {
+      segment.start = segment.start.wrapping_add_signed(delta);
+      segment.name_start = segment.name_start.wrapping_add_signed(delta);
+      segment.reach = segment.reach.wrapping_add_signed(delta);
+    }
+
+    // header is cheap to parse, so it is redone whenever
+    // the edit is near the start
+    let mut header_changed = false;
+    if from == 0 {
+      self.header = self.parse_header();
+      header_changed = self.text[.. self.header.end] != old_header;
+      from = self.header.end;
+    }
+
+    // reparsing stops at an untouched declaration, if it still
+    // starts where it did. a doc comment may have grown
+    // over lines before it, or lost some of them.
+    // only the first unterminated comment is reported, and once it
+    // is gone another one may come first. so while there is one,
+    // everything after the edit is parsed again
+    let comment = self.segments.iter().position(Segment::is_comment);
+    let untouched = &self.segments[last ..];
+    let (reparsed, stopped_at) = self.parse_segments(from, |start, name_start| {
+      return comment.is_none() && untouched
+        .binary_search_by_key(&(start, name_start), |segment| (segment.start, segment.name_start))
+        .is_ok()
+    });
+    let reused_from =
+      match untouched.binary_search_by_key(&stopped_at, |segment| segment.name_start) {
+        Ok(index) => last + index,
+        Err(_) => self.segments.len(),
+      };
+    // parsing may have gone on past them
+    old.extend(self.named_texts(last .. reused_from));
+
+    let mut tail = self.segments.split_off(reused_from);
+    self.segments.truncate(first);
+    for segment in &mut tail {
+      match segment.content {
+        Ok(ref mut decl) => shift_declaration(decl, delta),
+        Err(ref mut err) => err.span = shift_location(err.span, delta),
+      }
+    }
+    let new_range = first .. first + reparsed.len();
+    self.segments.extend(reparsed);
+    self.segments.extend(tail);
+
+    // doc comments point into text that was just edited,
+    // and it may have been moved
+    let bytes = Slice { source_data: self.text.as_ptr(), span: self.text.len() as u32 };
+    for segment in &mut self.segments {
+      if let Ok(Declaration { doc_comment: Some(ref mut doc), .. }) = segment.content {
+        doc.chars_ptr = bytes;
+      }
+    }
+
+    let new = self.named_texts(new_range);
+    let mut changes = DeclarationChanges { header_changed, ..Default::default() };
+    for (name, text) in &new {
+      match old.iter().find(|(old_name, _)| old_name == name) {
+        None => changes.added.push(*name),
+        Some((_, old_text)) if old_text != text => changes.changed.push(*name),
+        Some(_) => (),
+      }
+    }
+    for (name, _) in &old {
+      if !new.iter().any(|(new_name, _)| new_name == name) {
+        changes.removed.push(*name)
+      }
+    }
+    return changes
+  }
+  // names of well formed declarations in given segments,
+  // together with their text up to the last token.
+  // trivia after a declaration is not a part of it
+  fn named_texts(&self, range: std::ops::Range<usize>) -> Vec<(Symbol, String)> {
+    let mut named = Vec::new();
+    for index in range {
+      let segment = &self.segments[index];
+      let Ok(decl) = segment.content else { continue };
+      let end = self.segments.get(index + 1)
+        .map_or(self.text.len(), |next| next.start);
+      let end = last_token_end(&self.text, segment.name_start, end);
+      named.push((decl.project_name(), self.text[segment.start .. end].to_string()));
+    }
+    return named
+  }
+}
+
+
+/// Location shifting
+fn shift_location(location: SourceLocation, delta: isize) -> SourceLocation {
+  let shift = |offset: u32| (offset as isize + delta) as u32;
+  return SourceLocation {
+    primary_offset: shift(location.primary_offset),
+    secondary_offset: shift(location.secondary_offset),
+  }
+}
+
+fn shift_declaration(decl: &mut Declaration, delta: isize) {
+  if let Some(ref mut doc) = decl.doc_comment {
+    doc.location = shift_location(doc.location, delta)
+  }
+  match decl.repr {
+    DeclKind::RawMapping { ref mut name, .. } |
+    DeclKind::RawDefinition { ref mut name, .. } |
+    DeclKind::RawData { ref mut name, .. } => {
+      name.location = shift_location(name.location, delta)
+    },
+    _ => unreachable!("incremental source only holds raw declarations"),
+  }
+  let mut shift = |location: &mut SourceLocation| {
+    *location = shift_location(*location, delta)
+  };
+  walk_declaration(*decl, &mut shift);
+}
+
+fn walk_declaration(decl: Declaration, visit: &mut impl FnMut(&mut SourceLocation)) {
+  match decl.repr {
+    DeclKind::RawMapping { given_type, rewrite_rules, .. } => {
+      walk_expr(given_type, visit);
+      walk_array(rewrite_rules, |rule| walk_rule(rule, visit));
+    },
+    DeclKind::RawDefinition { given_type, value, .. } => {
+      walk_expr(given_type, visit);
+      walk_expr(value, visit);
+    },
+    DeclKind::RawData { given_type, constructors, .. } => {
+      walk_expr(given_type, visit);
+      walk_array(constructors, |ctor| {
+        let ctor = unsafe { &mut *ctor };
+        walk_symbol(&mut ctor.name, visit);
+        walk_expr(ctor.given_type, visit);
+      });
+    },
+    _ => unreachable!("incremental source only holds raw declarations"),
+  }
+}
+
+fn walk_array<T>(array: ArrayPtr<T>, mut visit: impl FnMut(*mut T)) {
+  let ptr = array.project_ptr();
+  for index in 0 .. array.project_count() as usize {
+    visit(unsafe { ptr.add(index) })
+  }
+}
+
+fn walk_symbol(symbol: &mut Symbol, visit: &mut impl FnMut(&mut SourceLocation)) {
+  visit(&mut symbol.location)
+}
+
+fn walk_expr(expr: *mut RawNode, visit: &mut impl FnMut(&mut SourceLocation)) {
+  let expr = unsafe { &mut *expr };
+  visit(&mut expr.location);
+  if let Some(ctx) = expr.implicit_context {
+    walk_array(ctx, |item| {
+      let (name, type_) = unsafe { &mut *item };
+      walk_symbol(name, visit);
+      if let Some(type_) = type_ { walk_expr(type_, visit) }
+    });
+  }
+  match expr.kind {
+    RawNodeRepr::Star => (),
+    RawNodeRepr::Ref(ref mut name) => walk_symbol(name, visit),
+    RawNodeRepr::App { ref mut root, arguments } => {
+      walk_symbol(root, visit);
+      walk_array(arguments, |arg| walk_expr(arg, visit));
+    },
+    RawNodeRepr::Wit { premises, conclusion } => {
+      walk_array(premises, |premise| walk_expr(premise, visit));
+      walk_expr(conclusion, visit);
+    },
+    RawNodeRepr::Fun { head, spine } |
+    RawNodeRepr::Sigma { head, spine } => {
+      walk_array(head, |item| {
+        let (name, type_) = unsafe { &mut *item };
+        if let Some(name) = name { walk_symbol(name, visit) }
+        walk_expr(type_, visit);
+      });
+      walk_expr(spine, visit);
+    },
+    RawNodeRepr::Lam { rewrite_rules } => {
+      walk_array(rewrite_rules, |rule| walk_rule(rule, visit));
+    },
+  }
+}
+
+fn walk_rule(rule: *mut RawRewriteRule, visit: &mut impl FnMut(&mut SourceLocation)) {
+  let rule = unsafe { &mut *rule };
+  visit(&mut rule.location);
+  walk_array(rule.matchers, |pattern| walk_pattern(pattern, visit));
+  walk_expr(rule.lhs, visit);
+}
+
+fn walk_pattern(pattern: *mut RawPattern, visit: &mut impl FnMut(&mut SourceLocation)) {
+  let pattern = unsafe { &mut *pattern };
+  visit(&mut pattern.location);
+  match pattern.repr {
+    RawPatternKind::Wildcard => (),
+    RawPatternKind::Mono(ref mut name) => walk_symbol(name, visit),
+    RawPatternKind::Compound { ref mut head, subexpressions } => {
+      walk_symbol(head, visit);
+      walk_array(subexpressions, |sub| walk_pattern(sub, visit));
+    },
+  }
+}
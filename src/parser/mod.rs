@@ -1,4 +1,7 @@
 
 pub mod node_allocator;
 
-pub mod new_parser;
\ No newline at end of file
+pub mod new_parser;
+pub mod formatter;
+pub mod concrete_syntax;
+pub mod incremental;
\ No newline at end of file
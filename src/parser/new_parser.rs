@@ -1,17 +1,24 @@
 
 
 use std::mem::{size_of};
+use std::cell::Cell;
 use std::str;
+use std::fmt::{Display, Debug, Formatter, self};
+use unicode_xid::UnicodeXID;
 use crate::support_structures::homemade_slice::Slice;
 use crate::expression_trees::better_nodes::{
   RawNodeRepr, RawNode, ArrayPtr, RawImplicitCtx, RawRewriteRule,
-  RawPattern, RawPatternKind, Declaration, DeclKind, Symbol};
+  RawPattern, RawPatternKind, Declaration, DeclKind, Symbol, DocComment,
+  RawConstructor, ModuleHeader, Import, ImportSelection, length_prefix_size, write_length_prefix};
 use crate::{ throw, guard };
 use crate::support_structures::monad::{
   fail_with_aux_gen_ctx_intro };
-use crate::expression_trees::raw_syntax_nodes::{RawKind, SourceLocation,};
+use crate::expression_trees::raw_syntax_nodes::{RawKind, SourceLocation, FileId};
+use crate::expression_trees::symbol_interner::global_interner;
+use crate::expression_trees::source_map::{LineTable, LineColumn};
 use crate::support_structures::mini_vector::InlineVector;
 use super::node_allocator::{LinearAllocator,};
+use super::concrete_syntax::{SyntaxEvent, SyntaxKind, TokenKind, last_token_end};
 
 
 #[derive(Debug, Clone, Copy)]
@@ -26,40 +33,246 @@ pub struct ParsingState {
   current_char: u32,
   pub byte_index: usize,
     // Todo: figure out how to expose fields to testing code
-  line_number: u32,
+  lines: LineTable,
+  origin: FileId,
   lin_alloc: Option<LinearAllocator<MINIMUM_ALLOC_SIZE>>,
+  // only recorded when lossless syntax tree was asked for
+  syntax_events: Option<Vec<SyntaxEvent>>,
+  // comments are skipped as trivia, which cant fail,
+  // so a `{-` without its `-}` is kept here until someone asks
+  unterminated_comment: Option<ParseError>,
+  // where the last skipped block comment ends.
+  // lines before it that look like doc comments are inside of it
+  block_comment_end: usize,
+  // furthest offset which char parser has looked at.
+  // text after it cant have changed what was parsed
+  furthest_read: Cell<usize>,
 }
 
+// Names come in two flavours: identifiers that follow unicode XID rules
+// (with primes allowed after the first char) and runs of operator chars
+fn is_ident_start(char: u32) -> bool {
+  let Some(char) = char::from_u32(char) else { return false };
+  return char == '_' || UnicodeXID::is_xid_start(char)
+}
+fn is_ident_continue(char: u32) -> bool {
+  let Some(char) = char::from_u32(char) else { return false };
+  return char == '\'' || UnicodeXID::is_xid_continue(char)
+}
+// whole text is a single identifier, without dots
+pub fn is_identifier(text: &str) -> bool {
+  let mut chars = text.chars().map(|char| char as u32);
+  let Some(first) = chars.next() else { return false };
+  return is_ident_start(first) && chars.all(is_ident_continue)
+}
+fn is_operator_char(char: u32) -> bool {
+  match char {
+    // ascii ones that dont make up punctuation of the syntax
+    0x21 | 0x23 ..= 0x26 | 0x2A | 0x2B | 0x2D | 0x2E | 0x2F |
+    0x3C ..= 0x3F | 0x40 | 0x5E | 0x7E |
+    // ¬ ± × ÷
+    0xAC | 0xB1 | 0xD7 | 0xF7 |
+    // arrows
+    0x2190 ..= 0x21FF | 0x27F0 ..= 0x27FF | 0x2900 ..= 0x297F |
+    // mathematical operators and symbols
+    0x2200 ..= 0x22FF | 0x27C0 ..= 0x27EF | 0x2980 ..= 0x2AFF => true,
+    _ => false
+  }
+}
 fn is_valid_char_for_symbol(char: u32) -> bool {
+  return is_ident_continue(char) || is_operator_char(char)
+}
 
-  if // Basic Latin small letterr
-     (0x61 <= char && char <= 0x7A) ||
-     // Basic Latin Capital Letters
-     (0x41 <= char && char <= 0x5A) ||
-     // _ symbol
-     char == 0x5F { return true; }
-  return false;
+// Comments are lexed only here, both for the parser
+// and for tools that walk trivia of a text.
+// Gives byte length of a comment that starts at given index,
+// and whether it was closed. line comments stop right before line break.
+// Bytes are compared, since a comment can hold any text,
+// and delimiters are ascii, so they never match inside of a wider char
+pub(crate) fn lex_comment(text: &[u8], index: usize) -> Option<(usize, bool)> {
+  let rest = &text[index ..];
+  if rest.starts_with(b"--") {
+    let length = rest.iter().position(|byte| *byte == b'\n').unwrap_or(rest.len());
+    return Some((length, true))
+  }
+  if rest.starts_with(b"{-") {
+    let mut nesting = 1;
+    let mut offset = 2;
+    while nesting != 0 && offset < rest.len() {
+      if rest[offset ..].starts_with(b"{-") { nesting += 1; offset += 2; continue; }
+      if rest[offset ..].starts_with(b"-}") { nesting -= 1; offset += 2; continue; }
+      offset += 1;
+    }
+    return Some((offset, nesting == 0))
+  }
+  return None
+}
+pub(crate) fn comment_length(text: &str, index: usize) -> Option<usize> {
+  return lex_comment(text.as_bytes(), index).map(|(length, _)| length)
 }
 
+// operator runs that mean something to the parser
+const RESERVED_OPERATORS : [&str ; 4] = ["=", "=>", "->", "*"];
+
 const EOT : char = '\u{3}' ;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Checkpoint {
   old_char: u32,
   old_ptr: usize,
-  old_ln_num: u32
+  old_event_count: usize,
 }
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum ParseErrorKind {
-  UnrecognisedCharacter, EmptySymbol, TooLongSymbol,
-  UnterminatedSubexpr, UnexpectedCharacter
+  EmptySymbol,
+  // `{-` that has no matching `-}`. covers the opening
+  UnterminatedComment,
+  // `module` or `import` line after declarations or another module line
+  MisplacedModuleLine,
+  Expected { tokens: TokenSet, context: ParseContext }
 }
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct ParseError {
   pub kind: ParseErrorKind,
-  pub absolute_offset: usize
+  // covers the text that parser choked on.
+  // empty at the end of a line or text
+  pub span: SourceLocation,
+}
+
+#[repr(u8)] #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+  Symbol, Colon, Comma, Semicolon, Equals, Bar,
+  FatArrow, ThinArrow, Turnstile,
+  OpenParen, CloseParen, OpenBrace, CloseBrace,
+  OpenLambda, OpenWitness, CloseWitness,
+  LineEnd,
+}
+const ALL_TOKENS : [Token ; 17] = [
+  Token::Symbol, Token::Colon, Token::Comma, Token::Semicolon,
+  Token::Equals, Token::Bar, Token::FatArrow, Token::ThinArrow,
+  Token::Turnstile, Token::OpenParen, Token::CloseParen,
+  Token::OpenBrace, Token::CloseBrace, Token::OpenLambda,
+  Token::OpenWitness, Token::CloseWitness, Token::LineEnd,
+];
+impl Display for Token {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    let str = match self {
+      Token::Symbol => return write!(f, "a name"),
+      Token::LineEnd => return write!(f, "end of line"),
+      Token::Colon => ":", Token::Comma => ",", Token::Semicolon => ";",
+      Token::Equals => "=", Token::Bar => "|", Token::FatArrow => "=>",
+      Token::ThinArrow => "->", Token::Turnstile => "|-",
+      Token::OpenParen => "(", Token::CloseParen => ")",
+      Token::OpenBrace => "{", Token::CloseBrace => "}",
+      Token::OpenLambda => "\\{", Token::OpenWitness => "[|",
+      Token::CloseWitness => "|]",
+    };
+    write!(f, "`{}`", str)
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct TokenSet(u32);
+impl TokenSet {
+  pub fn of(tokens: &[Token]) -> Self {
+    let mut set = 0;
+    for token in tokens { set |= 1 << *token as u32 }
+    return Self(set)
+  }
+  pub fn contains(&self, token: Token) -> bool {
+    (self.0 & (1 << token as u32)) != 0
+  }
+  pub fn iter(&self) -> impl Iterator<Item = Token> + '_ {
+    ALL_TOKENS.into_iter().filter(|token| self.contains(*token))
+  }
+}
+impl Debug for TokenSet {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    f.debug_set().entries(self.iter()).finish()
+  }
+}
+impl Display for TokenSet {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    let tokens = self.iter().collect::<Vec<_>>();
+    for (ix, token) in tokens.iter().enumerate() {
+      if ix != 0 {
+        let sep = if ix + 1 == tokens.len() { " or " } else { ", " };
+        write!(f, "{}", sep)?;
+      }
+      write!(f, "{}", token)?;
+    }
+    return Ok(())
+  }
+}
+
+// What parser was in the middle of when it failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseContext {
+  DeclName, DeclType, ConstructorName,
+  ImplicitCtxStart, ImplicitCtxItem,
+  WitnessStart, WitnessPremise, WitnessEnd,
+  LiftStart, LiftItem, LiftHead,
+  LambdaStart, ClauseStart, ClausePatterns,
+  Subexpr, Subpattern,
+  ImportList, ModuleLine, ImportLine,
+  ExprEnd,
+}
+impl Display for ParseContext {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    let str = match self {
+      ParseContext::DeclName => "after declaration name",
+      ParseContext::DeclType => "after declaration type",
+      ParseContext::ConstructorName => "after constructor name",
+      ParseContext::ImplicitCtxStart => "to open implicit context",
+      ParseContext::ImplicitCtxItem => "after item of implicit context",
+      ParseContext::WitnessStart => "to open witness",
+      ParseContext::WitnessPremise => "after witness premise",
+      ParseContext::WitnessEnd => "to close witness",
+      ParseContext::LiftStart => "to open binder list",
+      ParseContext::LiftItem => "after item of binder list",
+      ParseContext::LiftHead => "after binder list",
+      ParseContext::LambdaStart => "to open lambda",
+      ParseContext::ClauseStart => "to begin clause",
+      ParseContext::ClausePatterns => "after clause patterns",
+      ParseContext::Subexpr => "to close subexpression",
+      ParseContext::Subpattern => "to close subpattern",
+      ParseContext::ImportList => "in list of imported names",
+      ParseContext::ModuleLine => "after module name",
+      ParseContext::ImportLine => "after import",
+      ParseContext::ExprEnd => "after expression",
+    };
+    write!(f, "{}", str)
+  }
+}
+
+impl Display for ParseErrorKind {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self {
+      ParseErrorKind::EmptySymbol =>
+        write!(f, "expected a name"),
+      ParseErrorKind::UnterminatedComment =>
+        write!(f, "block comment is never closed"),
+      ParseErrorKind::MisplacedModuleLine =>
+        write!(f, "module line must come first, and imports before declarations"),
+      ParseErrorKind::Expected { tokens, context } =>
+        write!(f, "expected {} {}", tokens, context),
+    }
+  }
+}
+
+impl Display for ParseError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.kind)
+  }
+}
+
+impl ParseError {
+  pub fn project_unexpected_text<'a>(&self, text: &'a str) -> &'a str {
+    let SourceLocation { primary_offset, secondary_offset } = self.span;
+    return &text[primary_offset as usize .. secondary_offset as usize]
+  }
 }
 
 type Maybe<T> = Result<T, ParseError>;
@@ -67,7 +280,12 @@ type Maybe<T> = Result<T, ParseError>;
 
 /// Initializers
 impl ParsingState {
+  // for text that is not a part of any source file
   pub fn init(chars: &String) -> Self {
+    return Self::init_for_file(chars, FileId::DETACHED)
+  }
+  // symbols produced by this parser will refer to the given file
+  pub fn init_for_file(chars: &String, origin: FileId) -> Self {
     let slice = Slice {
       source_data: chars.as_ptr(),
       span: chars.len() as u32
@@ -75,10 +293,15 @@ impl ParsingState {
     return Self {
       byte_index: 0,
       bytes: slice,
-      current_char: unsafe { *chars.as_ptr() as u32 },
-      line_number: 1,
+      current_char:
+        chars.chars().next().map_or(EOT as u32, |char| char as u32),
+      lines: LineTable::compute(chars),
+      origin,
       lin_alloc: None,
-
+      syntax_events: None,
+      unterminated_comment: None,
+      block_comment_end: 0,
+      furthest_read: Cell::new(0),
     }
   }
 }
@@ -104,31 +327,87 @@ impl ParsingState {
     }
     unreachable!()
   }
-  fn get_mem<T>(&mut self, count: usize) -> *mut T {
-    let count = size_of::<T>() * count;
-    return self.get_raw_mem(count).cast::<T>();
+  // memory for items of ArrayPtr, with their count in front if it is long
+  fn get_array_mem<T>(&mut self, count: usize) -> *mut T {
+    let prefix = length_prefix_size::<T>(count);
+    let mem = self.get_raw_mem(prefix + size_of::<T>() * count);
+    let items = unsafe { mem.cast::<u8>().add(prefix).cast::<T>() };
+    if prefix != 0 { unsafe { write_length_prefix(items, count) } }
+    return items
   }
 }
 
 /// Preliminary methods
 impl ParsingState {
   fn fail_with(&self, error: ParseErrorKind) -> ParseError {
-    ParseError { absolute_offset: self.byte_index, kind: error }
+    ParseError { span: self.unexpected_span(), kind: error }
+  }
+  fn expected(&self, tokens: &[Token], context: ParseContext) -> ParseError {
+    let kind =
+      ParseErrorKind::Expected { tokens: TokenSet::of(tokens), context };
+    return self.fail_with(kind)
+  }
+  // a whole name, or a single char of anything else
+  fn unexpected_span(&self) -> SourceLocation {
+    let text = unsafe {
+      std::slice::from_raw_parts(self.bytes.source_data, self.bytes.span as usize)
+    };
+    let start = self.byte_index.min(text.len());
+    let rest = str::from_utf8(&text[start ..]).unwrap_or("");
+    let mut len = 0;
+    for char in rest.chars() {
+      if !is_valid_char_for_symbol(char as u32) { break; }
+      len += char.len_utf8();
+    }
+    if len == 0 {
+      len = match rest.chars().next() {
+        Some('\n') | None => 0,
+        Some(char) => char.len_utf8(),
+      };
+    }
+    self.furthest_read.set(self.furthest_read.get().max(start + len));
+    return SourceLocation {
+      primary_offset: start as u32,
+      secondary_offset: (start + len) as u32
+    }
+  }
+  pub fn line_table(&self) -> &LineTable {
+    &self.lines
+  }
+  pub fn current_position(&self) -> LineColumn {
+    self.lines.locate(self.byte_index as u32)
   }
   pub fn no_more_chars(&self) -> bool {
     self.byte_index == self.bytes.span as usize
   }
   fn next_char(&mut self) {
     if self.no_more_chars() { return (); }
-    // only ascii subset for now
-    let char = self.get_current_char();
-    if char == '\n' { self.line_number += 1; };
-    self.byte_index += 1;
-    self.current_char = unsafe {
-      let char_ptr =
-        self.bytes.source_data.add(self.byte_index as usize);
-      *char_ptr as u32
+    let width = char::from_u32(self.current_char).map_or(1, char::len_utf8);
+    self.byte_index += width;
+    self.current_char = self.decode_char_at(self.byte_index);
+  }
+  fn decode_char_at(&self, index: usize) -> u32 {
+    self.furthest_read.set(self.furthest_read.get().max(index));
+    let len = self.bytes.span as usize;
+    if index >= len { return EOT as u32 }
+    // text came from a String, so it is valid utf-8
+    let tail = unsafe {
+      let bytes = std::slice::from_raw_parts(
+        self.bytes.source_data.add(index), (len - index).min(4));
+      str::from_utf8_unchecked(bytes)
     };
+    return tail.chars().next().map_or(EOT as u32, |char| char as u32)
+  }
+  // moves back over bytes that were already scanned
+  pub(crate) fn rewind_to(&mut self, index: usize) {
+    self.byte_index = index;
+    self.current_char = self.decode_char_at(index);
+  }
+  // starts parsing in the middle of text. text before given offset
+  // was parsed by someone else, so none of it is a doc comment
+  pub(crate) fn start_at(&mut self, index: usize) {
+    self.rewind_to(index);
+    self.block_comment_end = index;
   }
   fn get_current_char(&self) -> char {
     if self.no_more_chars(){ return EOT; }
@@ -139,16 +418,18 @@ impl ParsingState {
     Checkpoint {
       old_char: self.current_char,
       old_ptr: self.byte_index,
-      old_ln_num: self.line_number
+      old_event_count: self.syntax_events.as_ref().map_or(0, Vec::len),
     }
   }
   pub fn backtrack_to(
     &mut self,
-    Checkpoint { old_char, old_ptr, old_ln_num }: Checkpoint
+    Checkpoint { old_char, old_ptr, old_event_count }: Checkpoint
   ) {
     self.byte_index = old_ptr;
     self.current_char = old_char;
-    self.line_number = old_ln_num;
+    if let Some(ref mut events) = self.syntax_events {
+      events.truncate(old_event_count)
+    }
   }
   pub fn skip_while(
     &mut self,
@@ -161,31 +442,79 @@ impl ParsingState {
     }
   }
   pub fn skip_trivia(&mut self) {
-    self.skip_while(|self_| {
-      let char = self_.get_current_char();
-      return char == '\n' || char == ' ';
-    });
+    loop {
+      self.skip_while(|self_| {
+        let char = self_.get_current_char();
+        return char == '\n' || char == ' ';
+      });
+      if !self.skip_comment() { break; }
+    }
   }
   pub fn skip_whitespaces(&mut self) {
-    self.skip_while(|self_| {
-      let char = self_.get_current_char();
-      return char == ' ';
-    })
+    loop {
+      self.skip_while(|self_| {
+        let char = self_.get_current_char();
+        return char == ' ';
+      });
+      if !self.skip_comment() { break; }
+    }
+  }
+  // line comments stop right before line break, so they dont glue
+  // lines together. block comments count as a space,
+  // even if they span several lines
+  fn skip_comment(&mut self) -> bool {
+    let start = self.byte_index;
+    let text = unsafe {
+      std::slice::from_raw_parts(self.bytes.source_data, self.bytes.span as usize)
+    };
+    // two chars tell whether it is a comment
+    self.furthest_read.set(self.furthest_read.get().max(start + 1));
+    let Some((length, is_closed)) = lex_comment(text, start) else { return false };
+    if !is_closed && self.unterminated_comment.is_none() {
+      let span = SourceLocation {
+        primary_offset: start as u32, secondary_offset: start as u32 + 2 };
+      self.unterminated_comment =
+        Some(ParseError { kind: ParseErrorKind::UnterminatedComment, span });
+    }
+    if text[start] == b'{' { self.block_comment_end = start + length }
+    self.rewind_to(start + length);
+    return true;
+  }
+  pub(crate) fn furthest_read(&self) -> usize {
+    return self.furthest_read.get()
+  }
+  // block comment that swallowed the rest of text, if one was skipped
+  pub fn take_comment_error(&mut self) -> Option<ParseError> {
+    return self.unterminated_comment.take()
   }
+  pub(crate) fn has_comment_error(&self) -> bool {
+    return self.unterminated_comment.is_some()
+  }
+  // indentation of the next line that has something besides comments.
+  // it ends at the first char that isnt a space, so a comment
+  // at the start of a line doesnt indent what comes after it
   pub fn probe_depth(&mut self) -> u32 {
-    let mut start = 0;
+    let mut depth = 0;
     loop {
       self.skip_whitespaces();
-      if self.get_current_char() == '\n' {
-        self.next_char();
-        self.line_number += 1;
-        start = self.byte_index;
-      } else { break };
+      if self.get_current_char() != '\n' { break; }
+      self.next_char();
+      let start = self.byte_index;
+      self.skip_while(|self_| self_.get_current_char() == ' ');
+      depth = (self.byte_index - start) as u32;
     }
-    if start == 0 { return 0; }
-    return (self.byte_index - start) as u32;
+    return depth;
   }
+  // stripped punctuation becomes a token of syntax tree
   pub fn prefix_match(&mut self, pattern: &str, should_strip: bool) -> bool {
+    let start = self.byte_index;
+    let matched = self.match_chars(pattern, should_strip);
+    if matched && should_strip {
+      self.note_token(TokenKind::of_punctuation(pattern), start);
+    }
+    return matched
+  }
+  fn match_chars(&mut self, pattern: &str, should_strip: bool) -> bool {
     let chkpt = self.checkpoint();
     let mut iter = pattern.chars();
     loop {
@@ -207,8 +536,40 @@ impl ParsingState {
     return true;
   }
   pub fn at_terminator(&self) -> bool {
-    let char = self.get_current_char();
-    return !is_valid_char_for_symbol(char as u32);
+    return self.measure_symbol().is_none();
+  }
+  // byte length of a name that starts at current position
+  fn measure_symbol(&self) -> Option<usize> {
+    let mut index = self.byte_index;
+    let first = self.decode_char_at(index);
+    let is_operator =
+      if is_ident_start(first) { false }
+      else if is_operator_char(first) { true }
+      else { return None };
+    let continues =
+      if is_operator { is_operator_char } else { is_ident_continue };
+    loop {
+      let char = self.decode_char_at(index);
+      if index >= self.bytes.span as usize { break; }
+      // qualified names are glued together with dots
+      let qualifies =
+        !is_operator && char == '.' as u32 &&
+        is_ident_start(self.decode_char_at(index + 1));
+      if qualifies { index += 1; continue; }
+      if !continues(char) { break; }
+      // a comment can follow operator without a space
+      let starts_comment =
+        char == '-' as u32 && self.decode_char_at(index + 1) == '-' as u32;
+      if is_operator && starts_comment { break; }
+      index += char::from_u32(char).map_or(1, char::len_utf8);
+    }
+    let len = index - self.byte_index;
+    if is_operator {
+      let run = unsafe { str::from_utf8_unchecked(std::slice::from_raw_parts(
+        self.bytes.source_data.add(self.byte_index), len)) };
+      if RESERVED_OPERATORS.contains(&run) { return None }
+    }
+    return Some(len)
   }
   pub fn begin_sloc(&self) -> TempSlocInfo {
     TempSlocInfo { primary_offset: self.byte_index as u32 }
@@ -218,8 +579,10 @@ impl ParsingState {
                      secondary_offset: self.byte_index as u32 }
   }
   pub fn accept_first_parse
-  <const N : usize, T>(&mut self, opts: [impl FnOnce(&mut Self) -> Maybe<T> ; N])
-  -> Maybe<T> {
+  <const N : usize, T>(
+    &mut self, opts: [impl FnOnce(&mut Self) -> Maybe<T> ; N],
+    expected: &[Token], context: ParseContext
+  ) -> Maybe<T> {
     let chkpt = self.checkpoint();
     for i in opts.into_iter() {
       let smth = i(self);
@@ -229,7 +592,7 @@ impl ParsingState {
       }
     };
     throw! {
-      self.fail_with(ParseErrorKind::UnexpectedCharacter)
+      self.expected(expected, context)
     }
   }
 }
@@ -239,19 +602,21 @@ impl ParsingState {
 impl ParsingState {
   pub fn parse_symbol(&mut self) -> Maybe<Symbol> {
     let loc = self.begin_sloc();
-    let symbol_start = self.byte_index;
-    self.skip_while(|self_|{
-      let char = self_.current_char;
-      return is_valid_char_for_symbol(char);
-    });
-    let symbol_end = self.byte_index;
-    let loc = self.end_sloc(loc);
-    let diff = symbol_end - symbol_start;
-    if diff == 0 {
+    let Some(len) = self.measure_symbol() else {
       throw!(self.fail_with(ParseErrorKind::EmptySymbol));
     };
+    let symbol_start = self.byte_index;
+    let symbol_end = symbol_start + len;
+    let text = unsafe {
+      std::str::from_utf8_unchecked(std::slice::from_raw_parts(
+        self.bytes.source_data.add(symbol_start), len))
+    };
+    let name = global_interner().intern(text);
+    self.rewind_to(symbol_end);
+    self.note_token(TokenKind::Name, symbol_start);
+    let loc = self.end_sloc(loc);
 
-    return Ok(Symbol { chars_ptr: self.bytes, location: loc });
+    return Ok(Symbol { location: loc, name, origin: self.origin });
   }
 }
 
@@ -262,6 +627,14 @@ impl ParsingState {
   pub fn parse_expr(
     &mut self,
     root_indentation_depth: u32
+  ) -> Maybe<RawNode> {
+    return self.within_node(
+      SyntaxKind::Expression,
+      |self_| self_.parse_expr_contents(root_indentation_depth))
+  }
+  fn parse_expr_contents(
+    &mut self,
+    root_indentation_depth: u32
   ) -> Maybe<RawNode> {
     let loc = self.begin_sloc();
     if self.prefix_match("*", true) {
@@ -297,13 +670,13 @@ impl ParsingState {
         },
         Err(_) => {
           self.backtrack_to(chk);
-          self.next_char();
+          let _ = self.prefix_match("(", true);
           let depth = self.probe_depth().max(root_indentation_depth);
           let mut expr =
             self.parse_expr(depth)?;
           guard! {
             self.prefix_match(")", true) =>
-            self.fail_with(ParseErrorKind::UnterminatedSubexpr)
+            self.expected(&[Token::CloseParen], ParseContext::Subexpr)
           };
           expr.implicit_context = imp_ctx;
           return Ok(expr);
@@ -318,7 +691,7 @@ impl ParsingState {
       if self.prefix_match("\n", false) {
         let depth = self.probe_depth();
         if depth <= root_indentation_depth {
-          self.byte_index -= depth as usize; break };
+          self.rewind_to(self.byte_index - depth as usize); break };
       }
       if self.prefix_match("(", true) {
         self.skip_trivia();
@@ -327,7 +700,7 @@ impl ParsingState {
         subexprs.push(subexpr);
         guard! {
           self.prefix_match(")", true) =>
-            self.fail_with(ParseErrorKind::UnterminatedSubexpr)
+            self.expected(&[Token::CloseParen], ParseContext::Subexpr)
         }
         continue;
       }
@@ -335,7 +708,7 @@ impl ParsingState {
 
       let terminal_subexpr = self.parse_symbol()?;
       let node = RawNode {
-        implicit_context: imp_ctx,
+        implicit_context: None,
         kind: RawNodeRepr::Ref(terminal_subexpr),
         location: terminal_subexpr.location
       };
@@ -353,9 +726,9 @@ impl ParsingState {
       return Ok(node);
     };
     let count = subexprs.count_items();
-    let mem = self.get_mem::<RawNode>(count as usize);
+    let mem = self.get_array_mem::<RawNode>(count);
     subexprs.move_content_into(mem);
-    let args_ptr = ArrayPtr::init(mem, count as u8);
+    let args_ptr = ArrayPtr::init(mem, count);
     let node = RawNode {
       implicit_context: imp_ctx,
       kind: RawNodeRepr::App { root, arguments: args_ptr },
@@ -364,9 +737,13 @@ impl ParsingState {
     return Ok(node)
   }
   pub fn parse_implicit_context(&mut self) -> Maybe<RawImplicitCtx> {
+    return self.within_node(
+      SyntaxKind::ImplicitContext, Self::parse_implicit_context_contents)
+  }
+  fn parse_implicit_context_contents(&mut self) -> Maybe<RawImplicitCtx> {
     guard! {
       self.prefix_match("{", true)
-      => self.fail_with(ParseErrorKind::UnexpectedCharacter)
+      => self.expected(&[Token::OpenBrace], ParseContext::ImplicitCtxStart)
     };
     let mut items =
       InlineVector::<4, (Symbol, Option<RawNode>)>::init();
@@ -386,24 +763,30 @@ impl ParsingState {
         _ if self.prefix_match(",", true) => continue,
         _ if self.prefix_match("}", true) => break,
         _ => {
-          throw! { self.fail_with(ParseErrorKind::UnexpectedCharacter) }
+          throw! {
+            self.expected(
+              &[Token::Comma, Token::CloseBrace], ParseContext::ImplicitCtxItem)
+          }
         }
       }
     }
     let count = items.count_items() as usize;
     let mem =
-      self.get_mem::<(Symbol, Option<RawNode>)>(count);
+      self.get_array_mem::<(Symbol, Option<RawNode>)>(count);
     items.move_content_into(mem);
     let ctx =
-      ArrayPtr::init(mem, count as u8);
+      ArrayPtr::init(mem, count);
     return Ok(ctx)
 
   }
   pub fn parse_witness(&mut self) -> Maybe<RawNode> {
+    return self.within_node(SyntaxKind::Witness, Self::parse_witness_contents)
+  }
+  fn parse_witness_contents(&mut self) -> Maybe<RawNode> {
     let loc = self.begin_sloc();
     guard! {
       self.prefix_match("[|", true) =>
-      self.fail_with(ParseErrorKind::UnexpectedCharacter)
+      self.expected(&[Token::OpenWitness], ParseContext::WitnessStart)
     };
     let mut premises =
       InlineVector::<4, RawNode>::init();
@@ -415,7 +798,10 @@ impl ParsingState {
       match () {
         _ if self.prefix_match(",", true) => continue,
         _ if self.prefix_match(";", true) => break,
-        _ => throw! { self.fail_with(ParseErrorKind::UnexpectedCharacter) }
+        _ => throw! {
+          self.expected(
+            &[Token::Comma, Token::Semicolon], ParseContext::WitnessPremise)
+        }
       };
     };
     let depth = self.probe_depth();
@@ -423,16 +809,16 @@ impl ParsingState {
     self.skip_trivia();
     guard! {
       self.prefix_match("|]", true) =>
-      self.fail_with(ParseErrorKind::UnexpectedCharacter) };
+      self.expected(&[Token::CloseWitness], ParseContext::WitnessEnd) };
     let loc = self.end_sloc(loc);
 
     let evidence_ = self.allocate(evidence);
 
     let count = premises.count_items();
     let concs =
-      self.get_mem::<RawNode>(count as usize);
+      self.get_array_mem::<RawNode>(count);
     premises.move_content_into(concs);
-    let prem_ptr = ArrayPtr::init(concs, count as u8);
+    let prem_ptr = ArrayPtr::init(concs, count);
     let node = RawNode {
       implicit_context: None,
       kind: RawNodeRepr::Wit { premises: prem_ptr, conclusion: evidence_ },
@@ -446,10 +832,13 @@ impl ParsingState {
 
 impl ParsingState {
   pub fn parse_lift_node(&mut self) -> Maybe<RawNode> {
+    return self.within_node(SyntaxKind::Lift, Self::parse_lift_node_contents)
+  }
+  fn parse_lift_node_contents(&mut self) -> Maybe<RawNode> {
     let loc = self.begin_sloc();
     guard! {
       self.prefix_match("(", true) =>
-      self.fail_with(ParseErrorKind::UnexpectedCharacter)
+      self.expected(&[Token::OpenParen], ParseContext::LiftStart)
     }
     let mut items =
       InlineVector::<4, (Option<Symbol>, RawNode)>::init();
@@ -477,7 +866,10 @@ impl ParsingState {
         _ if self.prefix_match(")", true) => {
           self.skip_trivia(); break;
         },
-        _ => throw! { self.fail_with(ParseErrorKind::UnexpectedCharacter) }
+        _ => throw! {
+          self.expected(
+            &[Token::Comma, Token::CloseParen], ParseContext::LiftItem)
+        }
       }
     }
     let node_kind = match () {
@@ -487,7 +879,10 @@ impl ParsingState {
       _ if self.prefix_match("|-", true) => {
         RawKind::Sig
       }
-      _ => throw! { self.fail_with(ParseErrorKind::UnexpectedCharacter) }
+      _ => throw! {
+        self.expected(
+          &[Token::ThinArrow, Token::Turnstile], ParseContext::LiftHead)
+      }
     };
     let depth = self.probe_depth();
     let spine_ = self.parse_expr(depth)?;
@@ -497,10 +892,10 @@ impl ParsingState {
 
     let count = items.count_items();
     let items_ =
-      self.get_mem::<(Option<Symbol>, RawNode)>(count as usize);
+      self.get_array_mem::<(Option<Symbol>, RawNode)>(count);
     items.move_content_into(items_);
     let items_ptr =
-      ArrayPtr::init(items_, count as u8);
+      ArrayPtr::init(items_, count);
     let kind = match node_kind {
       RawKind::Fun => {
         RawNodeRepr::Fun { head: items_ptr, spine }
@@ -523,10 +918,13 @@ impl ParsingState {
 
 impl ParsingState {
   pub fn parse_lambda(&mut self) -> Maybe<RawNode> {
+    return self.within_node(SyntaxKind::Lambda, Self::parse_lambda_contents)
+  }
+  fn parse_lambda_contents(&mut self) -> Maybe<RawNode> {
     let loc = self.begin_sloc();
     guard! {
       self.prefix_match("\\{", true)
-        => self.fail_with(ParseErrorKind::UnexpectedCharacter)
+        => self.expected(&[Token::OpenLambda], ParseContext::LambdaStart)
     };
     let mut depth = self.probe_depth();
     let mut clauses =
@@ -541,9 +939,9 @@ impl ParsingState {
     let loc = self.end_sloc(loc);
     let count = clauses.count_items();
     let mem =
-      self.get_mem::<RawRewriteRule>(count as usize);
+      self.get_array_mem::<RawRewriteRule>(count);
     clauses.move_content_into(mem);
-    let ptr = ArrayPtr::init(mem, count as u8);
+    let ptr = ArrayPtr::init(mem, count);
 
     let node = RawNode {
       implicit_context: None,
@@ -555,11 +953,18 @@ impl ParsingState {
   }
   pub fn parse_clause(
     &mut self, indentation_depth: u32
+  ) -> Maybe<RawRewriteRule> {
+    return self.within_node(
+      SyntaxKind::Clause,
+      |self_| self_.parse_clause_contents(indentation_depth))
+  }
+  fn parse_clause_contents(
+    &mut self, indentation_depth: u32
   ) -> Maybe<RawRewriteRule> {
     let loc_ = self.begin_sloc();
     guard! {
       self.prefix_match("|", true)
-        => self.fail_with(ParseErrorKind::UnexpectedCharacter)
+        => self.expected(&[Token::Bar], ParseContext::ClauseStart)
     };
     let loc: SourceLocation;
     let mut patterns =
@@ -575,7 +980,8 @@ impl ParsingState {
         loc = self.end_sloc(loc_);
         self.skip_whitespaces(); break;
       }
-      throw!(self.fail_with(ParseErrorKind::UnexpectedCharacter));
+      throw!(self.expected(
+        &[Token::Comma, Token::FatArrow], ParseContext::ClausePatterns));
     }
     let depth = self.probe_depth();
     let stencil = self.parse_expr(
@@ -584,10 +990,10 @@ impl ParsingState {
 
     let count = patterns.count_items();
     let patterns_ =
-      self.get_mem::<RawPattern>(count as usize);
+      self.get_array_mem::<RawPattern>(count);
     patterns.move_content_into(patterns_);
     let matchers_ptr =
-      ArrayPtr::init(patterns_, count as u8);
+      ArrayPtr::init(patterns_, count);
 
     let rr = RawRewriteRule {
       matchers: matchers_ptr,
@@ -598,6 +1004,9 @@ impl ParsingState {
     return Ok(rr);
   }
   pub fn parse_pattern(&mut self) -> Maybe<RawPattern> {
+    return self.within_node(SyntaxKind::Pattern, Self::parse_pattern_contents)
+  }
+  fn parse_pattern_contents(&mut self) -> Maybe<RawPattern> {
     let loc = self.begin_sloc();
     if self.prefix_match("_", true) {
       let loc = self.end_sloc(loc);
@@ -619,11 +1028,20 @@ impl ParsingState {
         self.skip_trivia();
         guard! {
           self.prefix_match(")", true)
-          => self.fail_with(ParseErrorKind::UnexpectedCharacter)
+          => self.expected(&[Token::CloseParen], ParseContext::Subpattern)
         }
         continue;
       }
       if self.at_terminator() { break; }
+      let arg_loc = self.begin_sloc();
+      if self.prefix_match("_", true) {
+        let wk = RawPattern {
+          repr: RawPatternKind::Wildcard,
+          location: self.end_sloc(arg_loc)
+        };
+        args.push(wk);
+        continue;
+      }
 
       let terminal_pat = self.parse_symbol()?;
       let subexpr = RawPattern {
@@ -642,9 +1060,9 @@ impl ParsingState {
     }
     let count = args.count_items();
 
-    let mem = self.get_mem::<RawPattern>(count as usize);
+    let mem = self.get_array_mem::<RawPattern>(count);
     args.move_content_into(mem);
-    let ptr = ArrayPtr::init(mem, count as u8);
+    let ptr = ArrayPtr::init(mem, count);
 
     let pat = RawPattern {
       location: loc,
@@ -658,11 +1076,18 @@ impl ParsingState {
 
 impl ParsingState {
   pub fn parse_decl(&mut self) -> Maybe<Declaration> {
+    return self.within_node(SyntaxKind::Declaration, Self::parse_decl_contents)
+  }
+  fn parse_decl_contents(&mut self) -> Maybe<Declaration> {
+    let doc_comment = self.find_doc_comment();
+    if self.probe_data_keyword() {
+      return self.parse_data_contents(doc_comment)
+    }
     let name = self.parse_symbol()?;
     self.skip_trivia();
     guard! {
       self.prefix_match(":", true) =>
-      self.fail_with(ParseErrorKind::UnexpectedCharacter)
+      self.expected(&[Token::Colon], ParseContext::DeclName)
     }
     let depth = self.probe_depth();
     let type_ =
@@ -677,10 +1102,12 @@ impl ParsingState {
 
       let def_decl = Declaration {
         repr: DeclKind::RawDefinition { name, given_type: type__, value: value_ },
-        participate_in_cycle_formation: false
+        participate_in_cycle_formation: false,
             // This is a stub value.     👆
             // Actuall information will be established later during
             // semantic analysis phase
+        is_malformed: false,
+        doc_comment,
       };
       return Ok(def_decl)
     }
@@ -693,25 +1120,370 @@ impl ParsingState {
         clauses.push(clause);
         depth = self.probe_depth();
         if self.prefix_match("|", false) { continue; }
-        else { self.byte_index -= depth as usize; break; }
+        else { self.rewind_to(self.byte_index - depth as usize); break; }
       }
       let count = clauses.count_items();
       let rrs =
-        self.get_mem::<RawRewriteRule>(count as usize);
+        self.get_array_mem::<RawRewriteRule>(count);
       clauses.move_content_into(rrs);
       let rrs_ptr =
-        ArrayPtr::init(rrs, count as u8);
+        ArrayPtr::init(rrs, count);
       let map_decl = Declaration {
         repr: DeclKind::RawMapping {
           name, given_type: type__, rewrite_rules: rrs_ptr
         },
-        participate_in_cycle_formation: false
+        participate_in_cycle_formation: false,
             // This is a stub value.     👆
             // Actuall information will be established later during
             // semantic analysis phase
+        is_malformed: false,
+        doc_comment,
       };
       return Ok(map_decl)
     }
-    throw!(self.fail_with(ParseErrorKind::UnexpectedCharacter));
+    throw!(self.expected(&[Token::Equals, Token::Bar], ParseContext::DeclType));
   }
-}
\ No newline at end of file
+  // `data` is a keyword only when a name follows it,
+  // so there can still be a declaration called `data`
+  fn probe_data_keyword(&mut self) -> bool {
+    let chkpt = self.checkpoint();
+    if self.match_word("data") {
+      self.skip_whitespaces();
+      if !self.at_terminator() { return true }
+    }
+    self.backtrack_to(chkpt);
+    return false
+  }
+  fn parse_data_contents(
+    &mut self, doc_comment: Option<DocComment>
+  ) -> Maybe<Declaration> {
+    let name = self.parse_symbol()?;
+    self.skip_trivia();
+    guard! {
+      self.prefix_match(":", true) =>
+      self.expected(&[Token::Colon], ParseContext::DeclName)
+    }
+    let depth = self.probe_depth();
+    let type_ = self.parse_expr(depth)?;
+    let type__ = self.allocate(type_);
+
+    // data type may have no constructors at all
+    let mut constructors =
+      InlineVector::<4, RawConstructor>::init();
+    loop {
+      let depth = self.probe_depth();
+      if !self.prefix_match("|", false) {
+        self.rewind_to(self.byte_index - depth as usize); break;
+      }
+      let constructor = self.parse_constructor()?;
+      constructors.push(constructor);
+    }
+    let count = constructors.count_items();
+    let ctors =
+      self.get_array_mem::<RawConstructor>(count);
+    constructors.move_content_into(ctors);
+    let ctors_ptr = ArrayPtr::init(ctors, count);
+    let data_decl = Declaration {
+      repr: DeclKind::RawData {
+        name, given_type: type__, constructors: ctors_ptr
+      },
+      participate_in_cycle_formation: false,
+      is_malformed: false,
+      doc_comment,
+    };
+    return Ok(data_decl)
+  }
+  fn parse_constructor(&mut self) -> Maybe<RawConstructor> {
+    return self.within_node(SyntaxKind::Constructor, Self::parse_constructor_contents)
+  }
+  fn parse_constructor_contents(&mut self) -> Maybe<RawConstructor> {
+    guard! {
+      self.prefix_match("|", true) =>
+      self.expected(&[Token::Bar], ParseContext::ClauseStart)
+    }
+    self.skip_trivia();
+    let name = self.parse_symbol()?;
+    self.skip_trivia();
+    guard! {
+      self.prefix_match(":", true) =>
+      self.expected(&[Token::Colon], ParseContext::ConstructorName)
+    }
+    let depth = self.probe_depth();
+    let type_ = self.parse_expr(depth)?;
+    let given_type = self.allocate(type_);
+    return Ok(RawConstructor { name, given_type })
+  }
+  // doc comments are found by looking back from the start of declaration,
+  // so they dont need any special treatment while skipping trivia.
+  // only unindented lines that directly precede declaration count.
+  // lines that start before the end of the last skipped block comment
+  // may be inside of it, so they dont
+  pub(crate) fn find_doc_comment(&self) -> Option<DocComment> {
+    let text = unsafe {
+      std::slice::from_raw_parts(self.bytes.source_data, self.bytes.span as usize)
+    };
+    let mut line_start = self.byte_index;
+    while line_start > 0 && text[line_start - 1] != b'\n' { line_start -= 1 }
+    // comment that lookahead has skipped after the declaration
+    // cant have anything to do with its doc
+    let comment_end = match self.block_comment_end {
+      end if end > self.byte_index => 0,
+      end => end,
+    };
+    let mut doc_start = line_start;
+    let mut doc_end = None;
+    while doc_start > 0 {
+      let prev_end = doc_start - 1;
+      let mut prev_start = prev_end;
+      while prev_start > 0 && text[prev_start - 1] != b'\n' { prev_start -= 1 }
+      if !text[prev_start .. prev_end].starts_with(b"--|") { break; }
+      if prev_start < comment_end { break; }
+      if doc_end.is_none() { doc_end = Some(prev_end) }
+      doc_start = prev_start;
+    }
+    let doc_end = doc_end?;
+    return Some(DocComment {
+      chars_ptr: self.bytes,
+      location: SourceLocation {
+        primary_offset: doc_start as u32,
+        secondary_offset: doc_end as u32
+      }
+    })
+  }
+}
+
+
+/// Module header
+impl ParsingState {
+  // `module` and `import` lines can only come before declarations.
+  // A line that starts with one of these words, but goes on
+  // with a colon, is a declaration of that name
+  fn probe_header_keyword(&mut self) -> Option<HeaderKeyword> {
+    let chkpt = self.checkpoint();
+    let keyword = match self.parse_symbol() {
+      Ok(symbol) => match symbol.materialise_name() {
+        "module" => Some(HeaderKeyword::Module),
+        "import" => Some(HeaderKeyword::Import),
+        _ => None
+      },
+      Err(_) => None
+    };
+    self.skip_whitespaces();
+    let is_decl = self.get_current_char() == ':';
+    self.backtrack_to(chkpt);
+    if is_decl { return None }
+    return keyword
+  }
+  pub fn parse_module_header(&mut self) -> (ModuleHeader, Vec<ParseError>) {
+    let mut header = ModuleHeader::default();
+    let mut errors = Vec::new();
+    loop {
+      self.skip_trivia();
+      let Some(keyword) = self.probe_header_keyword() else { break };
+      let chkpt = self.checkpoint();
+      let outcome = match keyword {
+        HeaderKeyword::Module => {
+          let is_first = header.name.is_none() && header.imports.is_empty();
+          self.within_node(
+            SyntaxKind::ModuleHeader, |self_| self_.parse_module_line(is_first))
+            .map(|name| header.name = Some(name))
+        },
+        HeaderKeyword::Import => {
+          self.within_node(SyntaxKind::Import, Self::parse_import_line)
+            .map(|import| header.imports.push(import))
+        },
+      };
+      if let Err(err) = outcome {
+        errors.push(err);
+        self.resync_at_next_decl(chkpt.old_ptr);
+        self.note_unparsed_text(chkpt);
+      }
+    }
+    return (header, errors)
+  }
+  fn parse_module_line(&mut self, is_first: bool) -> Maybe<Symbol> {
+    guard! {
+      is_first => self.fail_with(ParseErrorKind::MisplacedModuleLine)
+    }
+    let _ = self.parse_symbol()?;
+    self.skip_whitespaces();
+    let name = self.parse_symbol()?;
+    self.expect_line_end(ParseContext::ModuleLine)?;
+    return Ok(name)
+  }
+  fn parse_import_line(&mut self) -> Maybe<Import> {
+    let loc = self.begin_sloc();
+    let _ = self.parse_symbol()?;
+    self.skip_whitespaces();
+    let module = self.parse_symbol()?;
+    self.skip_whitespaces();
+    let mut alias = None;
+    if self.match_word("as") {
+      self.skip_whitespaces();
+      alias = Some(self.parse_symbol()?);
+      self.skip_whitespaces();
+    }
+    let selection = if self.match_word("hiding") {
+      self.skip_whitespaces();
+      ImportSelection::Hiding(self.parse_name_list()?)
+    } else if self.get_current_char() == '(' {
+      ImportSelection::Only(self.parse_name_list()?)
+    } else {
+      ImportSelection::Everything
+    };
+    let location = self.end_sloc(loc);
+    self.expect_line_end(ParseContext::ImportLine)?;
+    return Ok(Import { module, alias, selection, location })
+  }
+  // consumes a given word only if it is a whole name
+  fn match_word(&mut self, word: &str) -> bool {
+    let chkpt = self.checkpoint();
+    if let Ok(symbol) = self.parse_symbol() {
+      if symbol.materialise_name() == word { return true }
+    }
+    self.backtrack_to(chkpt);
+    return false
+  }
+  fn parse_name_list(&mut self) -> Maybe<Vec<Symbol>> {
+    guard! {
+      self.prefix_match("(", true) =>
+      self.expected(&[Token::OpenParen], ParseContext::ImportList)
+    }
+    let mut names = Vec::new();
+    self.skip_whitespaces();
+    if self.prefix_match(")", true) { return Ok(names) }
+    loop {
+      self.skip_whitespaces();
+      names.push(self.parse_symbol()?);
+      self.skip_whitespaces();
+      if self.prefix_match(",", true) { continue; }
+      guard! {
+        self.prefix_match(")", true) =>
+        self.expected(&[Token::Comma, Token::CloseParen], ParseContext::ImportList)
+      }
+      return Ok(names)
+    }
+  }
+  fn expect_line_end(&mut self, context: ParseContext) -> Maybe<()> {
+    self.skip_whitespaces();
+    let char = self.get_current_char();
+    guard! {
+      char == '\n' || self.no_more_chars() =>
+      self.expected(&[Token::LineEnd], context)
+    }
+    return Ok(())
+  }
+}
+
+#[derive(Clone, Copy)]
+enum HeaderKeyword { Module, Import }
+
+// Everything that could be salvaged from a source text
+pub struct ParsedSource {
+  pub header: ModuleHeader,
+  pub declarations: Vec<Declaration>,
+  pub errors: Vec<ParseError>,
+}
+
+/// Recovery
+impl ParsingState {
+  // Parses declarations till the end of text. After an error
+  // parsing resumes at the start of the next declaration,
+  // so that one typo doesnt hide the rest of the file
+  pub fn parse_decls_with_recovery(&mut self) -> ParsedSource {
+    let (header, mut errors) = self.parse_module_header();
+    let mut declarations = Vec::new();
+    loop {
+      self.skip_trivia();
+      if self.no_more_chars() { break; }
+      let chkpt = self.checkpoint();
+      if self.probe_header_keyword().is_some() {
+        errors.push(self.fail_with(ParseErrorKind::MisplacedModuleLine));
+        self.resync_at_next_decl(chkpt.old_ptr);
+        self.note_unparsed_text(chkpt);
+        continue;
+      }
+      match self.parse_decl() {
+        Ok(decl) => declarations.push(decl),
+        Err(err) => {
+          errors.push(err);
+          self.resync_at_next_decl(chkpt.old_ptr);
+          self.note_unparsed_text(chkpt);
+        }
+      }
+    }
+    if let Some(err) = self.take_comment_error() {
+      let offset = err.span.primary_offset;
+      let index = errors.partition_point(|other| other.span.primary_offset <= offset);
+      errors.insert(index, err)
+    }
+    return ParsedSource { header, declarations, errors }
+  }
+  // declarations are the only thing that can start
+  // with a symbol at zero indentation.
+  // clauses begin at zero indentation too, but with a bar.
+  // parsing may have stopped right where the next declaration starts,
+  // so current line is checked first.
+  // the one that failed at `failed_at` is never resumed
+  pub fn resync_at_next_decl(&mut self, failed_at: usize) {
+    let at_line_start =
+      self.byte_index > failed_at &&
+      unsafe { *self.bytes.source_data.add(self.byte_index - 1) } == b'\n';
+    if at_line_start && !self.at_terminator() { return; }
+    loop {
+      self.skip_while(|self_| self_.get_current_char() != '\n');
+      if self.no_more_chars() { return; }
+      let depth = self.probe_depth();
+      if depth == 0 && !self.at_terminator() { return; }
+    }
+  }
+}
+
+
+/// Syntax tree recording
+impl ParsingState {
+  pub fn record_syntax_events(&mut self) {
+    self.syntax_events = Some(Vec::new())
+  }
+  pub(crate) fn take_syntax_events(&mut self) -> Vec<SyntaxEvent> {
+    return self.syntax_events.take().unwrap_or_default()
+  }
+  fn note_token(&mut self, kind: TokenKind, start: usize) {
+    if let Some(ref mut events) = self.syntax_events {
+      let range = SourceLocation {
+        primary_offset: start as u32,
+        secondary_offset: self.byte_index as u32
+      };
+      events.push(SyntaxEvent::Token(kind, range))
+    }
+  }
+  // nodes that failed to parse are dropped on backtracking
+  fn within_node<T>(
+    &mut self, kind: SyntaxKind, parse: impl FnOnce(&mut Self) -> Maybe<T>
+  ) -> Maybe<T> {
+    if let Some(ref mut events) = self.syntax_events {
+      events.push(SyntaxEvent::Start(kind))
+    }
+    let node = parse(self)?;
+    if let Some(ref mut events) = self.syntax_events {
+      events.push(SyntaxEvent::Finish)
+    }
+    return Ok(node)
+  }
+  // whatever was skipped during recovery is kept as a single token,
+  // minus the trivia that belongs to the next declaration
+  fn note_unparsed_text(&mut self, start: Checkpoint) {
+    let Some(ref mut events) = self.syntax_events else { return };
+    events.truncate(start.old_event_count);
+    let text = unsafe { str::from_utf8_unchecked(
+      std::slice::from_raw_parts(self.bytes.source_data, self.bytes.span as usize)) };
+    let end = last_token_end(text, start.old_ptr, self.byte_index);
+    let range = SourceLocation {
+      primary_offset: start.old_ptr as u32,
+      secondary_offset: end as u32
+    };
+    events.push(SyntaxEvent::Start(SyntaxKind::Error));
+    events.push(SyntaxEvent::Token(TokenKind::Unparsed, range));
+    events.push(SyntaxEvent::Finish);
+  }
+}
@@ -1,5 +1,5 @@
 
-use std::{alloc::{Layout, alloc, dealloc}, mem::size_of, panic, marker::PhantomData};
+use std::{alloc::{Layout, alloc, dealloc}, mem::size_of, panic, marker::PhantomData, ptr::null_mut};
 
 
 const Page4K : Layout = unsafe {
@@ -62,6 +62,9 @@ pub struct LinearAllocator<const MIN_ALLOC_SIZE : usize> {
   pub first_page: *mut (),
   pub current_page: *mut (),
   pub ptr: u16,
+  // requests that dont fit in a page get memory of their own.
+  // each such block starts with a pointer to the next one and its size
+  pub oversized_blocks: *mut (),
 }
 
 impl <const s : usize> LinearAllocator<s> {
@@ -70,7 +73,8 @@ impl <const s : usize> LinearAllocator<s> {
     *page.cast::<usize>() = usize::MAX;
     return Self { first_page: page,
                   current_page: page,
-                  ptr: (size_of::<usize>() / s).max(1) as u16, }
+                  ptr: (size_of::<usize>() / s).max(1) as u16,
+                  oversized_blocks: null_mut(), }
   } }
 }
 
@@ -103,7 +107,7 @@ impl <const s : usize> LinearAllocator<s> {
   pub fn get_contiguos_mem(&mut self, byte_count: usize) -> *mut () {
     let size_ = byte_count;
     if size_ >= 4096 - 8 {
-      panic!("Too much memory has been requested!")
+      return self.get_oversized_mem(byte_count)
     }
     let mut size = size_ / s;
     if (size_ - (size * s)) != 0 { size += 1 }
@@ -120,10 +124,29 @@ impl <const s : usize> LinearAllocator<s> {
 
     return mem
   }
+  fn get_oversized_mem(&mut self, byte_count: usize) -> *mut () { unsafe {
+    let header_size = size_of::<[usize;2]>();
+    let layout =
+      Layout::from_size_align_unchecked(header_size + byte_count, 16);
+    let block = alloc(layout);
+    if block.is_null() {
+      panic!("Too much memory has been requested!")
+    }
+    block.cast::<[usize;2]>().write(
+      [self.oversized_blocks as usize, header_size + byte_count]);
+    self.oversized_blocks = block.cast();
+    return block.add(header_size).cast()
+  } }
 }
 
 impl <const s : usize> Drop for LinearAllocator<s> {
   fn drop(&mut self) { unsafe {
+    let mut block = self.oversized_blocks.cast::<u8>();
+    while !block.is_null() {
+      let [next, size] = *block.cast::<[usize;2]>();
+      dealloc(block, Layout::from_size_align_unchecked(size, 16));
+      block = next as *mut u8;
+    }
     let mut ptr = self.first_page.cast::<u8>();
     loop {
       let tail = *ptr.cast::<usize>();
@@ -1,6 +1,6 @@
 
 use std::{
-  mem::{size_of, align_of, }, ptr::{null_mut, drop_in_place,},
+  mem::{size_of, }, ptr::{null_mut, drop_in_place,},
   alloc::{alloc, Layout, dealloc}, marker::PhantomData,
   intrinsics::{transmute,},
 };
@@ -38,10 +38,7 @@ impl <X, Y, I> DetachedClosure<X, Y, I> {
     (self.1 & ((1 << MAXIMUM_FPTR_BITWIDTH) - 1)) as *mut ()
   }
   fn project_destructor_ptr(&self) -> *mut () {
-    let base = (self.1 & ((1 << MAXIMUM_FPTR_BITWIDTH) - 1)) as isize;
-    let offset = (self.1 >> MAXIMUM_FPTR_BITWIDTH) as isize;
-    let dctor_ptr = (base + offset) as usize;
-    return dctor_ptr as *mut ();
+    return unsafe { *self.project_env_ptr().cast::<*mut ()>().sub(1) };
   }
   fn was_invoked(&self) -> bool {
     (self.0 & 1) == 1
@@ -82,47 +79,48 @@ fn common_drop_impl(target: &mut CommonClosureRepr) { unsafe {
 } }
 
 impl <X, Y, I> DetachedClosure<X, Y, I> {
+  // destructor is stored in a word right before the env.
+  // it used to be encoded as an i16 offset from the function,
+  // but codegen is free to place them arbitrarily far apart,
+  // and in bigger binaries it does.
+  // returns layout of the whole block and offset of env in it
+  pub fn env_layout() -> (Layout, usize) {
+    return Layout::new::<*mut ()>().extend(Layout::new::<X>()).unwrap();
+  }
   fn dctor(env_ptr: *mut (), need_env_drop: bool) { unsafe {
     if need_env_drop {
       drop_in_place(env_ptr.cast::<X>())
     }
-    dealloc(
-      env_ptr.cast::<u8>(),
-      Layout::from_size_align_unchecked(
-        size_of::<X>(), align_of::<X>()));
+    let (layout, offset) = Self::env_layout();
+    dealloc(env_ptr.cast::<u8>().sub(offset), layout);
+  } }
+  fn place_env(mem: *mut u8, env: X) -> *mut X { unsafe {
+    let (_, offset) = Self::env_layout();
+    let env_ptr = mem.add(offset).cast::<X>();
+    env_ptr.write(env);
+    env_ptr.cast::<*mut ()>().sub(1).write(Self::dctor as *mut ());
+    return env_ptr
   } }
+  // given mem must be allocated with the layout of env_layout
   pub fn init_with_given_mem(
     mem: *mut (), env: X, fun: fn (*mut X, Y) -> I
-  ) -> Self { unsafe {
-    mem.cast::<X>().write(env);
-    let fun_ = fun as *mut u8;
-    let dctor = Self::dctor as *mut u8;
-    let ptr_diff = dctor.offset_from(fun_);
-    let mem = (mem as u64) << 1;
-    let procs = (fun as u64) + (ptr_diff) as u64;
-    return Self(mem, procs, PhantomData)
-  } }
+  ) -> Self {
+    let env_ptr = Self::place_env(mem.cast::<u8>(), env);
+    let mem = (env_ptr as u64) << 1;
+    return Self(mem, fun as u64, PhantomData)
+  }
   pub fn init_with_global_mem(
     env: X, fun: fn (*mut X, Y) -> I
   ) -> Self { unsafe {
     if size_of::<X>() != 0 {
-      let mem = alloc(Layout::from_size_align_unchecked(
-        size_of::<X>(), align_of::<X>()));
-      mem.cast::<X>().write(env);
-      let fun_ = fun as *mut u8;
-      let fun_addr = fun_ as u64;
+      let (layout, _) = Self::env_layout();
+      let env_ptr = Self::place_env(alloc(layout), env);
+      let fun_addr = fun as usize as u64;
       if fun_addr > (1 << MAXIMUM_FPTR_BITWIDTH) - 1 as u64 {
         panic!("Address of a function is too far away. ({:#x})", fun_addr);
       }
-      let dctor = Self::dctor as *mut u8;
-      let ptr_diff = dctor.offset_from(fun_);
-      if ptr_diff > i16::MAX as isize || ptr_diff < i16::MIN as isize {
-        panic!("Cannot place destructor close enough to the function.");
-      }
-      let ptr_diff = (ptr_diff << MAXIMUM_FPTR_BITWIDTH) as u64;
-      let mem = (mem as u64) << 1;
-      let procs = fun_addr + ptr_diff;
-      return Self(mem, procs, PhantomData)
+      let mem = (env_ptr as u64) << 1;
+      return Self(mem, fun_addr, PhantomData)
     } else {
       return Self(0, fun as u64, PhantomData)
     };
@@ -150,13 +148,11 @@ impl <X, Y, I> Clone for DetachedClosure<X, Y, I> where X:Clone {
         transmute::<_, *const Self>(self).read();
       return copy;
     } else { // need to clone env
-      let new_env_mem =
-        alloc(Layout::from_size_align_unchecked(
-          size_of::<X>(), align_of::<X>()));
+      let (layout, _) = Self::env_layout();
       let env_ptr = self.project_env_ptr();
       let copy = (&*env_ptr.cast::<X>()).clone();
-      new_env_mem.cast::<X>().write(copy);
-      let mem = (new_env_mem as u64) << 1;
+      let new_env_ptr = Self::place_env(alloc(layout), copy);
+      let mem = (new_env_ptr as u64) << 1;
       return Self(mem , self.1, PhantomData)
     }
   } }
@@ -174,10 +170,7 @@ impl CommonClosureRepr {
     (self.1 & ((1 <<MAXIMUM_FPTR_BITWIDTH) - 1)) as *mut ()
   }
   fn project_destructor_ptr(&self) -> *mut () {
-    let base = (self.1 & ((1 << MAXIMUM_FPTR_BITWIDTH) - 1)) as isize;
-    let offset = (self.1 >> MAXIMUM_FPTR_BITWIDTH) as isize;
-    let dctor_ptr = (base + offset) as usize;
-    return dctor_ptr as *mut ();
+    return unsafe { *self.project_env_ptr().cast::<*mut ()>().sub(1) };
   }
   fn was_invoked(&self) -> bool {
     (self.0 & 1) == 1
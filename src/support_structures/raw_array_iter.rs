@@ -16,8 +16,8 @@ impl <T> RawArrayIter<T> {
     }
   }
   pub fn from_array_ptr(val: ArrayPtr<T>) -> Self {
-    let ptr = val.0.project_ptr();
-    let length = val.0.project_tag().length as u32;
+    let ptr = val.project_ptr();
+    let length = val.project_count();
     return Self::new(ptr, length)
   }
 }
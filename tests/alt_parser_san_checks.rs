@@ -1,5 +1,5 @@
 use proto_sigil::{
-  parser::new_parser::ParsingState,
+  parser::new_parser::{ParsingState, ParsedSource},
   expression_trees::more_text_rendering::render_expr_tree};
 
 
@@ -54,4 +54,290 @@ fn sel_clause () {
     "  ? true, true => A (B C) D E\n" +
     "  ? _ => C D (E A) B";
 
-}
\ No newline at end of file
+}
+
+#[test]
+fn recovers_after_broken_decls() {
+  let example_text = concat!(
+    "first : (Dot) -> Dot\n",
+    "| x = x\n",
+    "\n",
+    "id : {T} (T) -> T\n",
+    "| v => v\n",
+    "\n",
+    "second (Dot) -> Dot\n",
+    "| _ => pt\n",
+    "\n",
+    "unit : (Dot) -> Dot\n",
+    "| _ => pt\n",
+    "\n",
+    "third : (Dot -> Dot\n",
+  ).to_string();
+  let mut ps =
+    ParsingState::init(
+      &example_text);
+  let ParsedSource { declarations, errors, .. } =
+    ps.parse_decls_with_recovery();
+  assert!(errors.len() == 3, "{:#?}", errors);
+  let names = declarations.iter().map(|decl| {
+    decl.project_name().materialise_name()
+  }).collect::<Vec<_>>();
+  assert!(names == ["id", "unit"], "{:?}", names);
+}
+
+#[test]
+fn recovers_at_decl_where_error_was_found() {
+  // missing clauses are found only on the line of the next declaration
+  let example_text = concat!(
+    "broken : (Dot) -> Dot\n",
+    "next : Dot = pt\n",
+    "also_broken : Dot\n",
+    "last : Dot = pt\n",
+  ).to_string();
+  let mut ps =
+    ParsingState::init(
+      &example_text);
+  let ParsedSource { declarations, errors, .. } =
+    ps.parse_decls_with_recovery();
+  assert!(errors.len() == 2, "{:#?}", errors);
+  let names = declarations.iter().map(|decl| {
+    decl.project_name().materialise_name()
+  }).collect::<Vec<_>>();
+  assert!(names == ["next", "last"], "{:?}", names);
+}
+
+
+#[test]
+fn errors_say_what_was_expected() {
+  let example_text = "first : (Dot) -> Dot\n| x = x\n".to_string();
+  let mut ps =
+    ParsingState::init(
+      &example_text);
+  let err = ps.parse_decl().err().unwrap();
+  assert!(
+    err.to_string() == "expected `,` or `=>` after clause patterns",
+    "{}", err);
+  assert!(err.project_unexpected_text(&example_text) == "=");
+
+  let example_text = "w : [| A, B C |]\n".to_string();
+  let mut ps =
+    ParsingState::init(
+      &example_text);
+  let err = ps.parse_decl().err().unwrap();
+  assert!(err.to_string() == "expected `,` or `;` after witness premise");
+
+  let example_text = "mystery : Dot\nlater".to_string();
+  let mut ps =
+    ParsingState::init(
+      &example_text);
+  let err = ps.parse_decl().err().unwrap();
+  assert!(err.to_string() == "expected `=` or `|` after declaration type");
+
+  let example_text = "module a.b c\nimport d e\n".to_string();
+  let mut ps =
+    ParsingState::init(
+      &example_text);
+  let (_, errors) = ps.parse_module_header();
+  let errors = errors.iter().map(|err| err.to_string()).collect::<Vec<_>>();
+  assert!(errors == [
+    "expected end of line after module name",
+    "expected end of line after import",
+  ], "{:?}", errors);
+}
+
+
+#[test]
+fn unicode_and_operator_names() {
+  let example_text =
+    "≤ : {α, Nat'} (x1 : α, y : Nat') -> Dot\n| a, b => ¬ (a ≤ b) pt\n".to_string();
+  let mut ps =
+    ParsingState::init(
+      &example_text);
+  let decl = ps.parse_decl().unwrap();
+  assert!(decl.project_name().materialise_name() == "≤");
+  assert!(ps.current_position().line == 3);
+
+  for (text, expected) in [
+    ("x1 ", "x1"), ("Nat' ", "Nat'"), ("λx ", "λx"),
+    ("<=> ", "<=>"), ("∘) ", "∘"), ("_ok ", "_ok"),
+  ] {
+    let text = text.to_string();
+    let mut ps = ParsingState::init(&text);
+    let sym = ps.parse_symbol().unwrap();
+    assert!(sym.materialise_name() == expected, "{}", text);
+  }
+  for text in ["1x", "=> ", "-> ", ",", "'a"] {
+    let text = text.to_string();
+    let mut ps = ParsingState::init(&text);
+    assert!(ps.parse_symbol().is_err(), "{}", text);
+  }
+}
+
+
+#[test]
+fn comments_are_trivia() {
+  let example_text = concat!(
+    "-- leading comment\n",
+    "{- block {- nested -}\n",
+    "   still a comment -}\n",
+    "id : {T} (T) -> T -- trailing comment\n",
+    "-- commented out line in between clauses\n",
+    "  -- indented one too\n",
+    "| v => {- inline -} v\n",
+    "\n",
+    "const : {T} (T, T) -> T\n",
+    "| a, _ => a--glued to a name\n",
+    "{- the end -}\n",
+  ).to_string();
+  let mut ps =
+    ParsingState::init(
+      &example_text);
+  let ParsedSource { declarations, errors, .. } =
+    ps.parse_decls_with_recovery();
+  assert!(errors.is_empty(), "{:#?}", errors);
+  let names = declarations.iter().map(|decl| {
+    decl.project_name().materialise_name()
+  }).collect::<Vec<_>>();
+  assert!(names == ["id", "const"], "{:?}", names);
+}
+
+#[test]
+fn comments_at_line_start_dont_indent() {
+  let example_text = concat!(
+    "x : Dot = pt\n",
+    "{- c -}f : Dot = pt\n",
+    "g : (Dot) -> Dot\n",
+    "{- c -}| _ => pt\n",
+    "  {- c -} | _ => pt\n",
+  ).to_string();
+  let mut ps =
+    ParsingState::init(
+      &example_text);
+  let ParsedSource { declarations, errors, .. } =
+    ps.parse_decls_with_recovery();
+  assert!(errors.is_empty(), "{:#?}", errors);
+  let names = declarations.iter().map(|decl| {
+    decl.project_name().materialise_name()
+  }).collect::<Vec<_>>();
+  assert!(names == ["x", "f", "g"], "{:?}", names);
+}
+
+#[test]
+fn unterminated_comment_is_reported() {
+  let example_text = concat!(
+    "id : {T} (T) -> T\n",
+    "| v => v {- {- nested -}\n",
+    "\n",
+    "lost : Dot = pt\n",
+  ).to_string();
+  let mut ps =
+    ParsingState::init(
+      &example_text);
+  let ParsedSource { declarations, errors, .. } =
+    ps.parse_decls_with_recovery();
+  assert!(declarations.len() == 1);
+  assert!(errors.len() == 1, "{:#?}", errors);
+  assert!(errors[0].to_string() == "block comment is never closed");
+  assert!(errors[0].span.primary_offset == 27);
+  assert!(errors[0].project_unexpected_text(&example_text) == "{-");
+}
+
+
+#[test]
+fn doc_comments_are_attached() {
+  let example_text = concat!(
+    "--| Identity function.\n",
+    "--|   Returns its argument.\n",
+    "id : {T} (T) -> T\n",
+    "| v => v\n",
+    "\n",
+    "--| Detached by a blank line.\n",
+    "\n",
+    "-- plain comment\n",
+    "unit : (Dot) -> Dot\n",
+    "| _ => pt\n",
+  ).to_string();
+  let mut ps =
+    ParsingState::init(
+      &example_text);
+  let ParsedSource { declarations, errors, .. } =
+    ps.parse_decls_with_recovery();
+  assert!(errors.is_empty(), "{:#?}", errors);
+  let doc = declarations[0].doc_comment.unwrap().materialise_text();
+  assert!(doc == "Identity function.\n  Returns its argument.", "{:?}", doc);
+  assert!(declarations[1].doc_comment.is_none());
+}
+
+#[test]
+fn doc_comments_start_lines_outside_of_block_comments() {
+  let example_text = concat!(
+    "{- old\n",
+    "--| not a doc\n",
+    "-}\n",
+    "a : Dot = pt\n",
+    "\n",
+    "  --| indented\n",
+    "b : Dot = pt\n",
+    "\n",
+    "{- c -}\n",
+    "--| after a comment\n",
+    "c : Dot = pt\n",
+  ).to_string();
+  let mut ps =
+    ParsingState::init(
+      &example_text);
+  let ParsedSource { declarations, errors, .. } =
+    ps.parse_decls_with_recovery();
+  assert!(errors.is_empty(), "{:#?}", errors);
+  let docs = declarations.iter()
+    .map(|decl| decl.doc_comment.map(|doc| doc.materialise_text()))
+    .collect::<Vec<_>>();
+  assert!(docs == [None, None, Some("after a comment".to_string())], "{:?}", docs);
+}
+
+#[test]
+fn long_arrays_keep_their_length() {
+  use proto_sigil::expression_trees::better_nodes::{
+    RawNodeRepr, DeclKind, RawPatternKind};
+  const N : usize = 300;
+  let names = (0 .. N).map(|i| format!("a{i}")).collect::<Vec<_>>();
+  let example_text = format!(
+    "big : {{{}}} ({}) -> Dot\n| {} => f {}\n| _ => pt\n",
+    names.join(", "),
+    names.iter().map(|n| format!("{n} : Dot")).collect::<Vec<_>>().join(", "),
+    names.join(", "),
+    names.join(" "));
+  let mut ps = ParsingState::init(&example_text);
+  let decl = ps.parse_decl().unwrap();
+
+  let DeclKind::RawMapping { given_type, rewrite_rules, .. } = decl.repr
+  else { panic!() };
+  assert!(rewrite_rules.project_count() == 2);
+  let type_ = unsafe { *given_type };
+  assert!(type_.implicit_context.unwrap().project_count() == N as u32);
+  let RawNodeRepr::Fun { head, .. } = type_.kind else { panic!() };
+  assert!(head.project_count() == N as u32);
+
+  let clause = unsafe { *rewrite_rules.get_ptr(0) };
+  assert!(clause.matchers.project_count() == N as u32);
+  let last = unsafe { *clause.matchers.get_ptr(N as u32 - 1) };
+  let RawPatternKind::Mono(last) = last.repr else { panic!() };
+  assert!(last.materialise_name() == "a299");
+  let RawNodeRepr::App { arguments, .. } = unsafe { *clause.lhs }.kind
+  else { panic!() };
+  assert!(arguments.project_count() == N as u32);
+}
+
+#[test]
+fn arguments_dont_share_implicit_context() {
+  use proto_sigil::expression_trees::better_nodes::RawNodeRepr;
+  let example_text = "{A} Vec A zero".to_string();
+  let mut ps = ParsingState::init(&example_text);
+  let expr = ps.parse_expr(0).unwrap();
+  assert!(expr.implicit_context.unwrap().project_count() == 1);
+  let RawNodeRepr::App { arguments, .. } = expr.kind else { panic!() };
+  for i in 0 .. arguments.project_count() {
+    let arg = unsafe { *arguments.get_ptr(i) };
+    assert!(arg.implicit_context.is_none());
+  }
+}
@@ -0,0 +1,50 @@
+// each test crate uses only some of what is here
+#![allow(dead_code)]
+
+use std::{
+  fs, ops::Deref,
+  path::{Path, PathBuf},
+  sync::atomic::{AtomicUsize, Ordering},
+};
+
+
+static DIRS_MADE: AtomicUsize = AtomicUsize::new(0);
+
+// Folder with source files for one test, removed when it goes out of scope.
+// Every folder gets its own path, so tests that run in parallel
+// or runs of the same test dont see files of each other
+pub struct SourceDir {
+  path: PathBuf,
+}
+
+impl SourceDir {
+  pub fn path(&self) -> PathBuf {
+    return self.path.clone()
+  }
+}
+
+impl Deref for SourceDir {
+  type Target = Path;
+  fn deref(&self) -> &Path {
+    return &self.path
+  }
+}
+
+impl Drop for SourceDir {
+  fn drop(&mut self) {
+    let _ = fs::remove_dir_all(&self.path);
+  }
+}
+
+pub fn setup_dir(name: &str, files: &[(&str, &str)]) -> SourceDir {
+  let index = DIRS_MADE.fetch_add(1, Ordering::Relaxed);
+  let path = std::env::temp_dir()
+    .join(format!("sigil-{}-{}-{}", name, std::process::id(), index));
+  // left over by a run that was killed midway
+  let _ = fs::remove_dir_all(&path);
+  fs::create_dir_all(&path).unwrap();
+  for (file_name, text) in files {
+    fs::write(path.join(file_name), text).unwrap();
+  }
+  return SourceDir { path }
+}
@@ -0,0 +1,117 @@
+use proto_sigil::{
+  parser::{
+    new_parser::{ParsingState, ParsedSource},
+    concrete_syntax::{SyntaxTree, SyntaxKind, SyntaxElement, TokenKind},
+  },
+  expression_trees::more_text_rendering::{render_declaration, DEFAULT_LINE_WIDTH},
+};
+
+
+const SOURCE : &str = concat!(
+  "-- leading comment\n",
+  "\n",
+  "--| identity\n",
+  "id : {T} (T) -> T -- trailing\n",
+  "| v   =>  v\n",
+  "\n",
+  "broken : ) oops\n",
+  "  more garbage\n",
+  "\n",
+  "{- block -}\n",
+  "k : {A, B} (A, B) -> A = \\{ | a, _ => a }\n",
+  "w : [| A, (B) ; C |]\n",
+  "  | _ => pt {- after -}\n",
+  "\n",
+  "-- the end",
+);
+
+#[test]
+fn text_is_kept_exactly() {
+  let tree = SyntaxTree::parse(SOURCE);
+  assert!(tree.render() == SOURCE);
+  assert!(tree.errors.len() == 1);
+
+  let kinds = tree.root().child_nodes().map(|node| node.kind).collect::<Vec<_>>();
+  assert!(kinds == [
+    SyntaxKind::Declaration, SyntaxKind::Error,
+    SyntaxKind::Declaration, SyntaxKind::Declaration]);
+  let broken = tree.root().child_nodes().nth(1).unwrap();
+  assert!(tree.node_text(broken) == "broken : ) oops\n  more garbage");
+
+  let last = tree.tokens().last().unwrap();
+  assert!(last.kind == TokenKind::EndOfText);
+  assert!(tree.slice(last.leading_trivia) == "\n\n-- the end");
+}
+
+#[test]
+fn tokens_carry_their_trivia() {
+  let tree = SyntaxTree::parse(SOURCE);
+  let id = tree.declarations().next().unwrap();
+  let SyntaxElement::Token(name) = id.children[0] else { panic!() };
+  let name = tree.tokens()[name as usize];
+  assert!(name.kind == TokenKind::Name);
+  assert!(tree.token_text(&name) == "id");
+  assert!(tree.slice(name.leading_trivia) == "-- leading comment\n\n--| identity\n");
+  assert!(tree.slice(name.trailing_trivia) == " ");
+
+  let arrow = tree.tokens().iter()
+    .find(|token| token.kind == TokenKind::ThinArrow).unwrap();
+  let arrow_end = arrow.range.secondary_offset;
+  let spine = tree.tokens().iter()
+    .find(|token| token.range.primary_offset > arrow_end).unwrap();
+  assert!(tree.token_text(spine) == "T");
+  assert!(tree.slice(spine.trailing_trivia) == " -- trailing");
+
+  let fat_arrow = tree.tokens().iter()
+    .find(|token| token.kind == TokenKind::FatArrow).unwrap();
+  assert!(tree.slice(fat_arrow.leading_trivia) == "");
+  assert!(tree.slice(fat_arrow.trailing_trivia) == "  ");
+
+  let k = tree.declarations().nth(1).unwrap();
+  assert!(tree.node_text(k).starts_with("k : {A, B}"));
+  let nested = k.child_nodes().map(|node| node.kind).collect::<Vec<_>>();
+  assert!(nested == [SyntaxKind::Expression, SyntaxKind::Expression]);
+  let lambda = k.child_nodes().nth(1).unwrap().child_nodes().next().unwrap();
+  assert!(lambda.kind == SyntaxKind::Lambda);
+  assert!(tree.node_text(lambda) == "\\{ | a, _ => a }");
+}
+
+#[test]
+fn lowering_matches_direct_parse() {
+  let tree = SyntaxTree::parse(SOURCE);
+  let lowered = tree.lower();
+
+  let source = SOURCE.to_string();
+  let mut ps = ParsingState::init(&source);
+  let ParsedSource { declarations, .. } = ps.parse_decls_with_recovery();
+  assert!(lowered.len() == declarations.len());
+  for (l, r) in lowered.iter().zip(declarations.iter()) {
+    assert!(
+      render_declaration(l, DEFAULT_LINE_WIDTH) ==
+      render_declaration(r, DEFAULT_LINE_WIDTH));
+    let name = l.project_name();
+    assert!(name == r.project_name());
+    assert!(name.location.primary_offset == r.project_name().location.primary_offset);
+  }
+
+  for (node, decl) in tree.declarations().zip(lowered.iter()) {
+    let found = tree.lower_declaration(node).unwrap();
+    assert!(found.project_name() == decl.project_name());
+  }
+  let broken = tree.root().child_nodes()
+    .find(|node| node.kind == SyntaxKind::Error).unwrap();
+  assert!(tree.lower_declaration(broken).is_none());
+  let other = SyntaxTree::parse(SOURCE);
+  assert!(tree.lower_declaration(other.declarations().next().unwrap()).is_none());
+}
+
+#[test]
+fn comments_can_hold_any_text() {
+  let text = "{- λ {- ∀ -} -}\nid : {T} (T) -> T {- → -}\n| v => v -- λ\n";
+  let tree = SyntaxTree::parse(text);
+  assert!(tree.errors.is_empty());
+  assert!(tree.render() == text);
+  let codomain = tree.tokens().iter()
+    .filter(|token| token.kind == TokenKind::Name).nth(3).unwrap();
+  assert!(tree.slice(codomain.trailing_trivia) == " {- → -}");
+}
@@ -0,0 +1,89 @@
+use proto_sigil::elaborator::{
+  main::{elaborate_directory, ElaborationOutcome},
+  diagnostics::Kind,
+};
+
+mod common;
+use common::setup_dir;
+
+
+fn missing_cases(outcome: &ElaborationOutcome) -> Vec<(&'static str, String)> {
+  return outcome.diagnostics.collect_reports().into_iter()
+    .filter_map(|(_, report)| match report.kind {
+      Kind::MissingCases { name, example } => Some((name.materialise_name(), example)),
+      _ => None,
+    }).collect()
+}
+
+
+#[test]
+fn exhaustive_rules_are_accepted() {
+  let dir = setup_dir("fine", &[("a.sigil", concat!(
+    "Bool : * = Either Dot Dot\n",
+    "\n",
+    "and : (Bool, Bool) -> Bool\n",
+    "| inl _, b => b\n",
+    "| inr _, _ => inr pt\n",
+    "\n",
+    "swap : (Pair Bool Dot) -> Pair Dot Bool\n",
+    "| two (inl x) y => two y (inr x)\n",
+    "| two (inr x) y => two x (inl y)\n",
+    "\n",
+    "id : {T} (T) -> T\n",
+    "| v => v\n",
+  ))]);
+  let outcome = elaborate_directory(dir.path());
+  assert!(!outcome.diagnostics.did_record_any_issues(), "{}", outcome.render_reports());
+}
+
+#[test]
+fn missing_cases_come_with_example() {
+  let dir = setup_dir("missing", &[("a.sigil", concat!(
+    "Bool : * = Either Dot Dot\n",
+    "\n",
+    "not : (Bool) -> Bool\n",
+    "| inl _ => inr pt\n",
+    "\n",
+    "both : (Bool, Pair Bool Bool) -> Bool\n",
+    "| inr _, _ => inr pt\n",
+    "| inl _, two (inl _) b => b\n",
+    "| inl _, two _ (inr _) => inr pt\n",
+  ))]);
+  let outcome = elaborate_directory(dir.path());
+  let missing = missing_cases(&outcome);
+  assert!(missing == [
+    ("not", "inr _".to_string()),
+    ("both", "inl _, two (inr _) (inl _)".to_string()),
+  ], "{:?}", missing);
+  let rendered = outcome.render_reports();
+  assert!(rendered.contains("rules of `not` dont cover every case, for example `inr _`"), "{}", rendered);
+  assert!(rendered.contains("some inputs match no rule"), "{}", rendered);
+}
+
+#[test]
+fn rules_after_catch_all_are_unreachable() {
+  let dir = setup_dir("unreachable", &[("a.sigil", concat!(
+    "Bool : * = Either Dot Dot\n",
+    "\n",
+    "not : (Bool) -> Bool\n",
+    "| inl _ => inr pt\n",
+    "| inr _ => inl pt\n",
+    "| inl pt => inl pt\n",
+    "\n",
+    "const : (Bool) -> Bool\n",
+    "| b => b\n",
+    "| inr _ => inl pt\n",
+  ))]);
+  let outcome = elaborate_directory(dir.path());
+  let lines = outcome.diagnostics.collect_reports().into_iter()
+    .map(|(_, report)| match report.kind {
+      Kind::UnreachableRule(loc) => {
+        let text = &outcome.source_files[0].text[.. loc.primary_offset as usize];
+        text.matches('\n').count() + 1
+      },
+      _ => panic!("{:#?}", report),
+    }).collect::<Vec<_>>();
+  assert!(lines == [6, 10], "{:?}\n{}", lines, outcome.render_reports());
+  let rendered = outcome.render_reports();
+  assert!(rendered.contains("rule can never fire"), "{}", rendered);
+}
@@ -1,5 +1,5 @@
 
-use std::{thread::{self, sleep}, time::Duration};
+use std::{thread::{self, sleep}, time::Duration, rc::Rc, alloc::alloc};
 
 use proto_sigil::{
   support_structures::no_bullshit_closure::{DetachedClosure,LocalClosure},
@@ -114,3 +114,59 @@ fn clos_works9 () {
   clos.invoke_once(());
   assert!(str == "!!");
 }
+
+#[test]
+fn dropped_clos_drops_env () {
+  let rc = Rc::new(());
+  let clos = detached!([rc = rc.clone()] {
+    let _ = rc;
+  });
+  assert!(Rc::strong_count(&rc) == 2);
+  drop(clos);
+  assert!(Rc::strong_count(&rc) == 1);
+}
+
+#[test]
+fn cloned_clos_has_own_env () {
+  let rc = Rc::new(7u64);
+  let clos = detached!([rc = rc.clone()] {
+    return *rc;
+  });
+  let copy = clos.clone();
+  assert!(Rc::strong_count(&rc) == 3);
+  assert!(clos.invoke_consume(()) == 7);
+  assert!(Rc::strong_count(&rc) == 2);
+  assert!(copy.invoke_consume(()) == 7);
+  assert!(Rc::strong_count(&rc) == 1);
+}
+
+#[test]
+fn overaligned_env_is_placed_right () {
+  #[repr(align(64))] #[derive(Clone)]
+  struct Wide([u8; 3]);
+  let clos =
+  DetachedClosure::<Wide, (), _>::init_with_global_mem(
+  Wide([1, 2, 3]), |env, _| {
+    assert!(env as usize % 64 == 0);
+    let env = unsafe { env.read() };
+    return env.0
+  });
+  let copy = clos.clone();
+  assert!(clos.invoke_consume(()) == [1, 2, 3]);
+  assert!(copy.invoke_consume(()) == [1, 2, 3]);
+}
+
+#[test]
+fn clos_in_given_mem () {
+  let rc = Rc::new(());
+  let (layout, _) = DetachedClosure::<Rc<()>, (), ()>::env_layout();
+  let mem = unsafe { alloc(layout) };
+  let clos =
+  DetachedClosure::<Rc<()>, (), ()>::init_with_given_mem(
+  mem.cast(), rc.clone(), |env, _| {
+    let _ = unsafe { env.read() };
+  });
+  assert!(Rc::strong_count(&rc) == 2);
+  clos.invoke_consume(());
+  assert!(Rc::strong_count(&rc) == 1);
+}
@@ -0,0 +1,121 @@
+use proto_sigil::elaborator::{
+  main::{elaborate_directory, ElaborationOutcome},
+  diagnostics::Kind,
+};
+
+mod common;
+use common::setup_dir;
+
+
+fn cyclic_names(outcome: &ElaborationOutcome) -> Vec<&'static str> {
+  let mut names = outcome.source_files.iter()
+    .flat_map(|file| file.declarations.iter())
+    .filter(|decl| decl.participate_in_cycle_formation)
+    .map(|decl| decl.project_name().materialise_name())
+    .collect::<Vec<_>>();
+  names.sort();
+  return names
+}
+
+fn reported_cycles(outcome: &ElaborationOutcome) -> Vec<Vec<&'static str>> {
+  return outcome.diagnostics.collect_reports().iter().map(|(_, report)| {
+    let Kind::DefinitionCycle(ref path) = report.kind else { panic!("{:#?}", report) };
+    return path.iter().map(|step| step.materialise_name()).collect()
+  }).collect()
+}
+
+
+#[test]
+fn recursion_through_rules_is_allowed() {
+  let dir = setup_dir("rules", &[("a.sigil", concat!(
+    "Bool : * = Either Dot Dot\n",
+    "\n",
+    "yes : Bool = inl pt\n",
+    "\n",
+    "--| @partial\n",
+    "loop : (Dot) -> Dot\n",
+    "| x => loop x\n",
+    "\n",
+    "--| @partial\n",
+    "even : (Bool) -> Bool\n",
+    "| inl _ => odd (inr pt)\n",
+    "| inr _ => yes\n",
+    "\n",
+    "odd : (Bool) -> Bool\n",
+    "| b => even b\n",
+    "\n",
+    "Fn : * = (Dot) -> Dot\n",
+    "\n",
+    "spin : Fn = \\{ | x => spin x }\n",
+    "\n",
+    "used : Dot = loop pt\n",
+  ))]);
+  let outcome = elaborate_directory(dir.path());
+  assert!(!outcome.diagnostics.did_record_any_issues(), "{}", outcome.render_reports());
+  assert!(cyclic_names(&outcome) == ["even", "loop", "odd", "spin"], "{:?}", cyclic_names(&outcome));
+}
+
+#[test]
+fn definitions_that_unfold_forever_are_reported() {
+  let dir = setup_dir("defs", &[("a.sigil", concat!(
+    "Bool : * = Either Dot Dot\n",
+    "\n",
+    "not : (Bool) -> Bool\n",
+    "| inl _ => inr pt\n",
+    "| inr _ => inl pt\n",
+    "\n",
+    "a : Dot = b\n",
+    "\n",
+    "b : Dot = c\n",
+    "\n",
+    "c : Dot = a\n",
+    "\n",
+    "flip : Bool = not flip\n",
+    "\n",
+    "fine : Bool = not (inl pt)\n",
+  ))]);
+  let outcome = elaborate_directory(dir.path());
+  let cycles = reported_cycles(&outcome);
+  assert!(cycles == [
+    vec!["a", "b", "c", "a"],
+    vec!["flip", "flip"],
+  ], "{:?}\n{}", cycles, outcome.render_reports());
+  assert!(cyclic_names(&outcome) == ["a", "b", "c", "flip"]);
+
+  let rendered = outcome.render_reports();
+  assert!(rendered.contains("without end: a -> b -> c -> a"), "{}", rendered);
+  assert!(rendered.contains("unfolds into `c` here"), "{}", rendered);
+}
+
+#[test]
+fn cycles_are_followed_across_files() {
+  let dir = setup_dir("files", &[
+    ("a.sigil", concat!(
+      "module A\n",
+      "\n",
+      "import B\n",
+      "\n",
+      "x : Dot = y\n",
+    )),
+    ("b.sigil", concat!(
+      "module B\n",
+      "\n",
+      "import A\n",
+      "\n",
+      "y : Dot = A.x\n",
+    )),
+  ]);
+  let outcome = elaborate_directory(dir.path());
+  let cycles = outcome.diagnostics.collect_reports().into_iter()
+    .filter_map(|(origin, report)| match report.kind {
+      Kind::DefinitionCycle(path) => Some((origin, path)),
+      _ => None,
+    }).collect::<Vec<_>>();
+  assert!(cycles.len() == 1, "{}", outcome.render_reports());
+  let (origin, ref path) = cycles[0];
+  assert!(origin.0 == 0);
+  let names = path.iter().map(|step| step.materialise_name()).collect::<Vec<_>>();
+  assert!(names == ["A.x", "B.y", "A.x"], "{:?}", names);
+  let rendered = outcome.render_reports();
+  assert!(rendered.contains("b.sigil:5:11"), "{}", rendered);
+}
@@ -0,0 +1,145 @@
+use proto_sigil::elaborator::{
+  main::{elaborate_directory, ElaborationOutcome},
+  diagnostics::Kind,
+};
+
+mod common;
+use common::setup_dir;
+
+
+fn missing_cases(outcome: &ElaborationOutcome) -> Vec<(&'static str, String)> {
+  return outcome.diagnostics.collect_reports().into_iter()
+    .filter_map(|(_, report)| match report.kind {
+      Kind::MissingCases { name, example } => Some((name.materialise_name(), example)),
+      _ => None,
+    }).collect()
+}
+
+const NAT : &str = concat!(
+  "data Nat : *\n",
+  "| zero : Nat\n",
+  "| succ : (Nat) -> Nat\n",
+  "\n",
+);
+
+
+#[test]
+fn recursion_on_constructors_is_accepted() {
+  let text = format!("{}{}", NAT, concat!(
+    "add : (Nat, Nat) -> Nat\n",
+    "| zero, m => m\n",
+    "| succ n, m => succ (add n m)\n",
+    "\n",
+    "two : Nat = add (succ zero) (succ zero)\n",
+  ));
+  let dir = setup_dir("nat", &[("a.sigil", &text)]);
+  let outcome = elaborate_directory(dir.path());
+  assert!(!outcome.diagnostics.did_record_any_issues(), "{}", outcome.render_reports());
+}
+
+#[test]
+fn missing_constructors_are_named() {
+  let text = format!("{}{}", NAT, concat!(
+    "pred : (Nat) -> Nat\n",
+    "| zero => zero\n",
+    "\n",
+    "is_two : (Nat) -> Dot\n",
+    "| succ (succ zero) => pt\n",
+    "| zero => pt\n",
+  ));
+  let dir = setup_dir("missing", &[("a.sigil", &text)]);
+  let outcome = elaborate_directory(dir.path());
+  let mut found = missing_cases(&outcome);
+  found.sort();
+  assert!(found == [
+    ("is_two", "succ zero".to_string()),
+    ("pred", "succ _".to_string()),
+  ], "{:?}\n{}", found, outcome.render_reports());
+}
+
+#[test]
+fn negative_occurrences_are_rejected() {
+  let dir = setup_dir("positivity", &[("a.sigil", concat!(
+    "data Bad : *\n",
+    "| mk : ((Bad) -> Dot) -> Bad\n",
+    "\n",
+    "data Fine : *\n",
+    "| leaf : Fine\n",
+    "| node : ((Dot) -> Fine) -> Fine\n",
+    "\n",
+    "data Odd : *\n",
+    "| odd : Dot\n",
+  ))]);
+  let outcome = elaborate_directory(dir.path());
+  let mut found = outcome.diagnostics.collect_reports().into_iter()
+    .map(|(_, report)| match report.kind {
+      Kind::NonPositiveOccurrence { data, .. } => ("positivity", data.materialise_name()),
+      Kind::ForeignConstructorType { data, .. } => ("foreign", data.materialise_name()),
+      other => panic!("{:?}", other),
+    }).collect::<Vec<_>>();
+  found.sort();
+  assert!(found == [("foreign", "Odd"), ("positivity", "Bad")], "{:?}", found);
+  let rendered = outcome.render_reports();
+  assert!(rendered.contains("not strictly positive"), "{}", rendered);
+}
+
+#[test]
+fn indices_rule_out_constructors() {
+  let text = format!("{}{}", NAT, concat!(
+    "data Vec : (*, Nat) -> *\n",
+    "| nil : {A} Vec A zero\n",
+    "| cons : {A, n : Nat} (A, Vec A n) -> Vec A (succ n)\n",
+    "\n",
+    "head : {A, n : Nat} (Vec A (succ n)) -> A\n",
+    "| cons x _ => x\n",
+    "\n",
+    "length : {A, n : Nat} (Vec A n) -> Nat\n",
+    "| nil => zero\n",
+    "| cons _ xs => succ (length xs)\n",
+    "\n",
+    "first : {A} (Vec A zero) -> Dot\n",
+    "| nil => pt\n",
+    "| cons _ _ => pt\n",
+  ));
+  let dir = setup_dir("vec", &[("a.sigil", &text)]);
+  let outcome = elaborate_directory(dir.path());
+  let reports = outcome.diagnostics.collect_reports();
+  // cons cant build an empty vector
+  assert!(
+    reports.len() == 1 && matches!(reports[0].1.kind, Kind::MismatchedType { .. }),
+    "{}", outcome.render_reports());
+}
+
+#[test]
+fn only_indices_bound_by_the_clause_are_refined() {
+  let text = format!("{}{}", NAT, concat!(
+    "data Vec : (*, Nat) -> *\n",
+    "| nil : {A} Vec A zero\n",
+    "| cons : {A, n : Nat} (A, Vec A n) -> Vec A (succ n)\n",
+    "\n",
+    "parametric : {T} (T) -> T\n",
+    "| zero => zero\n",
+    "| succ n => n\n",
+    "\n",
+    "outer : {A, n : Nat} (Vec A n, (Vec A n) -> Nat) -> Nat\n",
+    "| v, f => f v\n",
+    "\n",
+    "fixed : {A, n : Nat} (Vec A n) -> Nat\n",
+    "| v => outer v (\\{ | nil => zero | cons _ _ => zero })\n",
+  ));
+  let dir = setup_dir("refine", &[("a.sigil", &text)]);
+  let outcome = elaborate_directory(dir.path());
+  let mut found = outcome.diagnostics.collect_reports().into_iter()
+    .map(|(_, report)| match report.kind {
+      Kind::MismatchedType { term_expr, .. } =>
+        text[term_expr.primary_offset as usize .. term_expr.secondary_offset as usize]
+        .trim_end().to_string(),
+      _ => panic!("{:#?}", report)
+    }).collect::<Vec<_>>();
+  found.sort();
+  // `T` is a parameter, not an index of a matched data type,
+  // and a lambda cant refine `n` of the clause it is in
+  assert!(found == [
+    "cons _ _", "n", "nil", "succ n", "zero", "zero",
+  ], "{:?}\n{}", found, outcome.render_reports());
+}
@@ -0,0 +1,125 @@
+use proto_sigil::elaborator::{
+  main::{elaborate_directory, elab_invocation_setup},
+  diagnostics::Kind,
+  worker::WorkGroup,
+};
+use proto_sigil::expression_trees::raw_syntax_nodes::FileId;
+
+mod common;
+use common::setup_dir;
+
+
+#[test]
+fn clean_sources_elaborate_quietly() {
+  let dir = setup_dir("clean", &[
+    ("a.sigil", "id : {T} (T) -> T\n| v => v\n"),
+    ("b.sigil", "unit : (Dot) -> Dot\n| _ => pt\n"),
+    ("ignored.txt", "not even close"),
+  ]);
+  let outcome = elaborate_directory(dir.path());
+
+  assert!(outcome.source_files.len() == 2);
+  for file in &outcome.source_files {
+    assert!(file.declarations.len() == 1);
+  }
+  assert!(!outcome.diagnostics.did_record_any_issues());
+}
+
+#[test]
+fn duplicate_decls_are_reported() {
+  let dir = setup_dir("dupes", &[
+    ("a.sigil", "id : {T} (T) -> T\n| v => v\n"),
+    ("b.sigil", "id : {K} (K) -> K\n| v => v\n"),
+  ]);
+  let outcome = elaborate_directory(dir.path());
+
+  let reports = outcome.diagnostics.collect_reports();
+  assert!(reports.len() == 1);
+  let (origin, report) = &reports[0];
+  assert!(*origin == FileId(1));
+  match report.kind {
+    Kind::DuplicateDecls { one, another } => {
+      assert!(one == another)
+    },
+    _ => panic!("{:#?}", report)
+  }
+  assert!(report.kind.to_string() == "`id` is declared more than once");
+}
+
+#[test]
+fn earlier_declarations_keep_their_names() {
+  let dir = setup_dir("dupes-order", &[
+    ("a.sigil", "unit : (Dot) -> Dot\n| _ => pt\n\nid : {T} (T) -> T\n| v => v\n"),
+    ("b.sigil", "id : {K} (K) -> K\n| v => v\n"),
+    ("c.sigil", "id : Dot = pt\n\nunit : Dot = pt\n"),
+  ]);
+  // parsing order changes from run to run, outcome shouldnt
+  for _ in 0 .. 20 {
+    let outcome = elaborate_directory(dir.path());
+    let mut reports = outcome.diagnostics.collect_reports().into_iter()
+      .map(|(origin, report)| match report.kind {
+        Kind::DuplicateDecls { another, .. } =>
+          (origin.0, another.location.primary_offset),
+        _ => panic!("{:#?}", report)
+      }).collect::<Vec<_>>();
+    reports.sort();
+    assert!(reports == [(1, 0), (2, 0), (2, 15)], "{:?}", reports);
+    assert!(outcome.source_files[0].declarations.len() == 2);
+  }
+}
+
+#[test]
+fn problems_are_attributed_to_files() {
+  let dir = setup_dir("attribution", &[
+    ("a.sigil", "fine : (Dot) -> Dot\n| x => x\n"),
+    ("b.sigil", "broken : (Dot) -> Dot\n| x => y\n"),
+    ("c.sigil", "oops : (Dot) -> Dot\n| x =>\n"),
+  ]);
+  let outcome = elaborate_directory(dir.path());
+
+  assert!(outcome.diagnostics.count_reports_from(FileId(0)) == 0);
+  assert!(outcome.diagnostics.count_reports_from(FileId(1)) == 1);
+  assert!(outcome.diagnostics.count_reports_from(FileId(2)) == 1);
+  assert!(outcome.source_files[1].declarations[0].is_malformed);
+  let malformed = outcome.diagnostics.collect_reports().into_iter().any(
+    |(origin, report)|
+      origin == FileId(2) && matches!(report.kind, Kind::MalformedSyntax(_)));
+  assert!(malformed);
+}
+
+#[test]
+fn long_clause_lists_are_checked() {
+  let clauses = "| _ => pt\n".repeat(300);
+  let text = format!("many : (Dot) -> Dot\n{}", clauses);
+  let dir = setup_dir("long", &[("a.sigil", &text)]);
+  let outcome = elaborate_directory(dir.path());
+
+  assert!(outcome.source_files[0].declarations.len() == 1);
+  // every clause after the first is checked, and found to be useless
+  let reports = outcome.diagnostics.collect_reports();
+  assert!(reports.len() == 299, "{}", outcome.render_reports());
+  assert!(reports.iter().all(|(_, report)| matches!(report.kind, Kind::UnreachableRule(_))));
+}
+
+#[test]
+fn missing_directory_is_reported() {
+  let dir = setup_dir("gone", &[]);
+  let path = dir.join("nowhere");
+  let outcome = elaborate_directory(path);
+
+  assert!(outcome.source_files.is_empty());
+  let reports = outcome.diagnostics.collect_reports();
+  assert!(reports.len() == 1, "{:#?}", reports);
+  assert!(reports[0].0 == FileId::DETACHED);
+  assert!(matches!(reports[0].1.kind, Kind::UnreadableDirectory(_)));
+  let rendered = outcome.render_reports();
+  assert!(rendered.starts_with("error: cant read directory of sources: "), "{}", rendered);
+}
+
+#[test]
+fn graph_run_by_caller_leaves_failures_to_it() {
+  // used to end the whole process on a problem
+  let dir = setup_dir("direct", &[]);
+  let executor = WorkGroup::init(elab_invocation_setup(dir.join("nowhere")));
+  executor.await_completion();
+}
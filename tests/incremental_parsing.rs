@@ -0,0 +1,191 @@
+use proto_sigil::{
+  parser::{
+    new_parser::{ParsingState, ParsedSource, ParseError},
+    incremental::{IncrementalSource, TextEdit},
+  },
+  expression_trees::{
+    better_nodes::{Declaration, DeclKind, Symbol},
+    raw_syntax_nodes::{SourceLocation, FileId},
+    more_text_rendering::{render_declaration, DEFAULT_LINE_WIDTH},
+  },
+};
+
+
+const SOURCE : &str = concat!(
+  "--| first\n",
+  "a : (Dot) -> Dot\n",
+  "| _ => pt\n",
+  "\n",
+  "b : {T} (T) -> T\n",
+  "| x => x\n",
+  "\n",
+  "--| third\n",
+  "c : Dot = pt\n",
+  "\n",
+  "d : (Dot, Dot) -> Dot\n",
+  "| x, _ => x\n",
+);
+
+fn type_location(decl: &Declaration) -> SourceLocation {
+  match decl.repr {
+    DeclKind::RawMapping { given_type, .. } |
+    DeclKind::RawDefinition { given_type, .. } => unsafe { (*given_type).location },
+    _ => unreachable!(),
+  }
+}
+
+// incremental result has to be indistinguishable from parsing from scratch
+fn assert_agrees_with_full_parse(source: &IncrementalSource) {
+  let text = source.text().to_string();
+  let mut ps = ParsingState::init(&text);
+  let ParsedSource { declarations, errors, .. } = ps.parse_decls_with_recovery();
+  let incremental = source.declarations();
+  assert!(incremental.len() == declarations.len(), "{}", text);
+  for (l, r) in incremental.iter().zip(declarations.iter()) {
+    let (ln, rn) = (l.project_name(), r.project_name());
+    assert!(ln == rn);
+    assert!(ln.location.primary_offset == rn.location.primary_offset, "{}", text);
+    let (lt, rt) = (type_location(l), type_location(r));
+    assert!(lt.primary_offset == rt.primary_offset, "{}", text);
+    assert!(lt.secondary_offset == rt.secondary_offset, "{}", text);
+    assert!(
+      render_declaration(l, DEFAULT_LINE_WIDTH) ==
+      render_declaration(r, DEFAULT_LINE_WIDTH));
+    let doc_of = |decl: &Declaration| decl.doc_comment.map(|doc| doc.materialise_text());
+    assert!(doc_of(l) == doc_of(r), "{}", text);
+  }
+  let spans = |errors: &[ParseError]| errors.iter()
+    .map(|err| err.span.primary_offset)
+    .collect::<Vec<_>>();
+  assert!(spans(&source.errors()) == spans(&errors), "{}\n{:?}\n{:?}", text, spans(&source.errors()), spans(&errors));
+}
+
+fn replace(source: &IncrementalSource, old: &str, new: &str) -> TextEdit {
+  let start = source.text().find(old).unwrap();
+  return TextEdit {
+    range: SourceLocation {
+      primary_offset: start as u32,
+      secondary_offset: (start + old.len()) as u32
+    },
+    replacement: new.to_string()
+  }
+}
+
+fn names(symbols: &[Symbol]) -> Vec<&'static str> {
+  symbols.iter().map(|symbol| symbol.materialise_name()).collect()
+}
+
+#[test]
+fn edits_report_what_changed() {
+  let mut source = IncrementalSource::parse(SOURCE, FileId(0));
+  assert_agrees_with_full_parse(&source);
+
+  let changes = source.apply_edit(&replace(&source, "| x => x", "| y => y"));
+  assert!(names(&changes.changed) == ["b"]);
+  assert!(changes.added.is_empty() && changes.removed.is_empty());
+  assert_agrees_with_full_parse(&source);
+
+  let changes = source.apply_edit(&replace(&source, "\n\n--| third", "\n\ne : Dot = pt\n\n--| third"));
+  assert!(names(&changes.added) == ["e"], "{:?}", changes);
+  assert!(changes.changed.is_empty() && changes.removed.is_empty());
+  assert_agrees_with_full_parse(&source);
+
+  let changes = source.apply_edit(&replace(&source, "--| first", "--| the first"));
+  assert!(names(&changes.changed) == ["a"]);
+  assert_agrees_with_full_parse(&source);
+
+  let changes = source.apply_edit(&replace(&source, "--| third\nc : Dot = pt\n\n", ""));
+  assert!(names(&changes.removed) == ["c"]);
+  assert!(changes.added.is_empty() && changes.changed.is_empty());
+  assert_agrees_with_full_parse(&source);
+
+  // whitespace after a declaration is not part of it
+  let changes = source.apply_edit(&replace(&source, "| _ => pt\n", "| _ => pt   \n\n"));
+  assert!(changes.changed.is_empty() && changes.added.is_empty() && changes.removed.is_empty());
+  assert_agrees_with_full_parse(&source);
+}
+
+#[test]
+fn declarations_can_merge_and_break() {
+  let mut source = IncrementalSource::parse(SOURCE, FileId(0));
+
+  // indented name continues previous declaration
+  let changes = source.apply_edit(&replace(&source, "\nd :", "\n d :"));
+  assert!(names(&changes.removed).contains(&"d"), "{:?}", changes);
+  assert_agrees_with_full_parse(&source);
+
+  let changes = source.apply_edit(&replace(&source, "\n d :", "\nd :"));
+  assert!(names(&changes.added).contains(&"d"), "{:?}", changes);
+  assert_agrees_with_full_parse(&source);
+
+  let changes = source.apply_edit(&replace(&source, "b : {T}", "b : )"));
+  assert!(names(&changes.removed) == ["b"]);
+  assert!(source.errors().len() == 1);
+  assert_agrees_with_full_parse(&source);
+
+  let changes = source.apply_edit(&replace(&source, "b : )", "b : {T}"));
+  assert!(names(&changes.added) == ["b"]);
+  assert!(source.errors().is_empty());
+  assert_agrees_with_full_parse(&source);
+}
+
+#[test]
+fn every_single_char_edit_agrees() {
+  for insertion in ["\n", " ", "x", "|", "-- c\n", "{-"] {
+    for offset in 0 ..= SOURCE.len() {
+      let mut source = IncrementalSource::parse(SOURCE, FileId(0));
+      let at = SourceLocation { primary_offset: offset as u32, secondary_offset: offset as u32 };
+      source.apply_edit(&TextEdit { range: at, replacement: insertion.to_string() });
+      assert_agrees_with_full_parse(&source);
+      let undo = SourceLocation {
+        primary_offset: offset as u32,
+        secondary_offset: (offset + insertion.len()) as u32
+      };
+      source.apply_edit(&TextEdit { range: undo, replacement: String::new() });
+      assert!(source.text() == SOURCE);
+      assert_agrees_with_full_parse(&source);
+    }
+  }
+}
+
+#[test]
+fn random_edits_agree() {
+  // xorshift, so that failures can be replayed
+  let mut state = 0x2545f4914f6cdd1d_u64;
+  let mut random = |bound: usize| {
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    return (state % bound as u64) as usize
+  };
+  let pieces = [
+    "", "\n", " ", "x", ":", "|", "=", "(", "--| ", "--| doc\n", "-- c\n",
+    "{-", "-}", "e : Dot = pt\n", "\n--| e\ne : Dot =",
+  ];
+  let names_of = |source: &IncrementalSource| source.declarations().iter()
+    .map(|decl| decl.project_name().materialise_name())
+    .collect::<Vec<_>>();
+  for _ in 0 .. 500 {
+    let mut source = IncrementalSource::parse(SOURCE, FileId(0));
+    for _ in 0 .. 20 {
+      let len = source.text().len();
+      let start = random(len + 1);
+      let end = (start + random(8)).min(len);
+      let replacement = pieces[random(pieces.len())].to_string();
+      let range = SourceLocation { primary_offset: start as u32, secondary_offset: end as u32 };
+      let before = names_of(&source);
+      let changes = source.apply_edit(&TextEdit { range, replacement });
+      assert_agrees_with_full_parse(&source);
+      // names that came or went are reported, and one that stayed is not new.
+      // declarations of the same name are told apart by name alone
+      let after = names_of(&source);
+      let count = |names: &[&str], name| names.iter().filter(|other| **other == name).count();
+      for name in after.iter().chain(&before) {
+        let (was, is) = (count(&before, *name), count(&after, *name));
+        if was > 1 || is > 1 { continue }
+        assert!(names(&changes.added).contains(name) == (was < is), "{}", source.text());
+        assert!(names(&changes.removed).contains(name) == (was > is), "{}", source.text());
+      }
+    }
+  }
+}
@@ -0,0 +1,173 @@
+use proto_sigil::elaborator::{
+  main::elaborate_directory,
+  diagnostics::Kind,
+};
+use proto_sigil::expression_trees::{
+  better_nodes::{DeclKind, ConcretisedNodeRepr, ImportSelection},
+  raw_syntax_nodes::FileId,
+};
+use proto_sigil::parser::{
+  new_parser::{ParsingState, ParsedSource, ParseErrorKind},
+  formatter::format_source,
+};
+
+mod common;
+use common::setup_dir;
+
+
+#[test]
+fn header_is_parsed_before_declarations() {
+  let text =
+    "module Data.Lists\n".to_string() +
+    "\n" +
+    "import Base as B (id, const)\n" +
+    "import Other hiding (junk)\n" +
+    "import : * = Dot\n";
+  let mut ps = ParsingState::init(&text);
+  let ParsedSource { header, declarations, errors } =
+    ps.parse_decls_with_recovery();
+  assert!(errors.is_empty(), "{:#?}", errors);
+
+  assert!(header.name.unwrap().materialise_name() == "Data.Lists");
+  assert!(header.imports.len() == 2);
+  let base = &header.imports[0];
+  assert!(base.module.materialise_name() == "Base");
+  assert!(base.alias.unwrap().materialise_name() == "B");
+  let ImportSelection::Only(ref names) = base.selection else { panic!() };
+  assert!(names.len() == 2);
+  let ImportSelection::Hiding(ref names) = header.imports[1].selection else { panic!() };
+  assert!(names[0].materialise_name() == "junk");
+  // followed by a colon it is just a name
+  assert!(declarations.len() == 1);
+  assert!(declarations[0].project_name().materialise_name() == "import");
+
+  let text = "a : * = Dot\nimport Base\nmodule M\n".to_string();
+  let mut ps = ParsingState::init(&text);
+  let ParsedSource { errors, .. } = ps.parse_decls_with_recovery();
+  assert!(errors.len() == 2);
+  for err in errors {
+    assert!(matches!(err.kind, ParseErrorKind::MisplacedModuleLine))
+  }
+
+  let text = "import  Base  hiding(x ,y)\nmodule M\nid : * = Dot\n";
+  let formatted = format_source(text, 80);
+  assert!(formatted.is_err());
+  let text = "module M\nimport  Base  hiding(x ,y)\nimport Q as R\nid : * = Dot\n";
+  let formatted = format_source(text, 80).unwrap();
+  assert!(
+    formatted ==
+    "module M\n\nimport Base hiding (x, y)\nimport Q as R\n\nid : * = Dot\n",
+    "{}", formatted);
+}
+
+#[test]
+fn names_resolve_through_imports() {
+  let dir = setup_dir("resolve", &[
+    ("a.sigil", "module A\n\nid : {T} (T) -> T\n| v => v\n\nunit : (Dot) -> Dot\n| _ => pt\n"),
+    ("b.sigil", "module B\n\nid : {K} (K) -> K\n| v => v\n"),
+    ("c.sigil",
+      "module C\n\nimport A (unit)\nimport B as Q\n\n\
+       f : (Dot) -> Dot\n| x => unit x\n\n\
+       g : (Dot) -> Dot\n| x => Q.id (A.unit x)\n"),
+  ]);
+  let outcome = elaborate_directory(dir.path());
+  let rendered = outcome.render_reports();
+  assert!(!outcome.diagnostics.did_record_any_issues(), "{}", rendered);
+
+  // equally named declarations from different modules dont clash
+  let names = outcome.source_files.iter()
+    .flat_map(|file| file.declarations.iter())
+    .map(|decl| decl.project_name().materialise_name())
+    .collect::<Vec<_>>();
+  assert!(names == ["A.id", "A.unit", "B.id", "C.f", "C.g"], "{:?}", names);
+
+  let g = &outcome.source_files[2].declarations[1];
+  let DeclKind::WellScopedMapping { rewrite_rules, .. } = g.repr else { panic!() };
+  let rule = unsafe { *rewrite_rules.project_ptr() };
+  let ConcretisedNodeRepr::App { root, arguments, .. } = unsafe { *rule.rhs }.kind
+    else { panic!() };
+  assert!(root.materialise_name() == "B.id");
+  let ConcretisedNodeRepr::App { root, .. } = unsafe { *arguments.project_ptr() }.kind
+    else { panic!() };
+  assert!(root.materialise_name() == "A.unit");
+}
+
+#[test]
+fn bad_imports_are_reported() {
+  let dir = setup_dir("bad", &[
+    ("a.sigil", "module A\n\nid : {T} (T) -> T\n| v => v\n"),
+    ("b.sigil", "module B\n\nid : {K} (K) -> K\n| v => v\n"),
+    ("c.sigil",
+      "module C\n\nimport A\nimport B\nimport Missing\nimport A (nothing)\n\n\
+       f : (Dot) -> Dot\n| x => id x\n\n\
+       g : (Dot) -> Dot\n| x => Z.id x\n\n\
+       h : (Dot) -> Dot\n| x => B.unit x\n"),
+  ]);
+  let outcome = elaborate_directory(dir.path());
+  let reports = outcome.diagnostics.collect_reports();
+  assert!(reports.len() == 5, "{}", outcome.render_reports());
+  assert!(reports.iter().all(|(origin, _)| *origin == FileId(2)));
+
+  let mut unknown = Vec::new();
+  let mut irrelevant = Vec::new();
+  let mut ambiguous = 0;
+  for (_, report) in &reports {
+    match report.kind {
+      Kind::UnknownModule(module) => unknown.push(module.materialise_name()),
+      Kind::IrrelevantSymbol(name) => irrelevant.push(name.materialise_name()),
+      Kind::AmbiguousName { name, ref candidates } => {
+        assert!(name.materialise_name() == "id");
+        assert!(candidates.len() == 2);
+        ambiguous += 1;
+      },
+      _ => panic!("{:#?}", report)
+    }
+  }
+  unknown.sort();
+  irrelevant.sort();
+  assert!(unknown == ["Missing", "Z"]);
+  assert!(irrelevant == ["B.unit", "nothing"]);
+  assert!(ambiguous == 1);
+}
+
+#[test]
+fn import_cycles_are_reported() {
+  let dir = setup_dir("cycle", &[
+    ("a.sigil", "module A\n\nimport B\n\nx : * = Dot\n"),
+    ("b.sigil", "module B\n\nimport C\n\ny : * = Dot\n"),
+    ("c.sigil", "module C\n\nimport A\n\nz : * = Dot\n"),
+    ("d.sigil", "module D\n\nimport A\n\nw : * = Dot\n"),
+  ]);
+  let outcome = elaborate_directory(dir.path());
+  let reports = outcome.diagnostics.collect_reports();
+  assert!(reports.len() == 1, "{}", outcome.render_reports());
+  let (origin, report) = &reports[0];
+  assert!(*origin == FileId(2));
+  let Kind::ImportCycle(ref path) = report.kind else { panic!("{:#?}", report) };
+  let path = path.iter().map(|module| module.materialise_name()).collect::<Vec<_>>();
+  assert!(path == ["A", "B", "C", "A"], "{:?}", path);
+  assert!(
+    report.kind.to_string() ==
+    "modules import each other in a cycle: A -> B -> C -> A");
+  assert!(outcome.render_reports().contains("this import closes the cycle"));
+}
+
+#[test]
+fn dotted_operators_are_not_qualified() {
+  let dir = setup_dir("dotted", &[
+    ("a.sigil",
+      "module A\n\n<.> : (Dot, Dot) -> Dot\n| a, _ => a\n\n\
+       f : (Dot) -> Dot\n| x => <.> x x\n"),
+    ("b.sigil", "module B\n\nimport A\n\ng : (Dot) -> Dot\n| x => <.> x (A.f x)\n"),
+    ("c.sigil", "h : * = Dot\n"),
+    ("d.sigil", "module D\n\nk : * = h\n"),
+  ]);
+  let outcome = elaborate_directory(dir.path());
+  let reports = outcome.diagnostics.collect_reports();
+  assert!(reports.len() == 1, "{}", outcome.render_reports());
+  // unnamed module cant be imported, so named ones dont see it
+  let (origin, report) = &reports[0];
+  assert!(*origin == FileId(3));
+  let Kind::IrrelevantSymbol(name) = report.kind else { panic!("{:#?}", report) };
+  assert!(name.materialise_name() == "h");
+}
@@ -0,0 +1,120 @@
+use proto_sigil::elaborator::{
+  main::{elaborate_directory, ElaborationOutcome},
+  diagnostics::Kind,
+  normaliser::Normaliser,
+  semantic_terms::Term,
+};
+use proto_sigil::expression_trees::better_nodes::DeclKind;
+
+mod common;
+use common::setup_dir;
+
+
+fn value_of(outcome: &ElaborationOutcome, name: &str) -> Term {
+  let decl = outcome.source_files.iter()
+    .flat_map(|file| file.declarations.iter())
+    .find(|decl| decl.project_name().materialise_name() == name)
+    .unwrap();
+  let DeclKind::WellScopedDefinition { value, .. } = decl.repr else { panic!() };
+  return Term::of_node(unsafe { *value })
+}
+
+const BOOLEANS : &str = concat!(
+  "Bool : * = Either Dot Dot\n",
+  "\n",
+  "yes : Bool = inl pt\n",
+  "\n",
+  "not : (Bool) -> Bool\n",
+  "| inl _ => inr pt\n",
+  "| inr _ => inl pt\n",
+  "\n",
+  "and : (Bool, Bool) -> Bool\n",
+  "| inl _, b => b\n",
+  "| inr _, _ => inr pt\n",
+  "\n",
+  "both : (Pair Bool Bool) -> Bool\n",
+  "| two a b => and a b\n",
+  "\n",
+  "twice : ((Bool) -> Bool, Bool) -> Bool\n",
+  "| f, b => f (f b)\n",
+  "\n",
+);
+
+
+#[test]
+fn rules_rewrite_applications() {
+  let text = format!("{}{}", BOOLEANS, concat!(
+    "no : Bool = not (twice not yes)\n",
+    "\n",
+    "mixed : Bool = both (two yes (twice (\\{ | x => not x }) (not yes)))\n",
+  ));
+  let outcome = elaborate_directory(setup_dir("rules", &[("a.sigil", &text)]).path());
+  assert!(!outcome.diagnostics.did_record_any_issues(), "{}", outcome.render_reports());
+
+  let mut normaliser = Normaliser::init(&outcome.symbol_table);
+  normaliser.record_steps();
+  let no = normaliser.normalise(&value_of(&outcome, "no"));
+  assert!(no.to_string() == "inr pt", "{}", no);
+  let steps = normaliser.take_steps();
+  assert!(steps.len() > 1);
+  // arguments are reduced while matching, so the outer rewrite finishes last
+  let last = steps.last().unwrap();
+  assert!(last.redex.to_string() == "not (twice not yes)", "{}", last.redex);
+  assert!(last.contractum.to_string() == "inr pt", "{}", last.contractum);
+
+  let mixed = normaliser.normalise(&value_of(&outcome, "mixed"));
+  assert!(mixed.to_string() == "inr pt", "{}", mixed);
+}
+
+#[test]
+fn weak_head_stops_at_the_outer_shape() {
+  let text = format!("{}{}", BOOLEANS, concat!(
+    "wrapped : Either Bool Dot = inl (not yes)\n",
+    "\n",
+    "stuck : {b : Bool} Bool = not b\n",
+  ));
+  let outcome = elaborate_directory(setup_dir("whnf", &[("a.sigil", &text)]).path());
+  let mut normaliser = Normaliser::init(&outcome.symbol_table);
+  let wrapped = value_of(&outcome, "wrapped");
+  assert!(normaliser.whnf(&wrapped).to_string() == "inl (not yes)");
+  assert!(normaliser.normalise(&wrapped).to_string() == "inl (inr pt)");
+  // variable is not a value, so neither rule applies
+  let stuck = normaliser.normalise(&value_of(&outcome, "stuck"));
+  assert!(stuck.to_string() == "not b", "{}", stuck);
+}
+
+#[test]
+fn types_are_compared_after_rewriting() {
+  let text = format!("{}{}", BOOLEANS, concat!(
+    "Pick : (Bool) -> *\n",
+    "| inl _ => Dot\n",
+    "| inr _ => Void\n",
+    "\n",
+    "picked : Pick (not (not yes)) = pt\n",
+    "\n",
+    "missed : Pick (not yes) = pt\n",
+  ));
+  let outcome = elaborate_directory(setup_dir("types", &[("a.sigil", &text)]).path());
+  let reports = outcome.diagnostics.collect_reports();
+  assert!(reports.len() == 1, "{}", outcome.render_reports());
+  let Kind::MismatchedType { term_expr, .. } = reports[0].1.kind else { panic!() };
+  let term_text = &text[term_expr.primary_offset as usize .. term_expr.secondary_offset as usize];
+  assert!(term_text.trim_end() == "pt");
+  assert!(term_expr.primary_offset as usize > text.find("missed").unwrap());
+}
+
+#[test]
+fn looping_rules_run_out_of_fuel() {
+  let text = concat!(
+    "loop : (Dot) -> Dot\n",
+    "| x => loop x\n",
+    "\n",
+    "forever : Dot = loop pt\n",
+  );
+  let outcome = elaborate_directory(setup_dir("loop", &[("a.sigil", text)]).path());
+  let mut normaliser = Normaliser::init(&outcome.symbol_table);
+  normaliser.set_fuel(1000);
+  let result = normaliser.whnf(&value_of(&outcome, "forever"));
+  assert!(normaliser.ran_out_of_fuel());
+  assert!(result.to_string() == "loop pt", "{}", result);
+}
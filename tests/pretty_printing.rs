@@ -0,0 +1,236 @@
+use proto_sigil::{
+  parser::new_parser::{ParsingState, ParsedSource},
+  expression_trees::{
+    better_nodes::{
+      RawNode, RawNodeRepr, RawPattern, RawPatternKind, RawRewriteRule,
+      Declaration, DeclKind, ArrayPtr},
+    more_text_rendering::{
+      render_raw_expr, render_declaration, render_concretised_expr,
+      DEFAULT_LINE_WIDTH},
+  },
+  support_structures::raw_array_iter::RawArrayIter,
+  elaborator::main::elaborate_directory,
+};
+
+mod common;
+use common::setup_dir;
+
+
+fn same_arrays<T: Clone>(
+  l: ArrayPtr<T>, r: ArrayPtr<T>, same: impl Fn(T, T) -> bool
+) -> bool {
+  if l.project_count() != r.project_count() { return false }
+  return RawArrayIter::from_array_ptr(l)
+    .zip(RawArrayIter::from_array_ptr(r))
+    .all(|(l, r)| same(l, r))
+}
+
+// locations are allowed to differ
+fn same_exprs(l: RawNode, r: RawNode) -> bool {
+  let same_ctx = match (l.implicit_context, r.implicit_context) {
+    (None, None) => true,
+    (Some(l), Some(r)) => same_arrays(l, r, |(ln, lt), (rn, rt)| {
+      ln == rn && match (lt, rt) {
+        (None, None) => true,
+        (Some(l), Some(r)) => same_exprs(l, r),
+        _ => false
+      }
+    }),
+    _ => false
+  };
+  if !same_ctx { return false }
+  let same_items = |(ln, le): (_, RawNode), (rn, re): (_, RawNode)| {
+    ln == rn && same_exprs(le, re)
+  };
+  match (l.kind, r.kind) {
+    (RawNodeRepr::Star, RawNodeRepr::Star) => true,
+    (RawNodeRepr::Ref(l), RawNodeRepr::Ref(r)) => l == r,
+    (RawNodeRepr::App { root: lr, arguments: la },
+     RawNodeRepr::App { root: rr, arguments: ra }) => {
+      lr == rr && same_arrays(la, ra, same_exprs)
+    },
+    (RawNodeRepr::Wit { premises: lp, conclusion: lc },
+     RawNodeRepr::Wit { premises: rp, conclusion: rc }) => {
+      same_arrays(lp, rp, same_exprs) && same_exprs(unsafe { *lc }, unsafe { *rc })
+    },
+    (RawNodeRepr::Fun { head: lh, spine: ls },
+     RawNodeRepr::Fun { head: rh, spine: rs }) |
+    (RawNodeRepr::Sigma { head: lh, spine: ls },
+     RawNodeRepr::Sigma { head: rh, spine: rs }) => {
+      same_arrays(lh, rh, same_items) && same_exprs(unsafe { *ls }, unsafe { *rs })
+    },
+    (RawNodeRepr::Lam { rewrite_rules: l },
+     RawNodeRepr::Lam { rewrite_rules: r }) => {
+      same_arrays(l, r, same_clauses)
+    },
+    _ => false
+  }
+}
+
+fn same_clauses(l: RawRewriteRule, r: RawRewriteRule) -> bool {
+  return same_arrays(l.matchers, r.matchers, same_patterns)
+    && same_exprs(unsafe { *l.lhs }, unsafe { *r.lhs })
+}
+
+fn same_patterns(l: RawPattern, r: RawPattern) -> bool {
+  match (l.repr, r.repr) {
+    (RawPatternKind::Wildcard, RawPatternKind::Wildcard) => true,
+    (RawPatternKind::Mono(l), RawPatternKind::Mono(r)) => l == r,
+    (RawPatternKind::Compound { head: lh, subexpressions: ls },
+     RawPatternKind::Compound { head: rh, subexpressions: rs }) => {
+      lh == rh && same_arrays(ls, rs, same_patterns)
+    },
+    _ => false
+  }
+}
+
+fn same_decls(l: &Declaration, r: &Declaration) -> bool {
+  match (l.repr, r.repr) {
+    (DeclKind::RawMapping { name: ln, given_type: lt, rewrite_rules: lr },
+     DeclKind::RawMapping { name: rn, given_type: rt, rewrite_rules: rr }) => {
+      ln == rn && same_exprs(unsafe { *lt }, unsafe { *rt })
+        && same_arrays(lr, rr, same_clauses)
+    },
+    (DeclKind::RawDefinition { name: ln, given_type: lt, value: lv },
+     DeclKind::RawDefinition { name: rn, given_type: rt, value: rv }) => {
+      ln == rn && same_exprs(unsafe { *lt }, unsafe { *rt })
+        && same_exprs(unsafe { *lv }, unsafe { *rv })
+    },
+    _ => false
+  }
+}
+
+const EXPRESSIONS : &[&str] = &[
+  "*",
+  "{T, K} (a : T, K a a) -> (b : D) -> M a b",
+  "(f : (A) -> B, x : A) |- Eq (f x) (f x)",
+  "F (G a (H b)) (*) ({T} x) ((A) -> B)",
+  "[| A, B a; C (D e) |]",
+  "\\{ | x, _ => x | inl (two a b), pt => F a (b) }",
+  "{T : *, P : (T) -> *} ((x : T) -> P x) -> (y : T) -> P y",
+  "{T} (*)",
+  "Either (Pair A B) (Pair (Either C D) E)",
+];
+
+#[test]
+fn expressions_survive_round_trip() {
+  for source in EXPRESSIONS {
+    for width in [DEFAULT_LINE_WIDTH, 20, 1] {
+      let source = source.to_string();
+      let mut ps = ParsingState::init(&source);
+      let original = ps.parse_expr(0).unwrap();
+      let printed = render_raw_expr(original, width);
+
+      let mut ps2 = ParsingState::init(&printed);
+      let reparsed = match ps2.parse_expr(0) {
+        Ok(expr) => expr,
+        Err(err) => panic!("{}\n{}\n{}", source, printed, err),
+      };
+      assert!(ps2.no_more_chars(), "{}\n{}", source, printed);
+      assert!(same_exprs(original, reparsed), "{}\n{}", source, printed);
+      assert!(render_raw_expr(reparsed, width) == printed);
+    }
+  }
+}
+
+const DECLARATIONS : &str = concat!(
+  "--| identity\n",
+  "--|\n",
+  "--| works on anything\n",
+  "id : {T} (T) -> T\n",
+  "| v => v\n",
+  "\n",
+  "swap : {A, B} (Pair A B) -> Pair B A\n",
+  "| two a b => two b a\n",
+  "\n",
+  "const : {A, B} (A, B) -> A = \\{ | a, _ => a }\n",
+  "\n",
+  "choose : (Either Dot Dot, Dot) -> Dot\n",
+  "| inl x, _ => x\n",
+  "| inr (y), pt => Compose (F y) (G (H y) (\\{ | z => z }))\n",
+);
+
+#[test]
+fn declarations_survive_round_trip() {
+  let source = DECLARATIONS.to_string();
+  let mut ps = ParsingState::init(&source);
+  let ParsedSource { declarations, errors, .. } = ps.parse_decls_with_recovery();
+  assert!(errors.is_empty(), "{:#?}", errors);
+  assert!(declarations.len() == 4);
+
+  for width in [DEFAULT_LINE_WIDTH, 30, 1] {
+    let printed = declarations.iter()
+      .map(|decl| render_declaration(decl, width))
+      .collect::<Vec<_>>().join("\n\n");
+    let mut ps2 = ParsingState::init(&printed);
+    let ParsedSource { declarations: reparsed, errors, .. } =
+      ps2.parse_decls_with_recovery();
+    assert!(errors.is_empty(), "{}\n{:#?}", printed, errors);
+    assert!(reparsed.len() == declarations.len(), "{}", printed);
+    for (l, r) in declarations.iter().zip(reparsed.iter()) {
+      assert!(same_decls(l, r), "{}", printed);
+      let doc_of = |decl: &Declaration| decl.doc_comment.map(|doc| doc.materialise_text());
+      assert!(doc_of(l) == doc_of(r));
+    }
+  }
+}
+
+#[test]
+fn layout_breaks_long_lines() {
+  let source =
+    "{T} (first : T, second : Pair T T) -> Either (Pair T T) (F (G a) b)".to_string();
+  let mut ps = ParsingState::init(&source);
+  let expr = ps.parse_expr(0).unwrap();
+
+  assert!(render_raw_expr(expr, DEFAULT_LINE_WIDTH) == source);
+  let narrow = render_raw_expr(expr, 30);
+  let expected = concat!(
+    "{T} (\n",
+    "  first : T,\n",
+    "  second : Pair T T\n",
+    ") -> Either\n",
+    "  (Pair T T)\n",
+    "  (F (G a) b)");
+  assert!(narrow == expected, "{}", narrow);
+}
+
+#[test]
+fn concretised_trees_print_back() {
+  let dir = setup_dir("printing", &[("a.sigil", concat!(
+    "swap : {A, B} (Pair A B) -> Pair B A\n",
+    "| two a b => f b a\n",
+    "\n",
+    "f : {A, B} (A, B) -> Pair A B\n",
+    "| a, b => two a b\n",
+    "\n",
+    "unit : (Either Void Dot) -> Dot\n",
+    "| _ => pt\n",
+  ))]);
+  let outcome = elaborate_directory(dir.path());
+  assert!(!outcome.diagnostics.did_record_any_issues(), "{}", outcome.render_reports());
+
+  let decls = &outcome.source_files[0].declarations;
+  let printed = decls.iter()
+    .map(|decl| render_declaration(decl, DEFAULT_LINE_WIDTH))
+    .collect::<Vec<_>>();
+  assert!(printed[0] == "swap : {A, B} (Pair A B) -> Pair B A\n| two a b => f b a");
+  assert!(printed[2] == "unit : (Either Void Dot) -> Dot\n| _ => pt");
+  let DeclKind::WellScopedMapping { given_type, .. } = decls[1].repr else { panic!() };
+  let type_ = render_concretised_expr(unsafe { *given_type }, 10);
+  assert!(type_ == "{A, B} (\n  A,\n  B\n) -> Pair\n  A\n  B", "{}", type_);
+}
+
+#[test]
+fn every_pattern_of_a_rule_is_kept() {
+  let dir = setup_dir("packing", &[("a.sigil", concat!(
+    "third : {A} (A, Pair A A, A) -> A\n",
+    "| a, two b c, d => d\n",
+  ))]);
+  let outcome = elaborate_directory(dir.path());
+  assert!(!outcome.diagnostics.did_record_any_issues(), "{}", outcome.render_reports());
+  let printed = render_declaration(
+    &outcome.source_files[0].declarations[0], DEFAULT_LINE_WIDTH);
+  assert!(
+    printed == "third : {A} (A, Pair A A, A) -> A\n| a, two b c, d => d",
+    "{}", printed);
+}
@@ -0,0 +1,125 @@
+use proto_sigil::elaborator::{
+  main::elaborate_directory,
+  queries::{Query, evaluate, infer_type, describe},
+};
+
+mod common;
+use common::setup_dir;
+
+
+const LOGIC : &str = concat!(
+  "module Logic\n",
+  "\n",
+  "Bool : * = Either Dot Dot\n",
+  "\n",
+  "yes : Bool = inl pt\n",
+  "\n",
+  "not : (Bool) -> Bool\n",
+  "| inl _ => inr pt\n",
+  "| inr _ => inl pt\n",
+  "\n",
+  "no : Bool = not yes\n",
+);
+
+
+#[test]
+fn names_and_expressions_are_evaluated() {
+  let dir = setup_dir("eval", &[("logic.sigil", LOGIC)]);
+  let outcome = elaborate_directory(dir.path());
+  assert!(!outcome.diagnostics.did_record_any_issues(), "{}", outcome.render_reports());
+
+  for (input, expected) in [
+    ("no", "inr pt"),
+    ("Logic.no", "inr pt"),
+    ("  not (not no)\n", "inr pt"),
+    ("two yes (Logic.not yes)", "two (inl pt) (inr pt)"),
+  ] {
+    let query = Query::parse(&outcome, input).unwrap();
+    let evaluation = evaluate(&query, false);
+    assert!(evaluation.result.to_string() == expected, "{}: {}", input, evaluation.result);
+    assert!(evaluation.steps.is_empty() && !evaluation.gave_up);
+  }
+  let query = Query::parse(&outcome, "not no").unwrap();
+  let steps = evaluate(&query, true).steps.iter()
+    .map(|step| format!("{} ~> {}", step.redex, step.contractum))
+    .collect::<Vec<_>>();
+  // names in steps are qualified by their module
+  assert!(steps == [
+    "Logic.no ~> Logic.not Logic.yes",
+    "Logic.yes ~> inl pt",
+    "Logic.not Logic.yes ~> inr pt",
+    "Logic.not Logic.no ~> inl pt",
+  ], "{:?}", steps);
+}
+
+#[test]
+fn bad_queries_are_reported_against_input() {
+  let dir = setup_dir("bad", &[("logic.sigil", LOGIC)]);
+  let outcome = elaborate_directory(dir.path());
+
+  let Err(rendered) = Query::parse(&outcome, "not (") else { panic!() };
+  assert!(rendered.contains("--> <input>:1:6"), "{}", rendered);
+
+  let Err(rendered) = Query::parse(&outcome, "not nope") else { panic!() };
+  assert!(rendered.contains("`nope` is not defined"), "{}", rendered);
+  assert!(rendered.contains("--> <input>:1:5"), "{}", rendered);
+
+  let Err(rendered) = Query::parse(&outcome, "yes )") else { panic!() };
+  assert!(rendered.contains("expected end of line after expression"), "{}", rendered);
+}
+
+#[test]
+fn types_are_inferred_with_implicits_filled() {
+  let dir = setup_dir("type", &[
+    ("logic.sigil", LOGIC),
+    ("util.sigil", concat!(
+      "id : {T} (T) -> T\n",
+      "| v => v\n",
+    )),
+  ]);
+  let outcome = elaborate_directory(dir.path());
+  assert!(!outcome.diagnostics.did_record_any_issues(), "{}", outcome.render_reports());
+
+  for (input, expected) in [
+    ("yes", "Logic.Bool"),
+    ("two yes Logic.no", "Pair Logic.Bool Logic.Bool"),
+    ("id pt", "Dot"),
+    ("Logic.not", "(Logic.Bool) -> Logic.Bool"),
+    ("Dot", "*"),
+  ] {
+    let query = Query::parse(&outcome, input).unwrap();
+    let type_ = infer_type(&query).unwrap();
+    assert!(type_.to_string() == expected, "{}: {}", input, type_);
+  }
+  let query = Query::parse(&outcome, "not pt").unwrap();
+  let Err(rendered) = infer_type(&query) else { panic!() };
+  assert!(rendered.contains("does not have expected type"), "{}", rendered);
+  // expected type is shown where it was written
+  assert!(rendered.contains("logic.sigil:7:"), "{}", rendered);
+}
+
+#[test]
+fn declarations_are_described() {
+  let dir = setup_dir("info", &[("logic.sigil", &LOGIC.replace(
+    "not : (Bool)", "--| flips a boolean\nnot : (Bool)"))]);
+  let outcome = elaborate_directory(dir.path());
+  assert!(!outcome.diagnostics.did_record_any_issues(), "{}", outcome.render_reports());
+
+  let query = Query::parse(&outcome, "not").unwrap();
+  let info = describe(&query).unwrap();
+  assert!(info.name.materialise_name() == "Logic.not");
+  assert!(info.path.ends_with("logic.sigil"));
+  assert!(info.position.line == 8 && info.position.column == 1, "{}", info.position);
+  let rendered = info.rendered.unwrap();
+  assert!(rendered.starts_with("--| flips a boolean\n"), "{}", rendered);
+  assert!(rendered.contains("| inr _ => inl pt"), "{}", rendered);
+
+  let query = Query::parse(&outcome, "Logic.no").unwrap();
+  let references = describe(&query).unwrap().references.iter()
+    .map(|name| name.materialise_name())
+    .collect::<Vec<_>>();
+  assert!(references == ["Logic.Bool", "Logic.not", "Logic.yes"], "{:?}", references);
+
+  let query = Query::parse(&outcome, "not yes").unwrap();
+  assert!(describe(&query).is_err());
+}
@@ -0,0 +1,49 @@
+use proto_sigil::elaborator::main::elaborate_directory;
+
+mod common;
+use common::setup_dir;
+
+
+#[test]
+fn unknown_symbol_is_pointed_at() {
+  let dir = setup_dir("render-unknown", &[
+    ("a.sigil", "broken : (Dot) -> Dot\n| x => y\n"),
+  ]);
+  let outcome = elaborate_directory(dir.path());
+  let rendered = outcome.render_reports();
+  let expected = format!(
+"error: `y` is not defined in this scope
+ --> {}:2:8
+  |
+2 | | x => y
+  |        ^ not found in this scope
+  |
+
+", dir.join("a.sigil").display());
+  assert!(rendered == expected, "{}", rendered);
+}
+
+#[test]
+fn both_declarations_are_labeled() {
+  let dir = setup_dir("render-dupes", &[
+    ("a.sigil", "id : {T} (T) -> T\n| v => v\n"),
+    ("b.sigil", "\n\nid : {K} (K) -> K\n| v => v\n"),
+  ]);
+  let outcome = elaborate_directory(dir.path());
+  let rendered = outcome.render_reports();
+  let expected = format!(
+"error: `id` is declared more than once
+ --> {}:3:1
+  |
+3 | id : {{K}} (K) -> K
+  | ^^ redeclared here
+  |
+ ::: {}:1:1
+  |
+1 | id : {{T}} (T) -> T
+  | -- first declared here
+  |
+
+", dir.join("b.sigil").display(), dir.join("a.sigil").display());
+  assert!(rendered == expected, "{}", rendered);
+}
@@ -0,0 +1,69 @@
+use proto_sigil::elaborator::{
+  main::elaborate_directory,
+  diagnostics::Kind,
+};
+
+mod common;
+use common::setup_dir;
+
+
+fn slice(text: &str, from: u32, to: u32) -> &str {
+  return &text[from as usize .. to as usize]
+}
+
+
+#[test]
+fn clauses_must_match_arity() {
+  let text = concat!(
+    "first : (Dot, Dot) -> Dot\n",
+    "| x => x\n",
+    "| a, b => a\n",
+    "| a, b, c => c\n",
+    "\n",
+    "used : Dot = first pt pt\n",
+  );
+  let dir = setup_dir("arity", &[("a.sigil", text)]);
+  let outcome = elaborate_directory(dir.path());
+  let found = outcome.diagnostics.collect_reports().into_iter()
+    .map(|(_, report)| match report.kind {
+      Kind::ArityMismatch { expected: 2, found, clause_loc } => {
+        (found, slice(text, clause_loc.primary_offset, clause_loc.secondary_offset))
+      },
+      _ => panic!("{:#?}", report),
+    }).collect::<Vec<_>>();
+  // malformed rules are not checked for coverage or types
+  assert!(found.len() == 2, "{}", outcome.render_reports());
+  assert!(found[0].0 == 1 && found[0].1.starts_with("| x =>"), "{:?}", found);
+  assert!(found[1].0 == 3 && found[1].1.starts_with("| a, b, c =>"), "{:?}", found);
+  assert!(outcome.source_files[0].declarations[0].is_malformed);
+}
+
+#[test]
+fn patterns_are_held_against_signature() {
+  let text = concat!(
+    "Bool : * = Either Dot Dot\n",
+    "\n",
+    "pick : (Either Dot (Pair Dot Dot)) -> Dot\n",
+    "| inl pt => pt\n",
+    "| inr (inl x) => x\n",
+    "| inr (two a b) => b\n",
+    "\n",
+    "aliased : (Bool, Dot) -> Dot\n",
+    "| inl _, d => d\n",
+    "| inr _, pt => pt\n",
+    "| inr _, d => d\n",
+  );
+  let dir = setup_dir("signature", &[("a.sigil", text)]);
+  let outcome = elaborate_directory(dir.path());
+  let reports = outcome.diagnostics.collect_reports();
+  // last clause of `aliased` is not a conflict, only useless
+  assert!(reports.len() == 2, "{}", outcome.render_reports());
+  assert!(matches!(reports[1].1.kind, Kind::UnreachableRule(_)), "{}", outcome.render_reports());
+  let Kind::PatternMismatchesSignature { pattern_loc, type_loc } = reports[0].1.kind
+  else { panic!("{:#?}", reports[0]) };
+  let pattern = slice(text, pattern_loc.primary_offset, pattern_loc.secondary_offset);
+  assert!(pattern.trim_end() == "inr (inl x)", "{:?}", pattern);
+  assert!(slice(text, type_loc.primary_offset, type_loc.secondary_offset) == "Either Dot (Pair Dot Dot)");
+  let rendered = outcome.render_reports();
+  assert!(rendered.contains("does not fit this type"), "{}", rendered);
+}
@@ -166,4 +166,38 @@ fn well_sequenced_loops () {
   assert!(
     "TickTockTickTockTickTockTickTockTickTockTickTockBOOM!!" ==
     unsafe { &MSG })
-}
\ No newline at end of file
+}
+
+#[test]
+fn work_is_spread_while_executors_start () {
+  // first executors get work before the last of them was spawned,
+  // and ping the others right away
+  struct Ctx { counter: AtomicU64 }
+  fn bump(ctx : TaskContext) -> ActionLink {
+    let par = ctx.get_parrent_frame().unwrap();
+    let ctx = par.interpret_frame::<Ctx>();
+    let _ = ctx.counter.fetch_add(1, Ordering::Relaxed);
+    return ActionLink::make_completion();
+  }
+  fn done(ctx : TaskContext) -> ActionLink {
+    let ctx = ctx.interpret_frame::<Ctx>();
+    assert_eq!(ctx.counter.load(Ordering::Relaxed), 4);
+    return ActionLink::make_completion();
+  }
+  fn begin(ctx : TaskContext) -> ActionLink {
+    let frame = ctx.interpret_frame::<Ctx>();
+    frame.counter = AtomicU64::new(0);
+    for _ in 0 .. 4 {
+      let work_item = ActionLink::from_fun(bump);
+      ctx.assign_work_for_schedule(
+        ActionLink::make_frame_request(SlabSize::Bytes64, work_item));
+    }
+    return ActionLink::from_fun(done);
+  }
+  for _ in 0 .. 500 {
+    let init = ActionLink::from_fun(begin);
+    let work_graph =
+      ActionLink::make_frame_request(SlabSize::Bytes128, init);
+    WorkGroup::init(work_graph).await_completion();
+  }
+}
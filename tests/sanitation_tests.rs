@@ -2,7 +2,7 @@ use std::collections::HashSet;
 
 use proto_sigil::{
   parser::new_parser::ParsingState,
-  elaborator::{diagnostics::{ProblemReport, SomeDiagnosticsDelegate}, scope_analysis::concretise_declaration, presense_tester::PresenseSet, context_use_check::check_context_use, rewrite_system_check::check_rewrite_system}, expression_trees::better_nodes::DeclKind,
+  elaborator::{diagnostics::{ProblemReport, SomeDiagnosticsDelegate}, scope_analysis::concretise_declaration, presense_tester::PresenseSet, context_use_check::check_context_use, rewrite_system_check::check_rewrite_system, data_types::DataTypes}, expression_trees::better_nodes::DeclKind,
 };
 
 #[derive(Debug)]
@@ -310,7 +310,7 @@ fn rs_check () {
         &mut decl, &mut dd,
         &gs);
 
-      check_rewrite_system(decl, &mut dd);
+      check_rewrite_system(decl, &DataTypes::default(), &mut dd);
 
         println!("{:#?}", dd)
     },
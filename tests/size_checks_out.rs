@@ -3,7 +3,9 @@ use std::{mem::{size_of, ManuallyDrop}, intrinsics::transmute, ptr::addr_of_mut}
 
 use proto_sigil::{
   expression_trees::{raw_syntax_nodes::{LiftNodeItem, ExprPtr,
-  }, better_nodes::{RawNode, ConcretisedNode, Symbol, }},
+  }, better_nodes::{
+    RawNode, ConcretisedNode, Symbol, RawPattern, ConcretisedPattern,
+    RawConstructor, ConcretisedConstructor, }},
 
   elaborator::{
     worker::WorkQueue,
@@ -67,6 +69,13 @@ fn size_of_checked_node () {
   assert!(size_of::<ConcretisedNode>() <= 64);
 }
 
+// these are concretised in place of raw ones
+#[test]
+fn concretised_items_fit_in_raw_ones () {
+  assert!(size_of::<ConcretisedPattern>() <= size_of::<RawPattern>());
+  assert!(size_of::<ConcretisedConstructor>() == size_of::<RawConstructor>());
+}
+
 
 #[test]
 fn size_of_compact_node () {
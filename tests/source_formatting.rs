@@ -0,0 +1,228 @@
+use std::fs;
+
+use proto_sigil::{
+  parser::formatter::{format_source, format_directory, FormatMode, FormatStatus},
+  expression_trees::more_text_rendering::DEFAULT_LINE_WIDTH,
+};
+
+mod common;
+use common::setup_dir;
+
+
+#[test]
+fn layout_is_canonical() {
+  let source = concat!(
+    "id:{ T }(T)->T\n",
+    "  |v=>v\n",
+    "\n\n\n",
+    "const : {A,B} (A,B) -> A=\\{|a,_=>a}\n",
+    "pick   :   ( Either Dot Dot ) |-   Dot\n",
+    "|  inl x  =>  x\n",
+    " |inr (y)=>y\n",
+  );
+  let formatted = format_source(source, DEFAULT_LINE_WIDTH).unwrap();
+  let expected = concat!(
+    "id : {T} (T) -> T\n",
+    "| v => v\n",
+    "\n",
+    "const : {A, B} (A, B) -> A = \\{ | a, _ => a }\n",
+    "\n",
+    "pick : (Either Dot Dot) |- Dot\n",
+    "| inl x => x\n",
+    "| inr y => y\n",
+  );
+  assert!(formatted == expected, "{}", formatted);
+  assert!(format_source(&formatted, DEFAULT_LINE_WIDTH).unwrap() == formatted);
+}
+
+#[test]
+fn comments_are_preserved() {
+  let source = concat!(
+    "-- header\n",
+    "{- block\n",
+    "   comment -}\n",
+    "\n",
+    "--| identity\n",
+    "id:{T}(T)->T\n",
+    "|v=>v   -- trailing\n",
+    "-- between\n",
+    "\n",
+    "\n",
+    "two' : (Dot) -> Dot\n",
+    "  -- inside\n",
+    "| _   => pt\n",
+    "-- the end\n",
+  );
+  let formatted = format_source(source, DEFAULT_LINE_WIDTH).unwrap();
+  let expected = concat!(
+    "-- header\n",
+    "{- block\n",
+    "   comment -}\n",
+    "\n",
+    "--| identity\n",
+    "id : {T} (T) -> T\n",
+    "| v => v -- trailing\n",
+    "-- between\n",
+    "\n",
+    "two' : (Dot) -> Dot\n",
+    "-- inside\n",
+    "| _ => pt\n",
+    "-- the end\n",
+  );
+  assert!(formatted == expected, "{}", formatted);
+  assert!(format_source(&formatted, DEFAULT_LINE_WIDTH).unwrap() == formatted);
+}
+
+#[test]
+fn broken_sources_are_not_touched() {
+  let source = "id : (T) -> T\n| v =>\n\nok : Dot = pt\n\nbad : = \n";
+  let errors = format_source(source, DEFAULT_LINE_WIDTH).unwrap_err();
+  assert!(errors.len() == 2, "{:?}", errors);
+}
+
+#[test]
+fn check_mode_leaves_files_alone() {
+  let messy = "f:(Dot)->Dot\n|_=>pt\n";
+  let tidy = "g : (Dot) -> Dot\n| _ => pt\n";
+  let dir = setup_dir("formatting", &[("a.sigil", messy), ("b.sigil", tidy)]);
+
+  let reports = format_directory(&dir, FormatMode::Check).unwrap();
+  assert!(matches!(reports[0].status, FormatStatus::Reformatted));
+  assert!(matches!(reports[1].status, FormatStatus::Unchanged));
+  assert!(fs::read_to_string(dir.join("a.sigil")).unwrap() == messy);
+
+  format_directory(&dir, FormatMode::Rewrite).unwrap();
+  assert!(fs::read_to_string(dir.join("a.sigil")).unwrap() == "f : (Dot) -> Dot\n| _ => pt\n");
+  let reports = format_directory(&dir, FormatMode::Check).unwrap();
+  assert!(reports.iter().all(|report| matches!(report.status, FormatStatus::Unchanged)));
+}
+
+#[test]
+fn comments_can_hold_any_text() {
+  let source = "{- λ comment -}\nid : {T} (T) -> T\n| v => v\n";
+  let formatted = format_source(source, DEFAULT_LINE_WIDTH).unwrap();
+  assert!(formatted == source, "{}", formatted);
+
+  let source = "-- ∀ α\nk : {A, B} (A, B) -> A {- → β -}\n| a, _ => a -- λ\n";
+  let formatted = format_source(source, DEFAULT_LINE_WIDTH).unwrap();
+  assert!(formatted == source, "{}", formatted);
+}
+
+#[test]
+fn comments_inside_declarations_stay_with_their_tokens() {
+  let source = concat!(
+    "module a.b -- name\n",
+    "-- imports follow\n",
+    "import c.d ( x , y ) {- only two -}\n",
+    "\n",
+    "--| doc\n",
+    "f : {A, B} -- implicits\n",
+    "  (A, {- first -} B) -> A\n",
+    "  -- before clauses\n",
+    "| inl (a), b => {- result -} a -- done\n",
+    "| _, _ => a\n",
+    "g\n",
+    "  -- odd place\n",
+    "  : Dot = pt {- x -} -- y\n",
+  );
+  let formatted = format_source(source, DEFAULT_LINE_WIDTH).unwrap();
+  let expected = concat!(
+    "module a.b -- name\n",
+    "\n",
+    "-- imports follow\n",
+    "import c.d (x, y) {- only two -}\n",
+    "\n",
+    "--| doc\n",
+    "f : {A, B} (A, B) -> A -- implicits\n",
+    "{- first -}\n",
+    "-- before clauses\n",
+    "| inl a, b => {- result -} a -- done\n",
+    "| _, _ => a\n",
+    "\n",
+    "g : Dot = pt -- odd place\n",
+    "{- x -} -- y\n",
+  );
+  assert!(formatted == expected, "{}", formatted);
+  assert!(format_source(&formatted, DEFAULT_LINE_WIDTH).unwrap() == formatted);
+
+  // lines of a printer are kept, comments go around them
+  let narrow = format_source(source, 12).unwrap();
+  let expected = concat!(
+    "f : {A, B} ( -- implicits\n",
+    "    A, {- first -}\n",
+    "    B\n",
+    "  ) -> A\n",
+    "-- before clauses\n",
+    "| inl a, b => {- result -}\n",
+    "  a -- done\n",
+  );
+  assert!(narrow.contains(expected), "{}", narrow);
+  assert!(format_source(&narrow, 12).unwrap() == narrow);
+}
+
+#[test]
+fn doc_lines_inside_block_comments_stay_there() {
+  let source = "{- c3\n --| d4\n-}pick : (Dot) |- Dot\n| x => x\n";
+  let formatted = format_source(source, DEFAULT_LINE_WIDTH).unwrap();
+  assert!(formatted == "{- c3\n --| d4\n-}\npick : (Dot) |- Dot\n| x => x\n", "{}", formatted);
+  assert!(format_source(&formatted, DEFAULT_LINE_WIDTH).unwrap() == formatted);
+
+  let source = "{- c3\n--| d4\n-}pick : Dot = pt\n";
+  let formatted = format_source(source, DEFAULT_LINE_WIDTH).unwrap();
+  assert!(formatted == "{- c3\n--| d4\n-}\npick : Dot = pt\n", "{}", formatted);
+}
+
+#[test]
+fn formatting_twice_changes_nothing() {
+  let source = concat!(
+    "longish_name : -- a\n",
+    "-- b\n",
+    "(Either Dot Dot, Pair Dot Dot, Either (Pair Dot Dot) Dot) -> Either Dot Dot\n",
+    "| _, _, _ => inr pt\n",
+  );
+  let formatted = format_source(source, DEFAULT_LINE_WIDTH).unwrap();
+  let expected = concat!(
+    "longish_name : ( -- a\n",
+    "    -- b\n",
+    "    Either Dot Dot,\n",
+    "    Pair Dot Dot,\n",
+    "    Either (Pair Dot Dot) Dot\n",
+    "  ) -> Either Dot Dot\n",
+    "| _, _, _ => inr pt\n",
+  );
+  assert!(formatted == expected, "{}", formatted);
+
+  // every comment, in the order they are written
+  let comments = |text: &str| {
+    let mut found = Vec::new();
+    for mut line in text.lines() {
+      while let Some(ix) = line.find(['-', '{']) {
+        if line[ix ..].starts_with("--") {
+          found.push(line[ix ..].to_string());
+          break
+        } else if line[ix ..].starts_with("{-") {
+          let end = ix + line[ix ..].find("-}").unwrap() + 2;
+          found.push(line[ix .. end].to_string());
+          line = &line[end ..];
+        } else {
+          line = &line[ix + 1 ..];
+        }
+      }
+    }
+    return found
+  };
+  for source in [
+    source,
+    "f : -- a\n-- b\n{- c -} (Dot) -- d\n-> Dot -- e\n| _ -- f\n=> pt\n",
+    "pick : {- a -} {T} -- b\n(T, T) -> T\n| x, -- c\n  _ => x -- d\n",
+    "swap : {A, B} (Pair A B)\n-- c\n  -> Pair {- b -}  B A\n|\n-- a\n  two a b => two b a\n",
+    "swap : {A, B} (Pair A B) -> Pair B A\n| -- c\n two a b {- b -} =>\n-- a\n two b a\n",
+  ] {
+    for width in [DEFAULT_LINE_WIDTH, 12] {
+      let once = format_source(source, width).unwrap();
+      let twice = format_source(&once, width).unwrap();
+      assert!(twice == once, "{}\n---\n{}", once, twice);
+      assert!(comments(&once) == comments(source), "{}", once);
+    }
+  }
+}
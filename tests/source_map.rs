@@ -0,0 +1,59 @@
+use std::path::PathBuf;
+
+use proto_sigil::{
+  expression_trees::{
+    source_map::{LineTable, LineColumn, SourceMap},
+    raw_syntax_nodes::{SourceLocation, FileId},
+  },
+  parser::new_parser::ParsingState,
+};
+
+
+#[test]
+fn lines_and_columns_are_one_based() {
+  let lines = LineTable::compute("ab\ncd\n\nef");
+  assert!(lines.line_count() == 4);
+  assert!(lines.locate(0) == LineColumn { line: 1, column: 1 });
+  assert!(lines.locate(2) == LineColumn { line: 1, column: 3 });
+  assert!(lines.locate(3) == LineColumn { line: 2, column: 1 });
+  assert!(lines.locate(6) == LineColumn { line: 3, column: 1 });
+  assert!(lines.locate(8) == LineColumn { line: 4, column: 2 });
+  // past the end sticks to the end
+  assert!(lines.locate(100) == LineColumn { line: 4, column: 3 });
+}
+
+#[test]
+fn columns_count_chars_not_bytes() {
+  let text = "αβ x\nλ → y";
+  let lines = LineTable::compute(text);
+  let x = text.find('x').unwrap() as u32;
+  let y = text.find('y').unwrap() as u32;
+  assert!(lines.locate(x) == LineColumn { line: 1, column: 4 });
+  assert!(lines.locate(y) == LineColumn { line: 2, column: 5 });
+  let second = lines.line_span(2);
+  let second =
+    &text[second.primary_offset as usize .. second.secondary_offset as usize];
+  assert!(second == "λ → y");
+}
+
+#[test]
+fn map_resolves_spans_per_file() {
+  let mut map = SourceMap::init();
+  let a = map.register_file(PathBuf::from("a.sigil"), LineTable::compute("one\ntwo"));
+  let b = map.register_file(PathBuf::from("b.sigil"), LineTable::compute("three"));
+  assert!(a == FileId(0) && b == FileId(1));
+  let span = SourceLocation { primary_offset: 4, secondary_offset: 7 };
+  let range = map.locate(a, span);
+  assert!(range.start == LineColumn { line: 2, column: 1 });
+  assert!(range.end == LineColumn { line: 2, column: 4 });
+  assert!(map.path_of(b) == PathBuf::from("b.sigil"));
+  assert!(format!("{}", map.locate(b, span).start) == "1:5");
+}
+
+#[test]
+fn parser_knows_where_it_is() {
+  let text = "id : {T} (T) -> T\n| v => v\n".to_string();
+  let mut parser = ParsingState::init(&text);
+  let _ = parser.parse_decl().unwrap();
+  assert!(parser.current_position().line == 3);
+}
@@ -0,0 +1,75 @@
+use std::{thread::spawn, sync::Arc};
+
+use proto_sigil::{
+  expression_trees::symbol_interner::{SymbolInterner, InternedName},
+  parser::new_parser::ParsingState,
+  expression_trees::raw_syntax_nodes::FileId,
+};
+
+
+#[test]
+fn same_text_same_id () {
+  let interner = SymbolInterner::init();
+  let a = interner.intern("foo");
+  let b = interner.intern("bar");
+  let c = interner.intern("foo");
+  assert!(a == c);
+  assert!(a != b);
+  assert!(interner.resolve(a) == "foo");
+  assert!(interner.resolve(b) == "bar");
+  assert!(interner.interned_count() == 2);
+}
+
+#[test]
+fn concurrent_interning_agrees () {
+  const Limit : usize = 5000;
+  let interner = Arc::new(SymbolInterner::init());
+
+  let mut handles = Vec::new();
+  for th in 0 .. 4 {
+    let interner = interner.clone();
+    handles.push(spawn(move || {
+      let mut ids = Vec::new();
+      for i in 0 .. Limit {
+        // walk in different orders to provoke races
+        let i = if th % 2 == 0 { i } else { Limit - 1 - i };
+        ids.push((i, interner.intern(&format!("name{i}"))));
+      }
+      ids.sort();
+      return ids.into_iter().map(|(_, id)| id).collect::<Vec<InternedName>>()
+    }));
+  }
+  let results =
+    handles.into_iter().map(|h| h.join().unwrap()).collect::<Vec<_>>();
+  for result in &results[1..] {
+    assert!(*result == results[0]);
+  }
+  assert!(interner.interned_count() == Limit);
+  for (i, id) in results[0].iter().enumerate() {
+    assert!(interner.resolve(*id) == format!("name{i}"));
+  }
+}
+
+#[test]
+fn symbols_from_different_sources_compare_by_name () {
+  let one = "left".to_string();
+  let another = "left+".to_string();
+  let mut p1 = ParsingState::init(&one);
+  let mut p2 = ParsingState::init(&another);
+  let s1 = p1.parse_symbol().unwrap();
+  let s2 = p2.parse_symbol().unwrap();
+  assert!(s1 == s2);
+  assert!(s2.materialise_name() == "left");
+  let s3 = p2.parse_symbol().unwrap();
+  assert!(s3.materialise_name() == "+");
+  assert!(s1 != s3);
+}
+
+#[test]
+fn detached_text_has_no_file () {
+  let text = "name".to_string();
+  let symbol = ParsingState::init(&text).parse_symbol().unwrap();
+  assert!(symbol.origin == FileId::DETACHED);
+  let symbol = ParsingState::init_for_file(&text, FileId(0)).parse_symbol().unwrap();
+  assert!(symbol.origin == FileId(0));
+}
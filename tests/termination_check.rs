@@ -0,0 +1,86 @@
+use proto_sigil::elaborator::{
+  main::{elaborate_directory, ElaborationOutcome},
+  diagnostics::Kind,
+};
+
+mod common;
+use common::setup_dir;
+
+
+// other problems are of no interest here
+fn nonterminating<'a>(outcome: &ElaborationOutcome, text: &'a str) -> Vec<(&'static str, &'a str)> {
+  let mut found = outcome.diagnostics.collect_reports().into_iter()
+    .filter_map(|(_, report)| match report.kind {
+      Kind::NonTerminatingRecursion { name, call_site } => Some((
+        name.materialise_name(),
+        text[call_site.primary_offset as usize .. call_site.secondary_offset as usize].trim_end())),
+      _ => None,
+    }).collect::<Vec<_>>();
+  found.sort();
+  return found
+}
+
+
+#[test]
+fn shrinking_recursion_is_accepted() {
+  let text = concat!(
+    "size : {T} (T) -> Dot\n",
+    "| inl x => size x\n",
+    "| inr x => size x\n",
+    "| two _ b => size b\n",
+    "| _ => pt\n",
+    "\n",
+    "--| arguments take turns to get smaller\n",
+    "alternate : {T} (T, T) -> Dot\n",
+    "| two a _, y => alternate y a\n",
+    "| _, _ => pt\n",
+  );
+  let dir = setup_dir("fine", &[("a.sigil", text)]);
+  let outcome = elaborate_directory(dir.path());
+  let found = nonterminating(&outcome, text);
+  assert!(found.is_empty(), "{:?}\n{}", found, outcome.render_reports());
+}
+
+#[test]
+fn calls_that_dont_shrink_are_reported() {
+  let text = concat!(
+    "ping : (Dot) -> Dot\n",
+    "| x => pong x\n",
+    "\n",
+    "pong : (Dot) -> Dot\n",
+    "| x => ping x\n",
+    "\n",
+    "grow : {T} (T) -> Dot\n",
+    "| inl x => grow x\n",
+    "| x => grow (inl x)\n",
+  );
+  let dir = setup_dir("loops", &[("a.sigil", text)]);
+  let outcome = elaborate_directory(dir.path());
+  let found = nonterminating(&outcome, text);
+  assert!(found == [
+    ("grow", "grow (inl x)"),
+    ("ping", "pong x"),
+    ("pong", "ping x"),
+  ], "{:?}\n{}", found, outcome.render_reports());
+  let rendered = outcome.render_reports();
+  assert!(rendered.contains("`ping` may recurse without end"), "{}", rendered);
+  assert!(rendered.contains("add `--| @partial` to allow this"), "{}", rendered);
+}
+
+#[test]
+fn partial_declarations_are_trusted() {
+  let text = concat!(
+    "--| waits for something\n",
+    "--| @partial\n",
+    "ping : (Dot) -> Dot\n",
+    "| x => pong x\n",
+    "\n",
+    "pong : (Dot) -> Dot\n",
+    "| x => ping x\n",
+  );
+  let dir = setup_dir("partial", &[("a.sigil", text)]);
+  let outcome = elaborate_directory(dir.path());
+  assert!(!outcome.diagnostics.did_record_any_issues(), "{}", outcome.render_reports());
+  let decls = &outcome.source_files[0].declarations;
+  assert!(decls[0].is_marked_partial() && !decls[1].is_marked_partial());
+}
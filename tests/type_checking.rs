@@ -0,0 +1,194 @@
+use proto_sigil::elaborator::{
+  main::elaborate_directory,
+  diagnostics::Kind,
+};
+use proto_sigil::expression_trees::raw_syntax_nodes::FileId;
+
+mod common;
+use common::setup_dir;
+
+
+fn slice(text: &str, (start, end): (u32, u32)) -> &str {
+  // expression spans take trailing whitespace along
+  return text[start as usize .. end as usize].trim_end()
+}
+
+
+#[test]
+fn well_typed_declarations_pass() {
+  let dir = setup_dir("good", &[("a.sigil", concat!(
+    "Bool : * = Either Dot Dot\n",
+    "\n",
+    "yes : Bool = inl pt\n",
+    "\n",
+    "not : (Bool) -> Bool\n",
+    "| inl _ => inr pt\n",
+    "| inr _ => inl pt\n",
+    "\n",
+    "id : {T} (T) -> T\n",
+    "| v => v\n",
+    "\n",
+    "swap : {A, B} (Pair A B) -> Pair B A\n",
+    "| two a b => two b a\n",
+    "\n",
+    "twice : ((Bool) -> Bool, Bool) -> Bool\n",
+    "| f, b => f (f b)\n",
+    "\n",
+    "no : Bool = twice not (id (not yes))\n",
+    "\n",
+    "Some : * = (b : Bool) |- Dot\n",
+    "\n",
+    "some : Some = [| yes; pt |]\n",
+    "\n",
+    "flip : (Pair Bool Dot) -> Pair Dot Bool = \\{ | p => swap p }\n",
+    "\n",
+    "poly : {T} (T) -> T = \\{ | x => id x }\n",
+  ))]);
+  let outcome = elaborate_directory(dir.path());
+  assert!(!outcome.diagnostics.did_record_any_issues(), "{}", outcome.render_reports());
+}
+
+#[test]
+fn mismatches_point_at_type_and_term() {
+  let text = concat!(
+    "bad : Dot = inl pt\n",
+    "\n",
+    "wrong_rhs : (Dot) -> Void\n",
+    "| x => x\n",
+    "\n",
+    "wrong_pattern : (Dot) -> Dot\n",
+    "| two a b => pt\n",
+    "\n",
+    "wrong_arg : Dot = wrong_rhs pt\n",
+    "\n",
+    "rigid : {A, B} (A) -> B\n",
+    "| a => a\n",
+  );
+  let dir = setup_dir("bad", &[("a.sigil", text)]);
+  let outcome = elaborate_directory(dir.path());
+  let mut reports = outcome.diagnostics.collect_reports();
+  // patterns are held against signatures before types are checked
+  let located = |kind: &Kind| match *kind {
+    Kind::MismatchedType { type_expr, term_expr, .. } => (type_expr, term_expr),
+    Kind::PatternMismatchesSignature { type_loc, pattern_loc } => (type_loc, pattern_loc),
+    _ => panic!("{:#?}", kind)
+  };
+  reports.sort_by_key(|(_, report)| located(&report.kind).1.primary_offset);
+  let spans = reports.iter().map(|(_, report)| {
+    let (type_expr, term_expr) = located(&report.kind);
+    return (
+      slice(text, (type_expr.primary_offset, type_expr.secondary_offset)),
+      slice(text, (term_expr.primary_offset, term_expr.secondary_offset)))
+  }).collect::<Vec<_>>();
+  assert!(spans == [
+    ("Dot", "inl pt"),
+    ("Void", "x"),
+    ("Dot", "two a b"),
+    ("Dot", "wrong_rhs pt"),
+    ("B", "a"),
+  ], "{:?}\n{}", spans, outcome.render_reports());
+  assert!(outcome.render_reports().contains("expected because of this type"));
+}
+
+#[test]
+fn applications_are_checked_against_signatures() {
+  let dir = setup_dir("apps", &[
+    ("a.sigil", concat!(
+      "module A\n",
+      "\n",
+      "apply : ((Dot) -> Dot, Dot) -> Dot\n",
+      "| f, x => f x\n",
+    )),
+    ("b.sigil", concat!(
+      "module B\n",
+      "\n",
+      "import A\n",
+      "\n",
+      "many : Dot = apply (\\{ | x => x }) pt pt\n",
+      "\n",
+      "lambda : Dot = apply (\\{ | x, y => x }) pt\n",
+      "\n",
+      "argument : Dot = apply (\\{ | x => x }) (inr pt)\n",
+    )),
+  ]);
+  let outcome = elaborate_directory(dir.path());
+  let reports = outcome.diagnostics.collect_reports();
+  assert!(reports.len() == 3, "{}", outcome.render_reports());
+  assert!(reports.iter().all(|(origin, _)| *origin == FileId(1)));
+
+  let kinds = reports.iter().map(|(_, report)| match report.kind {
+    Kind::IncorrectArity(_) => "arity",
+    Kind::ArityMismatch { expected: 1, found: 2, .. } => "clause",
+    Kind::MismatchedType { type_expr, type_origin, .. } => {
+      // expected type comes from another file and points into it
+      assert!(type_origin == FileId(0));
+      let text = &outcome.source_files[0].text;
+      let type_text = slice(text, (type_expr.primary_offset, type_expr.secondary_offset));
+      assert!(type_text == "Dot", "{}", type_text);
+      "type"
+    },
+    _ => panic!("{:#?}", report)
+  }).collect::<Vec<_>>();
+  for kind in ["arity", "clause", "type"] {
+    assert!(kinds.contains(&kind), "{:?}", kinds);
+  }
+  let rendered = outcome.render_reports();
+  assert!(rendered.contains("a.sigil:3:"), "{}", rendered);
+}
+
+#[test]
+fn undecided_types_are_reported() {
+  let text = concat!(
+    "--| @partial\n",
+    "Loop : (Dot) -> *\n",
+    "| x => Loop x\n",
+    "\n",
+    "stuck : Loop pt = pt\n",
+    "\n",
+    "wrap : {T} (T) -> Either T Dot\n",
+    "| v => inl v\n",
+    "\n",
+    "same : {S} (S, S) -> Dot\n",
+    "| _, _ => pt\n",
+    "\n",
+    "apply_to : {T} ((T) -> Dot) -> Dot\n",
+    "| f => pt\n",
+    "\n",
+    "hidden : Dot = same (\\{ | x => x }) pt\n",
+    "\n",
+    "nested : Dot = apply_to (\\{ | y => same y (wrap y) })\n",
+  );
+  let dir = setup_dir("undecided", &[("a.sigil", text)]);
+  let outcome = elaborate_directory(dir.path());
+  let reports = outcome.diagnostics.collect_reports();
+  let mut found = reports.iter().map(|(_, report)| {
+    let (kind, loc) = match report.kind {
+      Kind::ReductionOutOfFuel { type_expr, .. } => ("fuel", type_expr),
+      Kind::UninferableType(loc) => ("lambda", loc),
+      Kind::MismatchedType { term_expr, .. } => ("type", term_expr),
+      _ => panic!("{:#?}", report)
+    };
+    return (kind, slice(text, (loc.primary_offset, loc.secondary_offset)))
+  }).collect::<Vec<_>>();
+  found.sort();
+  assert!(found == [
+    ("fuel", "Loop pt"),
+    ("lambda", "\\{ | x => x }"),
+    // type of `wrap y` would have to contain itself
+    ("type", "wrap y"),
+  ], "{:?}\n{}", found, outcome.render_reports());
+}
+
+#[test]
+fn arguments_arent_captured_by_binders_of_the_callee() {
+  // B of `h` goes under the binder B in the type of `f`
+  let dir = setup_dir("capture", &[("a.sigil", concat!(
+    "f : (A : *) -> (B : *, A) -> Dot\n",
+    "| _ => \\{ | _, _ => pt }\n",
+    "\n",
+    "h : (B : *) -> (C : *, B) -> Dot\n",
+    "| B => f B\n",
+  ))]);
+  let outcome = elaborate_directory(dir.path());
+  assert!(!outcome.diagnostics.did_record_any_issues(), "{}", outcome.render_reports());
+}